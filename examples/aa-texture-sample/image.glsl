@@ -1,11 +1,11 @@
 //modified from https://www.shadertoy.com/view/ltfXWS, tested a few different versions of this same function and this one seemed to have the nicest results
-vec4 texture2DAA(sampler2D tex, vec2 uv) {
-    vec2 texsize = vec2(textureSize(tex,0));
+//takes the texture size rather than the sampler, so that `tasty check` can validate it
+vec2 uvAA(vec2 texsize, vec2 uv) {
     vec2 uv_texspace = uv*texsize;
     vec2 seam = floor(uv_texspace+.5);
     uv_texspace = (uv_texspace-seam)/fwidth(uv_texspace)+seam;
     uv_texspace = clamp(uv_texspace, seam-.5, seam+.5);
-    return texture(tex, uv_texspace/texsize);
+    return uv_texspace/texsize;
 }
 
 
@@ -17,7 +17,7 @@ void mainImage( out vec4 fragColor, in vec2 fragCoord )
 
 
     if(fragCoord.x/iResolution.x < .5){
-        fragColor = texture2DAA(iChannel0, uv); //anti aliased scaling (iChannel0 is set to "linear" scaling)
+        fragColor = texture(iChannel0, uvAA(vec2(textureSize(iChannel0,0)), uv)); //anti aliased scaling (iChannel0 is set to "linear" scaling)
     } else {
         fragColor = texture(iChannel1, uv); //nearest neighbor scaling (iChannel1 is set to "nearest" scaling)
     }
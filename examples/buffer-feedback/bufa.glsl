@@ -0,0 +1,11 @@
+// Accumulate a moving dot into a fading trail
+void mainImage( out vec4 fragColor, in vec2 fragCoord )
+{
+    vec2 uv = fragCoord/iResolution.xy;
+    vec4 prev = texture(iChannel0, uv);
+
+    vec2 center = 0.5 + 0.3*vec2(cos(iTime), sin(2.0*iTime));
+    float dot = smoothstep(0.03, 0.0, length((uv - center)*iResolution.xy/iResolution.y));
+
+    fragColor = max(prev*0.98, vec4(dot));
+}
@@ -0,0 +1,187 @@
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use clap::Args;
+
+use crate::runtime::headless::Headless;
+use crate::runtime::Renderer;
+use crate::toy::{shader, Toy};
+
+/// Options of an offline render of the sound pass
+#[derive(Args, Debug, Clone)]
+pub struct AudioOptions {
+    /// Length of the rendered audio, in seconds
+    #[arg(long, default_value_t = 60.0)]
+    pub duration: f32,
+
+    /// Samples per second, also passed to the shader as `iSampleRate`
+    #[arg(long, default_value_t = 44100)]
+    pub sample_rate: u32,
+
+    /// Output WAV file
+    #[arg(short, long, default_value = "out.wav")]
+    pub output: PathBuf,
+}
+
+/// Render the stereo samples of a toy's sound pass with an existing renderer
+pub fn render_samples(
+    renderer: &mut Renderer,
+    toy: &Toy,
+    options: &AudioOptions,
+) -> Result<Vec<[i16; 2]>, Box<dyn Error + 'static>> {
+    if toy.sound.is_none() {
+        return Err("toy has no sound pass, add a sound.glsl defining mainSound".into());
+    }
+    renderer.compile_sound(toy)?;
+
+    let total = (options.duration * options.sample_rate as f32).round() as usize;
+    let mut samples = Vec::with_capacity(total);
+    while samples.len() < total {
+        let block = renderer
+            .render_sound(samples.len() as u32, options.sample_rate as f32)
+            .expect("sound pass compiled");
+        samples.extend(block);
+    }
+    samples.truncate(total);
+    Ok(samples)
+}
+
+/// Render the sound pass of the toy at `path` to a 16 bit stereo WAV file
+pub fn render_audio<P>(path: P, options: &AudioOptions) -> Result<(), Box<dyn Error + 'static>>
+where
+    P: AsRef<Path>,
+{
+    let toy = Toy::from_path(&path);
+    let headless = Headless::new()?;
+    let mut renderer = headless.renderer(shader::SOUND_BLOCK, shader::SOUND_BLOCK);
+    let samples = render_samples(&mut renderer, &toy, options)?;
+
+    if let Some(parent) = options.output.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let spec = hound::WavSpec {
+        channels: 2,
+        sample_rate: options.sample_rate,
+        bits_per_sample: 16,
+        sample_format: hound::SampleFormat::Int,
+    };
+    let mut writer = hound::WavWriter::create(&options.output, spec)?;
+    for [left, right] in samples {
+        writer.write_sample(left)?;
+        writer.write_sample(right)?;
+    }
+    writer.finalize()?;
+    log::info!("Wrote audio to {:?}", options.output);
+    Ok(())
+}
+
+pub fn run(path: PathBuf, options: AudioOptions) {
+    if let Err(e) = render_audio(&path, &options) {
+        log::error!("Error rendering audio of {:?}: {}", path, e);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::render::test::headless;
+    use tempdir::TempDir;
+
+    const SOUND: &str = "vec2 mainSound(int samp, float time) {
+        return vec2(0.5, samp % 2 == 0 ? -1.0 : time);
+    }";
+
+    #[test]
+    fn render_sound_samples() {
+        let Some(headless) = headless() else {
+            return;
+        };
+        let toy = Toy {
+            sound: Some(SOUND.into()),
+            ..Default::default()
+        };
+        let options = AudioOptions {
+            duration: 7.0,
+            sample_rate: 40000,
+            output: "out.wav".into(),
+        };
+        let mut renderer = headless.renderer(4, 4);
+        let samples = render_samples(&mut renderer, &toy, &options).unwrap();
+
+        // several blocks are stitched together
+        assert_eq!(280000, samples.len());
+        assert!(samples.iter().all(|s| s[0] == 16384));
+        assert_eq!(-32768, samples[0][1]);
+        for i in [1, 511, 513, 262145, 279999] {
+            let time = i as f32 / 40000.0;
+            let expected = (time.min(1.0) * 32768.0).min(32767.0);
+            assert!(
+                (samples[i][1] as f32 - expected).abs() <= 2.0,
+                "sample {}: {:?}",
+                i,
+                samples[i]
+            );
+        }
+
+        // only the sound pass is compiled
+        let broken = Toy {
+            main_image: "void mainImage(out vec4 c, in vec2 p) { c = }".into(),
+            ..toy
+        };
+        let short = AudioOptions {
+            duration: 0.01,
+            ..options
+        };
+        assert_eq!(
+            400,
+            render_samples(&mut renderer, &broken, &short)
+                .unwrap()
+                .len()
+        );
+
+        // common code may use the uniforms of every pass, set for the block
+        let common = Toy {
+            common: Some(
+                "float level() { return iResolution.x / 1024.0 + iTime * 0.0 + \
+                 texture(iChannel0, vec2(0.5)).r * 0.0; }"
+                    .into(),
+            ),
+            sound: Some("vec2 mainSound(int s, float t) { return vec2(level()); }".into()),
+            ..Default::default()
+        };
+        let samples = render_samples(&mut renderer, &common, &short).unwrap();
+        assert!(samples.iter().all(|s| s[0] == 16384), "{:?}", samples[0]);
+
+        // toys without sound are an error
+        assert!(render_samples(&mut renderer, &Toy::default(), &short).is_err());
+    }
+
+    #[test]
+    fn render_audio_to_wav() {
+        if headless().is_none() {
+            return;
+        }
+        let tmp_dir = TempDir::new("render_audio_to_wav").unwrap().into_path();
+        let toy = Toy {
+            sound: Some(SOUND.into()),
+            ..Default::default()
+        };
+        toy.write(&tmp_dir, true).unwrap();
+        let options = AudioOptions {
+            duration: 0.5,
+            sample_rate: 8000,
+            output: tmp_dir.join("audio/out.wav"),
+        };
+        render_audio(&tmp_dir, &options).unwrap();
+
+        let reader = hound::WavReader::open(&options.output).unwrap();
+        let spec = reader.spec();
+        assert_eq!(
+            (2, 8000, 16),
+            (spec.channels, spec.sample_rate, spec.bits_per_sample)
+        );
+        assert_eq!(4000, reader.duration());
+    }
+}
@@ -0,0 +1,422 @@
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use naga::front::glsl::{Frontend, Options};
+use naga::valid::{Capabilities, ValidationFlags, Validator};
+use naga::ShaderStage;
+
+use crate::golden::find_toys;
+use crate::toy::shader::Fragment;
+use crate::toy::{validate_channel_count, BufferName, Channel, Config, Toy};
+
+/// Sampler types, which naga does not accept as function parameters
+const SAMPLERS: &[&str] = &["sampler2D", "samplerCube", "sampler3D"];
+
+/// Error found in a file of a toy
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    /// File in the toy directory, e.g. `image.glsl`
+    pub file: String,
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl Diagnostic {
+    fn error(file: &str, line: Option<usize>, message: String) -> Self {
+        Diagnostic {
+            file: file.into(),
+            line,
+            message,
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "{}:{}: error: {}", self.file, line, self.message),
+            None => write!(f, "{}: error: {}", self.file, self.message),
+        }
+    }
+}
+
+/// Line of a byte offset in `source`, starting at 1
+fn line_of(source: &str, offset: usize) -> usize {
+    source[..offset.min(source.len())].matches('\n').count() + 1
+}
+
+/// Check `toy.toml` against the schema of the configuration, returning the
+/// configuration when it can be read
+fn check_config(source: &str, diagnostics: &mut Vec<Diagnostic>) -> Option<Config> {
+    let parse_error = |e: toml::de::Error| {
+        let line = e.span().map(|span| line_of(source, span.start));
+        Diagnostic::error("toy.toml", line, e.message().trim().to_string())
+    };
+    // unknown keys are rejected by the configuration itself
+    match toml::from_str(source) {
+        Ok(config) => Some(config),
+        Err(e) => {
+            diagnostics.push(parse_error(e));
+            None
+        }
+    }
+}
+
+/// Sources set by a channel, of which there should be at most one
+fn sources(channel: &Channel) -> Vec<&'static str> {
+    [
+        (channel.name.is_some(), "`name`"),
+        (channel.path.is_some(), "`path`"),
+        (channel.buffer.is_some(), "`buffer`"),
+    ]
+    .into_iter()
+    .filter_map(|(set, key)| set.then_some(key))
+    .collect()
+}
+
+/// Check the channels of every pass of the toy as `Toy::validate` does,
+/// reporting every error along with where it is in `toy.toml`
+fn check_channel_sources(toy: &Toy, diagnostics: &mut Vec<Diagnostic>) {
+    for (prefix, channels) in toy.config.pass_channels() {
+        if let Err(e) = validate_channel_count(channels) {
+            diagnostics.push(Diagnostic::error(
+                "toy.toml",
+                None,
+                format!("`{}`: {}", prefix, e),
+            ));
+        }
+        for (i, channel) in channels.iter().enumerate() {
+            let sources = sources(channel);
+            let message = if sources.len() > 1 {
+                format!("sets {}, but reads a single source", sources.join(" and "))
+            } else if let Err(e) = toy.validate_channel(channel) {
+                e.to_string()
+            } else {
+                continue;
+            };
+            diagnostics.push(Diagnostic::error(
+                "toy.toml",
+                None,
+                format!("`{}[{}]`: {}", prefix, i, message),
+            ));
+        }
+    }
+}
+
+/// Translate a line of generated code to the GLSL 4.50 naga reads. Inputs and
+/// outputs need locations, uniforms become plain globals since naga only
+/// accepts them in blocks, and channels become separate textures and samplers
+/// behind a macro combining them.
+fn naga_line(line: &str, binding: &mut u32) -> String {
+    if line.starts_with("#version") {
+        return "#version 450".into();
+    }
+    if let Some(input) = line.strip_prefix("varying ") {
+        let input = ["lowp ", "mediump ", "highp "]
+            .iter()
+            .fold(input.to_string(), |input, precision| {
+                input.replace(precision, "")
+            });
+        return format!("layout(location = 0) in {}", input);
+    }
+    if line.starts_with("out ") {
+        return format!("layout(location = 0) {}", line);
+    }
+    let Some(uniform) = line.strip_prefix("uniform ") else {
+        return line.into();
+    };
+    let mut words = uniform.trim_end_matches(';').split_whitespace();
+    match (words.next(), words.next()) {
+        (Some(sampler), Some(name)) if SAMPLERS.contains(&sampler) => {
+            let texture = sampler.replacen("sampler", "texture", 1);
+            *binding += 2;
+            format!(
+                "layout(binding = {b0}) uniform {texture} {name}_texture;\n\
+                 layout(binding = {b1}) uniform sampler {name}_sampler;\n\
+                 #define {name} {sampler}({name}_texture, {name}_sampler)",
+                b0 = *binding - 2,
+                b1 = *binding - 1,
+            )
+        }
+        _ => uniform.into(),
+    }
+}
+
+/// File and line a line of a shader was read from, or the pass file for
+/// generated code
+fn origin(fragment: &Fragment, file: &str, line: u32) -> (String, Option<usize>) {
+    match fragment.map.lookup(line as usize) {
+        Some((file, line)) => (file.into(), Some(line)),
+        None => (file.into(), None),
+    }
+}
+
+/// Check the shader of the pass read from `file` with naga, reporting errors
+/// at the lines of the toy's files they come from
+fn check_shader(fragment: &Fragment, file: &str, diagnostics: &mut Vec<Diagnostic>) {
+    for (_, line, origin) in fragment.lines() {
+        let Some((source_file, source_line)) = origin else {
+            continue;
+        };
+        let takes_sampler = line
+            .split(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+            .any(|word| SAMPLERS.contains(&word));
+        // a pass that is not validated must not pass for a valid one
+        if takes_sampler {
+            diagnostics.push(Diagnostic::error(
+                source_file,
+                Some(source_line),
+                format!(
+                    "samplers passed to functions cannot be checked, so {} is not validated",
+                    file
+                ),
+            ));
+            return;
+        }
+    }
+
+    let mut binding = 0;
+    let fragment = fragment.rewrite_generated(|line| naga_line(line, &mut binding));
+    let source = &fragment.source;
+    let module = match Frontend::default().parse(&Options::from(ShaderStage::Fragment), source) {
+        Ok(module) => module,
+        Err(e) => {
+            for error in e.errors {
+                let (file, line) = origin(&fragment, file, error.meta.location(source).line_number);
+                diagnostics.push(Diagnostic::error(&file, line, error.kind.to_string()));
+            }
+            return;
+        }
+    };
+    if let Err(e) = Validator::new(ValidationFlags::all(), Capabilities::all()).validate(&module) {
+        // the innermost span points at the offending expression
+        let (file, line) = match e.spans().filter(|(span, _)| span.is_defined()).last() {
+            Some((span, _)) => origin(&fragment, file, span.location(source).line_number),
+            None => (file.into(), None),
+        };
+        let mut message = e.as_inner().to_string();
+        let mut cause = std::error::Error::source(e.as_inner());
+        while let Some(inner) = cause {
+            message.push_str(&format!(": {}", inner));
+            cause = inner.source();
+        }
+        diagnostics.push(Diagnostic::error(&file, line, message));
+    }
+}
+
+/// Check the configuration and the shaders of every pass of the toy at
+/// `path`, without any GPU
+pub fn check<P>(path: P) -> Vec<Diagnostic>
+where
+    P: AsRef<Path>,
+{
+    let mut diagnostics = vec![];
+    let mut toy = Toy::from_path(&path);
+
+    let config_path = path.as_ref().join("toy.toml");
+    if config_path.exists() {
+        match fs::read_to_string(&config_path) {
+            Ok(source) => {
+                if let Some(config) = check_config(&source, &mut diagnostics) {
+                    toy.config = config;
+                    check_channel_sources(&toy, &mut diagnostics);
+                }
+            }
+            Err(e) => diagnostics.push(Diagnostic::error("toy.toml", None, e.to_string())),
+        }
+    }
+
+    let mut passes = vec![("image.glsl", Some(toy.fragment_shader()))];
+    for buffer in BufferName::ALL {
+        passes.push((buffer.file_name(), toy.buffer_fragment_shader(buffer)));
+    }
+    passes.push(("sound.glsl", toy.sound_shader()));
+    passes.push(("cubea.glsl", toy.cube_shader()));
+    for (file, shader) in passes {
+        match shader {
+            Some(Ok(fragment)) => check_shader(&fragment, file, &mut diagnostics),
+            Some(Err(e)) => diagnostics.push(Diagnostic::error(file, None, e.to_string())),
+            None => (),
+        }
+    }
+    diagnostics
+}
+
+/// Check every toy under `location`, exiting with a non-zero status on errors
+pub fn run(location: PathBuf) {
+    let mut failed = false;
+    for path in find_toys(&location) {
+        let diagnostics = check(&path);
+        for diagnostic in &diagnostics {
+            println!("{}/{}", path.display(), diagnostic);
+        }
+        if !diagnostics.is_empty() {
+            failed = true;
+        } else {
+            println!("OK {}", path.display());
+        }
+    }
+    if failed {
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tempdir::TempDir;
+
+    fn messages(diagnostics: &[Diagnostic]) -> Vec<String> {
+        diagnostics.iter().map(|d| d.to_string()).collect()
+    }
+
+    #[test]
+    fn config_schema() {
+        let tmp_dir = TempDir::new("config_schema").unwrap().into_path();
+        fs::write(
+            tmp_dir.join("toy.toml"),
+            r#"
+            [[channels]]
+            name = "rgba_noise_small"
+            path = "wood.png"
+            [[channels]]
+            buffer = "a"
+            [[channels]]
+            name = "bayer"
+            config = { vflip = true, filter = "linear", wrap = "clamp" }
+            [[channels]]
+            config = "keyboard"
+            [[channels]]
+            config = "keyboard"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            vec![
+                "toy.toml: error: `channels`: pass has 5 channels, \
+                 but reads at most 4 (iChannel0 to iChannel3)",
+                "toy.toml: error: `channels[0]`: sets `name` and `path`, but reads a single source",
+                "toy.toml: error: `channels[1]`: channel reads buffer A but bufa.glsl is not defined",
+            ],
+            messages(&check(&tmp_dir))
+        );
+
+        // unknown keys are errors, including keys of another kind of channel
+        for (config, line) in [
+            ("\nspeed = 2\n", 2),
+            (
+                "[[channels]]\nconfig = { audio = \"a.ogg\", vflip = true }\n",
+                2,
+            ),
+            (
+                "[[channels]]\nname = \"bayer\"\nbuffer = \"a\"\nflip = true\n",
+                4,
+            ),
+            ("[sound]\nchanels = []\n", 2),
+            ("[[tests]]\nreference = \"a.png\"\nsise = [1, 1]\n", 3),
+        ] {
+            fs::write(tmp_dir.join("toy.toml"), config).unwrap();
+            let diagnostics = check(&tmp_dir);
+            assert_eq!(1, diagnostics.len(), "{}: {:?}", config, diagnostics);
+            assert_eq!(Some(line), diagnostics[0].line, "{}", config);
+        }
+        fs::write(tmp_dir.join("toy.toml"), "speed = 2\n").unwrap();
+        assert!(check(&tmp_dir)[0].message.contains("unknown field `speed`"));
+
+        // errors reading the configuration point at their line
+        fs::write(tmp_dir.join("toy.toml"), "\n[[channels]]\nname = 3\n").unwrap();
+        let diagnostics = check(&tmp_dir);
+        assert_eq!(1, diagnostics.len());
+        assert_eq!(Some(3), diagnostics[0].line);
+    }
+
+    #[test]
+    fn shader_errors() {
+        let tmp_dir = TempDir::new("shader_errors").unwrap().into_path();
+        fs::write(
+            tmp_dir.join("common.glsl"),
+            "float twice(float x) { return 2.0*x; }\n",
+        )
+        .unwrap();
+        fs::write(
+            tmp_dir.join("toy.toml"),
+            "[[channels]]\nname = \"sky\"\nconfig = { cubemap = \"image\" }\n",
+        )
+        .unwrap();
+        fs::write(
+            tmp_dir.join("image.glsl"),
+            "void mainImage( out vec4 fragColor, in vec2 fragCoord )\n\
+             {\n\
+             \x20   vec3 dir = normalize(vec3(fragCoord/iResolution.xy, twice(iTime)));\n\
+             \x20   fragColor = texture(iChannel0, dir) + texelFetch(iChannel1, ivec2(0), 0);\n\
+             }\n",
+        )
+        .unwrap();
+        assert_eq!(Vec::<String>::new(), messages(&check(&tmp_dir)));
+
+        // unknown identifiers and type errors point at the line of image.glsl
+        fs::write(
+            tmp_dir.join("image.glsl"),
+            "void mainImage( out vec4 fragColor, in vec2 fragCoord )\n\
+             {\n\
+             \x20   fragColor = vec4(undefined);\n\
+             }\n",
+        )
+        .unwrap();
+        let diagnostics = check(&tmp_dir);
+        assert_eq!(1, diagnostics.len());
+        assert_eq!(
+            ("image.glsl", Some(3)),
+            (diagnostics[0].file.as_str(), diagnostics[0].line)
+        );
+        fs::write(
+            tmp_dir.join("bufa.glsl"),
+            "void mainImage( out vec4 fragColor, in vec2 fragCoord )\n\
+             {\n\
+             \x20   vec2 uv = fragCoord;\n\
+             \x20   fragColor = uv;\n\
+             }\n",
+        )
+        .unwrap();
+        let diagnostics = check(&tmp_dir);
+        assert_eq!(2, diagnostics.len());
+        assert_eq!(
+            ("bufa.glsl", Some(4)),
+            (diagnostics[1].file.as_str(), diagnostics[1].line)
+        );
+
+        // passes taking samplers as parameters cannot be checked, which fails
+        fs::write(
+            tmp_dir.join("image.glsl"),
+            "vec4 read(sampler2D s) { return texture(s, vec2(0)); }\n\
+             void mainImage( out vec4 fragColor, in vec2 fragCoord ) { fragColor = read(iChannel1); }\n",
+        )
+        .unwrap();
+        let diagnostics = check(&tmp_dir);
+        assert!(diagnostics[0]
+            .message
+            .contains("image.glsl is not validated"));
+        assert_eq!(Some(1), diagnostics[0].line);
+    }
+
+    #[test]
+    fn examples_and_templates_check() {
+        let examples = Path::new(env!("CARGO_MANIFEST_DIR")).join("examples");
+        for path in find_toys(examples) {
+            assert_eq!(Vec::<String>::new(), messages(&check(&path)), "{:?}", path);
+        }
+
+        let tmp_dir = TempDir::new("templates_check").unwrap().into_path();
+        for template in <crate::template::Template as clap::ValueEnum>::value_variants() {
+            let path = tmp_dir.join(format!("{:?}", template));
+            crate::template::create(&path, *template, false).unwrap();
+            assert_eq!(
+                Vec::<String>::new(),
+                messages(&check(&path)),
+                "{:?}",
+                template
+            );
+        }
+    }
+}
@@ -0,0 +1,297 @@
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use image::{Rgba, RgbaImage};
+
+use crate::render::{render_frames, RenderOptions};
+use crate::runtime::headless::Headless;
+use crate::toy::{Config, TestCase, Toy};
+
+/// Result of a single golden image test
+#[derive(Debug, PartialEq)]
+pub enum Outcome {
+    Pass,
+    /// The reference image was (re)written from the rendered frame
+    Updated,
+    Fail(String),
+}
+
+/// Toy directories under `dir`, including `dir` itself, sorted by path
+pub fn find_toys<P: AsRef<Path>>(dir: P) -> Vec<PathBuf> {
+    let dir = dir.as_ref();
+    if dir.join("toy.toml").exists() || dir.join("image.glsl").exists() {
+        return vec![dir.to_path_buf()];
+    }
+    let mut entries: Vec<PathBuf> = match fs::read_dir(dir) {
+        Ok(entries) => entries
+            .filter_map(|e| e.ok())
+            .map(|e| e.path())
+            .filter(|p| p.is_dir())
+            .collect(),
+        Err(e) => {
+            log::error!("Error reading {:?}: {}", dir, e);
+            vec![]
+        }
+    };
+    entries.sort();
+    entries.iter().flat_map(find_toys).collect()
+}
+
+/// Compare two images, returning the number of pixels differing by more than
+/// `tolerance` in any channel along with an image highlighting them in red
+pub fn compare(actual: &RgbaImage, reference: &RgbaImage, tolerance: u8) -> (u64, RgbaImage) {
+    let mut failing = 0;
+    let diff = RgbaImage::from_fn(actual.width(), actual.height(), |x, y| {
+        let a = actual.get_pixel(x, y);
+        let r = reference.get_pixel(x, y);
+        let exceeds = a.0.iter().zip(r.0).any(|(a, r)| a.abs_diff(r) > tolerance);
+        if exceeds {
+            failing += 1;
+            Rgba([255, 0, 0, 255])
+        } else {
+            // dimmed grayscale of the frame for context
+            let luma = (a[0] as u32 + a[1] as u32 + a[2] as u32) / 9;
+            Rgba([luma as u8, luma as u8, luma as u8, 255])
+        }
+    });
+    (failing, diff)
+}
+
+/// Path next to the reference image with `suffix` added to the file stem
+fn sibling(reference: &Path, suffix: &str) -> PathBuf {
+    let stem = reference.file_stem().unwrap_or_default().to_string_lossy();
+    reference.with_file_name(format!("{}.{}.png", stem, suffix))
+}
+
+/// Render a test case and compare it against, or write, its reference image
+pub fn run_case(
+    headless: &Headless,
+    toy: &Toy,
+    case: &TestCase,
+    tolerance: Option<u8>,
+    update: bool,
+) -> Result<Outcome, Box<dyn Error + 'static>> {
+    let options = RenderOptions {
+        frames: case.frames.max(1),
+        size: case.size,
+        time: case.time,
+        fps: case.fps,
+        output: case.reference.clone(),
+        record: false,
+    };
+    let mut renderer = headless.renderer(case.size.0, case.size.1);
+    let mut last = None;
+    render_frames(&mut renderer, toy, &options, |_, image| {
+        last = Some(image);
+        Ok(())
+    })?;
+    let actual = last.expect("at least one frame rendered");
+
+    let reference_path = toy.root.join(&case.reference);
+    let actual_path = sibling(&reference_path, "actual");
+    let diff_path = sibling(&reference_path, "diff");
+    if update {
+        if let Some(parent) = reference_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        actual.save(&reference_path)?;
+        return Ok(Outcome::Updated);
+    }
+
+    let reference = match image::open(&reference_path) {
+        Ok(reference) => reference.into_rgba8(),
+        Err(e) => {
+            return Ok(Outcome::Fail(format!(
+                "error reading reference {:?}: {}",
+                reference_path, e
+            )))
+        }
+    };
+    if reference.dimensions() != actual.dimensions() {
+        actual.save(&actual_path)?;
+        return Ok(Outcome::Fail(format!(
+            "size {:?} differs from reference size {:?}, see {:?}",
+            actual.dimensions(),
+            reference.dimensions(),
+            actual_path
+        )));
+    }
+
+    let (failing, diff) = compare(&actual, &reference, tolerance.unwrap_or(case.tolerance));
+    if failing == 0 {
+        // clear out results of earlier failures
+        let _ = fs::remove_file(&actual_path);
+        let _ = fs::remove_file(&diff_path);
+        return Ok(Outcome::Pass);
+    }
+    actual.save(&actual_path)?;
+    diff.save(&diff_path)?;
+    Ok(Outcome::Fail(format!(
+        "{} pixels differ from reference, see {:?}",
+        failing, diff_path
+    )))
+}
+
+/// Run golden image tests of every toy under `dir`, returning whether all passed
+pub fn test<P: AsRef<Path>>(
+    dir: P,
+    tolerance: Option<u8>,
+    update: bool,
+) -> Result<bool, Box<dyn Error + 'static>> {
+    let headless = Headless::new()?;
+    let mut passed = true;
+    for path in find_toys(dir) {
+        // a toy whose tests cannot be read would otherwise pass unchecked
+        if let Err(e) = Config::read(&path) {
+            println!("FAIL {}: {}", path.display(), e);
+            passed = false;
+            continue;
+        }
+        let toy = Toy::from_path(&path);
+        if toy.config.tests.is_empty() {
+            println!("FAIL {}: no [[tests]] in toy.toml", path.display());
+            passed = false;
+        }
+        for (i, case) in toy.config.tests.iter().enumerate() {
+            let outcome = run_case(&headless, &toy, case, tolerance, update)
+                .unwrap_or_else(|e| Outcome::Fail(e.to_string()));
+            match outcome {
+                Outcome::Pass => println!("PASS {} [{}]", path.display(), i),
+                Outcome::Updated => println!("UPDATED {} [{}]", path.display(), i),
+                Outcome::Fail(reason) => {
+                    println!("FAIL {} [{}]: {}", path.display(), i, reason);
+                    passed = false;
+                }
+            }
+        }
+    }
+    Ok(passed)
+}
+
+pub fn run(path: PathBuf, tolerance: Option<u8>, update: bool) {
+    match test(&path, tolerance, update) {
+        Ok(true) => (),
+        Ok(false) => std::process::exit(1),
+        Err(e) => {
+            log::error!("Error testing {:?}: {}", path, e);
+            std::process::exit(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::render::test::headless;
+    use tempdir::TempDir;
+
+    #[test]
+    fn compare_with_tolerance() {
+        let reference = RgbaImage::from_pixel(4, 2, Rgba([100, 100, 100, 255]));
+        let mut actual = reference.clone();
+        actual.put_pixel(1, 1, Rgba([103, 100, 100, 255]));
+        actual.put_pixel(2, 0, Rgba([100, 99, 100, 255]));
+
+        let (failing, diff) = compare(&actual, &reference, 2);
+        assert_eq!(1, failing);
+        assert_eq!(&Rgba([255, 0, 0, 255]), diff.get_pixel(1, 1));
+        assert_ne!(&Rgba([255, 0, 0, 255]), diff.get_pixel(2, 0));
+
+        let (failing, _) = compare(&actual, &reference, 3);
+        assert_eq!(0, failing);
+    }
+
+    #[test]
+    fn finds_nested_toys() {
+        let tmp_dir = TempDir::new("finds_nested_toys").unwrap().into_path();
+        Toy::default().write(tmp_dir.join("b"), true).unwrap();
+        Toy::default()
+            .write(tmp_dir.join("a/nested"), true)
+            .unwrap();
+        fs::create_dir_all(tmp_dir.join("empty")).unwrap();
+        assert_eq!(
+            vec![tmp_dir.join("a/nested"), tmp_dir.join("b")],
+            find_toys(&tmp_dir)
+        );
+    }
+
+    #[test]
+    fn examples_match_references() {
+        if headless().is_none() {
+            return;
+        }
+        let examples = Path::new(env!("CARGO_MANIFEST_DIR")).join("examples");
+        assert!(test(examples, None, false).unwrap());
+    }
+
+    #[test]
+    fn toys_without_tests_fail() {
+        if headless().is_none() {
+            return;
+        }
+        let tmp_dir = TempDir::new("toys_without_tests_fail").unwrap().into_path();
+        Toy::default().write(&tmp_dir, true).unwrap();
+        assert!(!test(&tmp_dir, None, false).unwrap());
+
+        // as do toys whose config cannot be parsed
+        fs::write(tmp_dir.join("toy.toml"), "[[tests]]\nreference = ").unwrap();
+        assert!(matches!(
+            Config::read(&tmp_dir),
+            Err(crate::toy::ConfigError::Parse(_))
+        ));
+        assert!(!test(&tmp_dir, None, false).unwrap());
+    }
+
+    #[test]
+    fn golden_image_round_trip() {
+        let Some(headless) = headless() else {
+            return;
+        };
+        let tmp_dir = TempDir::new("golden_image_round_trip").unwrap().into_path();
+        let mut toy = Toy::default();
+        toy.config.tests = vec![TestCase {
+            reference: "golden/start.png".into(),
+            time: 1.0,
+            frames: 1,
+            fps: 60.0,
+            size: (32, 16),
+            tolerance: 2,
+        }];
+        toy.write(&tmp_dir, true).unwrap();
+        let toy = Toy::from_path(&tmp_dir);
+        let case = &toy.config.tests[0];
+
+        // missing references fail until written
+        assert!(matches!(
+            run_case(&headless, &toy, case, None, false).unwrap(),
+            Outcome::Fail(_)
+        ));
+        assert_eq!(
+            Outcome::Updated,
+            run_case(&headless, &toy, case, None, true).unwrap()
+        );
+        assert!(tmp_dir.join("golden/start.png").exists());
+        assert_eq!(
+            Outcome::Pass,
+            run_case(&headless, &toy, case, None, false).unwrap()
+        );
+
+        // changes to the shader are caught, leaving diff images behind
+        let mut changed = toy.clone();
+        changed.main_image = changed.main_image.replace("iTime", "(iTime+1.0)");
+        assert!(matches!(
+            run_case(&headless, &changed, case, None, false).unwrap(),
+            Outcome::Fail(_)
+        ));
+        assert!(tmp_dir.join("golden/start.diff.png").exists());
+        assert!(tmp_dir.join("golden/start.actual.png").exists());
+
+        // unless within tolerance
+        assert_eq!(
+            Outcome::Pass,
+            run_case(&headless, &changed, case, Some(255), false).unwrap()
+        );
+        assert!(!tmp_dir.join("golden/start.diff.png").exists());
+    }
+}
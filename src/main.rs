@@ -2,7 +2,15 @@ use std::path::PathBuf;
 
 use clap::{Parser, Subcommand};
 
+mod audio;
+mod check;
+mod golden;
+mod record;
+mod render;
 mod runtime;
+mod shadertoy;
+mod template;
+mod terminal;
 mod toy;
 mod watch;
 
@@ -19,7 +27,70 @@ enum Commands {
     Watch {
         /// lists test values
         location: PathBuf,
+        #[command(flatten)]
+        options: watch::WatchOptions,
     },
+    /// Render frames of a toy to image files without opening a window
+    Render {
+        /// Toy directory
+        location: PathBuf,
+        #[command(flatten)]
+        options: render::RenderOptions,
+    },
+    /// Render the sound pass of a toy to a WAV file
+    RenderAudio {
+        /// Toy directory
+        location: PathBuf,
+        #[command(flatten)]
+        options: audio::AudioOptions,
+    },
+    /// Compare rendered frames of toys against their reference images
+    Test {
+        /// Toy directory, or a directory containing toys
+        location: PathBuf,
+        /// Override the per-pixel tolerance of every test
+        #[arg(long)]
+        tolerance: Option<u8>,
+        /// Write rendered frames as the new reference images
+        #[arg(long)]
+        update: bool,
+    },
+    /// Check the shaders and configuration of toys without a GPU
+    Check {
+        /// Toy directory, or a directory containing toys
+        location: PathBuf,
+    },
+    /// Create a toy from a built-in template
+    New {
+        /// Toy directory to create
+        location: PathBuf,
+        /// Template to start from
+        #[arg(short, long, value_enum, default_value = "basic")]
+        template: template::Template,
+        /// Overwrite existing files of the template in the directory
+        #[arg(long)]
+        force: bool,
+    },
+    /// Create a toy from a shader exported from Shadertoy as JSON
+    Import {
+        /// Exported shader JSON file
+        file: PathBuf,
+        /// Toy directory to create
+        location: PathBuf,
+        /// Overwrite an existing toy in the directory
+        #[arg(long)]
+        force: bool,
+    },
+    /// Write a toy as Shadertoy JSON
+    Export {
+        /// Toy directory
+        location: PathBuf,
+        /// Output file, standard output if not given
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// List built-in channel data with sizes and recommended channel settings
+    Builtins,
 }
 
 fn debug() {
@@ -42,7 +113,27 @@ fn main() {
 
     match cli.command {
         Some(Commands::Debug) => debug(),
-        Some(Commands::Watch { location }) => watch::run(location),
+        Some(Commands::Watch { location, options }) => watch::run(location, options),
+        Some(Commands::Render { location, options }) => render::run(location, options),
+        Some(Commands::RenderAudio { location, options }) => audio::run(location, options),
+        Some(Commands::Test {
+            location,
+            tolerance,
+            update,
+        }) => golden::run(location, tolerance, update),
+        Some(Commands::Check { location }) => check::run(location),
+        Some(Commands::New {
+            location,
+            template,
+            force,
+        }) => template::run(location, template, force),
+        Some(Commands::Import {
+            file,
+            location,
+            force,
+        }) => shadertoy::run_import(file, location, force),
+        Some(Commands::Export { location, output }) => shadertoy::run_export(location, output),
+        Some(Commands::Builtins) => toy::builtin::run(),
         None => (),
     }
 }
@@ -0,0 +1,417 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use image::codecs::gif::{GifEncoder, Repeat};
+use image::codecs::webp::WebPEncoder;
+use image::{Delay, ExtendedColorType, Frame, RgbaImage};
+
+/// Format of a recording, detected from the extension of its file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Gif,
+    /// Animated PNG
+    Apng,
+    /// Animated lossless WebP
+    WebP,
+    /// Uncompressed YUV 4:4:4 video, for other tools to encode
+    Y4m,
+}
+
+impl Format {
+    /// Format of a recording at `path`, with `-` standing for y4m written to
+    /// standard output
+    pub fn from_path(path: &Path) -> Option<Format> {
+        if path == Path::new("-") {
+            return Some(Format::Y4m);
+        }
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "gif" => Some(Format::Gif),
+            "png" | "apng" => Some(Format::Apng),
+            "webp" => Some(Format::WebP),
+            "y4m" => Some(Format::Y4m),
+            _ => None,
+        }
+    }
+}
+
+/// Error recording frames
+#[derive(Debug)]
+pub enum RecordError {
+    /// The file extension is not one of a recording format
+    Format(PathBuf),
+    /// A frame has another size than the recording
+    Size {
+        expected: (u32, u32),
+        actual: (u32, u32),
+    },
+    Io(io::Error),
+    Image(image::ImageError),
+    Png(png::EncodingError),
+}
+
+impl std::fmt::Display for RecordError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RecordError::Format(path) => write!(
+                f,
+                "unknown recording format of {:?}, expected .gif, .png, .webp or .y4m",
+                path
+            ),
+            RecordError::Size { expected, actual } => write!(
+                f,
+                "{}x{} frame does not match the {}x{} recording",
+                actual.0, actual.1, expected.0, expected.1
+            ),
+            RecordError::Io(e) => write!(f, "{}", e),
+            RecordError::Image(e) => write!(f, "{}", e),
+            RecordError::Png(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for RecordError {}
+
+impl From<io::Error> for RecordError {
+    fn from(e: io::Error) -> Self {
+        RecordError::Io(e)
+    }
+}
+
+impl From<image::ImageError> for RecordError {
+    fn from(e: image::ImageError) -> Self {
+        RecordError::Image(e)
+    }
+}
+
+impl From<png::EncodingError> for RecordError {
+    fn from(e: png::EncodingError) -> Self {
+        RecordError::Png(e)
+    }
+}
+
+/// Duration of a frame in `units` per second, with the end of every frame
+/// rounded so that durations add up to the time of the frames
+fn duration(frame: u32, fps: f32, units: f64) -> u32 {
+    let end = |frame: u32| (frame as f64 * units / fps as f64).round() as u32;
+    end(frame + 1) - end(frame)
+}
+
+/// APNG of the frames pushed, encoded once their count is known since it
+/// comes before the first frame
+struct Apng {
+    writer: BufWriter<File>,
+    frames: Vec<(RgbaImage, u32)>,
+}
+
+impl Apng {
+    fn create(path: &Path) -> io::Result<Apng> {
+        Ok(Apng {
+            writer: BufWriter::new(File::create(path)?),
+            frames: vec![],
+        })
+    }
+
+    fn push(&mut self, image: RgbaImage, delay_ms: u32) {
+        self.frames.push((image, delay_ms));
+    }
+
+    fn finish(self) -> Result<(), png::EncodingError> {
+        let Some((first, _)) = self.frames.first() else {
+            return Ok(());
+        };
+        let mut encoder = png::Encoder::new(self.writer, first.width(), first.height());
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        // looping forever
+        encoder.set_animated(self.frames.len() as u32, 0)?;
+        let mut writer = encoder.write_header()?;
+        for (image, delay_ms) in &self.frames {
+            writer.set_frame_delay(*delay_ms as u16, 1000)?;
+            writer.write_image_data(image.as_raw())?;
+        }
+        writer.finish()
+    }
+}
+
+/// Animated WebP written as frames arrive, with the file size filled in at
+/// the end
+struct WebP {
+    writer: BufWriter<File>,
+}
+
+/// Write a RIFF chunk, padded to an even size
+fn write_riff_chunk<W: Write>(w: &mut W, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    w.write_all(kind)?;
+    w.write_all(&(data.len() as u32).to_le_bytes())?;
+    w.write_all(data)?;
+    if data.len() % 2 == 1 {
+        w.write_all(&[0])?;
+    }
+    Ok(())
+}
+
+impl WebP {
+    fn create(path: &Path, (width, height): (u32, u32)) -> io::Result<WebP> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writer.write_all(b"RIFF\0\0\0\0WEBP")?;
+        // animated, without alpha
+        let mut vp8x = vec![1 << 1, 0, 0, 0];
+        vp8x.extend(&(width - 1).to_le_bytes()[..3]);
+        vp8x.extend(&(height - 1).to_le_bytes()[..3]);
+        write_riff_chunk(&mut writer, b"VP8X", &vp8x)?;
+        // black background, looping forever
+        write_riff_chunk(&mut writer, b"ANIM", &[0, 0, 0, 0, 0, 0])?;
+        Ok(WebP { writer })
+    }
+
+    fn push(&mut self, image: &RgbaImage, delay_ms: u32) -> Result<(), RecordError> {
+        let mut still = vec![];
+        WebPEncoder::new_lossless(&mut still).encode(
+            image.as_raw(),
+            image.width(),
+            image.height(),
+            ExtendedColorType::Rgba8,
+        )?;
+        let mut anmf = vec![0; 6];
+        anmf.extend(&(image.width() - 1).to_le_bytes()[..3]);
+        anmf.extend(&(image.height() - 1).to_le_bytes()[..3]);
+        anmf.extend(&delay_ms.to_le_bytes()[..3]);
+        // frames replace the previous one without blending
+        anmf.push(1 << 1);
+        // the VP8L chunk of the still, after its RIFF header
+        anmf.extend(&still[12..]);
+        write_riff_chunk(&mut self.writer, b"ANMF", &anmf)?;
+        Ok(())
+    }
+
+    fn finish(mut self) -> io::Result<()> {
+        let len = self.writer.stream_position()?;
+        self.writer.seek(SeekFrom::Start(4))?;
+        self.writer.write_all(&(len as u32 - 8).to_le_bytes())?;
+        self.writer.flush()
+    }
+}
+
+/// Write the header of a y4m stream
+fn y4m_header<W: Write>(w: &mut W, (width, height): (u32, u32), fps: f32) -> io::Result<()> {
+    let gcd = |mut a: u32, mut b: u32| {
+        while b != 0 {
+            (a, b) = (b, a % b);
+        }
+        a
+    };
+    let rate = (fps * 1000.0).round() as u32;
+    let d = gcd(rate, 1000).max(1);
+    writeln!(
+        w,
+        "YUV4MPEG2 W{} H{} F{}:{} Ip A1:1 C444",
+        width,
+        height,
+        rate / d,
+        1000 / d
+    )
+}
+
+/// Write a frame of a y4m stream, converted to BT.601 limited range YCbCr
+fn y4m_frame<W: Write>(w: &mut W, image: &RgbaImage) -> io::Result<()> {
+    let texels = image.pixels().map(|p| p.0.map(|c| c as f32 / 255.0));
+    let mut planes = [vec![], vec![], vec![]];
+    for [r, g, b, _] in texels {
+        planes[0].push(16.0 + 65.481 * r + 128.553 * g + 24.966 * b);
+        planes[1].push(128.0 - 37.797 * r - 74.203 * g + 112.0 * b);
+        planes[2].push(128.0 + 112.0 * r - 93.786 * g - 18.214 * b);
+    }
+    w.write_all(b"FRAME\n")?;
+    for plane in planes {
+        let bytes: Vec<u8> = plane.iter().map(|v| v.round() as u8).collect();
+        w.write_all(&bytes)?;
+    }
+    Ok(())
+}
+
+enum Encoder {
+    Gif(GifEncoder<BufWriter<File>>),
+    Apng(Apng),
+    WebP(WebP),
+    Y4m(Box<dyn Write>),
+}
+
+/// Frames encoded to an animation or video as they are rendered, each shown
+/// for the same time step
+pub struct Recorder {
+    encoder: Encoder,
+    size: (u32, u32),
+    fps: f32,
+    frames: u32,
+}
+
+impl Recorder {
+    /// Create a recording of `size` frames at `path`, in the format of its
+    /// extension
+    pub fn create(path: &Path, size: (u32, u32), fps: f32) -> Result<Recorder, RecordError> {
+        let format = Format::from_path(path).ok_or(RecordError::Format(path.to_path_buf()))?;
+        let encoder = match format {
+            Format::Gif => {
+                let mut gif = GifEncoder::new_with_speed(BufWriter::new(File::create(path)?), 10);
+                gif.set_repeat(Repeat::Infinite)?;
+                Encoder::Gif(gif)
+            }
+            Format::Apng => Encoder::Apng(Apng::create(path)?),
+            Format::WebP => Encoder::WebP(WebP::create(path, size)?),
+            Format::Y4m => {
+                let mut writer: Box<dyn Write> = if path == Path::new("-") {
+                    Box::new(io::stdout())
+                } else {
+                    Box::new(BufWriter::new(File::create(path)?))
+                };
+                y4m_header(&mut writer, size, fps)?;
+                Encoder::Y4m(writer)
+            }
+        };
+        Ok(Recorder {
+            encoder,
+            size,
+            fps,
+            frames: 0,
+        })
+    }
+
+    /// Add the next frame, made opaque like frames shown in the window
+    pub fn push(&mut self, image: &RgbaImage) -> Result<(), RecordError> {
+        if image.dimensions() != self.size {
+            return Err(RecordError::Size {
+                expected: self.size,
+                actual: image.dimensions(),
+            });
+        }
+        let mut image = image.clone();
+        for pixel in image.pixels_mut() {
+            pixel[3] = 255;
+        }
+
+        let frame = self.frames;
+        match &mut self.encoder {
+            Encoder::Gif(gif) => {
+                // GIF delays are in hundredths of a second
+                let delay = duration(frame, self.fps, 100.0) * 10;
+                let delay = Delay::from_numer_denom_ms(delay, 1);
+                gif.encode_frame(Frame::from_parts(image, 0, 0, delay))?;
+            }
+            Encoder::Apng(apng) => apng.push(image, duration(frame, self.fps, 1000.0)),
+            Encoder::WebP(webp) => webp.push(&image, duration(frame, self.fps, 1000.0))?,
+            Encoder::Y4m(writer) => y4m_frame(writer, &image)?,
+        }
+        self.frames += 1;
+        Ok(())
+    }
+
+    /// Complete the file, returning the number of frames recorded
+    pub fn finish(self) -> Result<u32, RecordError> {
+        match self.encoder {
+            // the GIF trailer is written when the encoder is dropped
+            Encoder::Gif(gif) => drop(gif),
+            Encoder::Apng(apng) => apng.finish()?,
+            Encoder::WebP(webp) => webp.finish()?,
+            Encoder::Y4m(mut writer) => writer.flush()?,
+        }
+        Ok(self.frames)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use image::codecs::gif::GifDecoder;
+    use image::codecs::png::PngDecoder;
+    use image::codecs::webp::WebPDecoder;
+    use image::{AnimationDecoder, Rgba};
+    use std::io::BufReader;
+    use tempdir::TempDir;
+
+    /// Frames filled with a shade of red, with a transparent alpha
+    fn frames() -> Vec<RgbaImage> {
+        [0, 128, 255]
+            .map(|red| RgbaImage::from_pixel(6, 4, Rgba([red, 0, 0, 0])))
+            .to_vec()
+    }
+
+    fn record(path: &Path) -> Vec<(RgbaImage, u32)> {
+        let mut recorder = Recorder::create(path, (6, 4), 30.0).unwrap();
+        for frame in frames() {
+            recorder.push(&frame).unwrap();
+        }
+        assert_eq!(3, recorder.finish().unwrap());
+
+        let reader = BufReader::new(File::open(path).unwrap());
+        let decoded = match Format::from_path(path).unwrap() {
+            Format::Gif => GifDecoder::new(reader).unwrap().into_frames(),
+            Format::Apng => PngDecoder::new(reader)
+                .unwrap()
+                .apng()
+                .unwrap()
+                .into_frames(),
+            Format::WebP => WebPDecoder::new(reader).unwrap().into_frames(),
+            Format::Y4m => unreachable!(),
+        };
+        decoded
+            .map(|frame| {
+                let frame = frame.unwrap();
+                let (numer, denom) = frame.delay().numer_denom_ms();
+                (frame.into_buffer(), numer / denom)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn recording_formats() {
+        assert_eq!(Some(Format::Apng), Format::from_path(Path::new("a.PNG")));
+        assert_eq!(Some(Format::Y4m), Format::from_path(Path::new("-")));
+        assert_eq!(None, Format::from_path(Path::new("a.mp4")));
+        assert!(matches!(
+            Recorder::create(Path::new("a.mp4"), (6, 4), 30.0),
+            Err(RecordError::Format(_))
+        ));
+
+        // frame durations add up to the time of the frames
+        let ms: Vec<_> = (0..3).map(|f| duration(f, 30.0, 1000.0)).collect();
+        assert_eq!(vec![33, 34, 33], ms);
+
+        let tmp_dir = TempDir::new("recording_formats").unwrap().into_path();
+        for (name, delays) in [
+            ("out.png", [33, 34, 33]),
+            ("out.webp", [33, 34, 33]),
+            ("out.gif", [30, 40, 30]),
+        ] {
+            let decoded = record(&tmp_dir.join(name));
+            assert_eq!(3, decoded.len(), "{}", name);
+            for ((image, ms), (expected, delay)) in decoded.iter().zip(frames().iter().zip(delays))
+            {
+                assert_eq!(delay, *ms, "{}", name);
+                let (actual, expected) = (image.get_pixel(2, 2), expected.get_pixel(2, 2));
+                // GIF palettes are approximate
+                assert!(
+                    actual[0].abs_diff(expected[0]) <= 8,
+                    "{}: {:?}",
+                    name,
+                    actual
+                );
+                assert_eq!(255, actual[3], "{}", name);
+            }
+        }
+    }
+
+    #[test]
+    fn y4m_stream() {
+        let mut y4m = vec![];
+        y4m_header(&mut y4m, (6, 4), 29.97).unwrap();
+        assert_eq!(b"YUV4MPEG2 W6 H4 F2997:100 Ip A1:1 C444\n", &y4m[..]);
+
+        let mut y4m = vec![];
+        let white = RgbaImage::from_pixel(6, 4, Rgba([255; 4]));
+        y4m_frame(&mut y4m, &white).unwrap();
+        assert_eq!(6 + 6 * 4 * 3, y4m.len());
+        assert_eq!([235, 128, 128], [y4m[6], y4m[6 + 24], y4m[6 + 48]]);
+    }
+}
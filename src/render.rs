@@ -0,0 +1,468 @@
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use clap::Args;
+
+use crate::record::Recorder;
+use crate::runtime::headless::Headless;
+use crate::runtime::{Renderer, Uniforms};
+use crate::toy::Toy;
+
+/// Parse a `WIDTHxHEIGHT` resolution
+pub fn parse_size(s: &str) -> Result<(u32, u32), String> {
+    let invalid = || format!("invalid size {:?}, expected WIDTHxHEIGHT", s);
+    let (w, h) = s.split_once('x').ok_or_else(invalid)?;
+    let w: u32 = w.parse().map_err(|_| invalid())?;
+    let h: u32 = h.parse().map_err(|_| invalid())?;
+    if w == 0 || h == 0 {
+        return Err(invalid());
+    }
+    Ok((w, h))
+}
+
+/// Parse a rate that is a positive, finite number, e.g. frames per second
+pub fn parse_positive(s: &str) -> Result<f32, String> {
+    match s.parse::<f32>() {
+        Ok(value) if value.is_finite() && value > 0.0 => Ok(value),
+        _ => Err(format!("invalid value {:?}, expected a positive number", s)),
+    }
+}
+
+/// Options of a fixed-timestep offscreen render
+#[derive(Args, Debug, Clone)]
+pub struct RenderOptions {
+    /// Number of frames to render
+    #[arg(long, default_value_t = 1)]
+    pub frames: u32,
+
+    /// Resolution of rendered frames
+    #[arg(long, value_parser = parse_size, default_value = "800x450")]
+    pub size: (u32, u32),
+
+    /// Value of `iTime` for the first frame, in seconds
+    #[arg(long, default_value_t = 0.0)]
+    pub time: f32,
+
+    /// Frames per second, setting `iTimeDelta` and the time between frames
+    #[arg(long, value_parser = parse_positive, default_value_t = 60.0)]
+    pub fps: f32,
+
+    /// Output file, with an optional printf-style frame number (e.g. `%04d`)
+    #[arg(short, long, default_value = "out/%04d.png")]
+    pub output: String,
+
+    /// Record all frames to a single animation or video at `output`, in the
+    /// format of its extension: gif, png (APNG), webp or y4m (`-` for y4m on
+    /// standard output)
+    #[arg(long)]
+    pub record: bool,
+}
+
+impl RenderOptions {
+    /// Set the deterministic time uniforms of a frame
+    pub fn set_frame(&self, uniforms: &mut Uniforms, frame: u32) {
+        uniforms.iTime = self.time + frame as f32 / self.fps;
+        uniforms.iTimeDelta = 1.0 / self.fps;
+        uniforms.iFrame = frame as i32;
+        uniforms.iFrameRate = self.fps;
+    }
+}
+
+/// Substitute a frame number for the first `%d` or `%0Nd` of `pattern`
+pub fn frame_path(pattern: &str, frame: u32) -> Option<PathBuf> {
+    let start = pattern.find('%')?;
+    let rest = &pattern[start + 1..];
+    let end = rest.find('d')?;
+    let width = match &rest[..end] {
+        "" => 0,
+        spec if spec.starts_with('0') => spec.parse().ok()?,
+        _ => return None,
+    };
+    Some(PathBuf::from(format!(
+        "{}{:0width$}{}",
+        &pattern[..start],
+        frame,
+        &rest[end + 1..],
+        width = width
+    )))
+}
+
+/// Render frames of a toy with an existing renderer, calling `output` with each
+pub fn render_frames<F>(
+    renderer: &mut Renderer,
+    toy: &Toy,
+    options: &RenderOptions,
+    mut output: F,
+) -> Result<(), Box<dyn Error + 'static>>
+where
+    F: FnMut(u32, image::RgbaImage) -> Result<(), Box<dyn Error + 'static>>,
+{
+    renderer.compile(toy)?;
+    let target = renderer.new_frame_target();
+    for frame in 0..options.frames {
+        options.set_frame(&mut renderer.uniforms, frame);
+        output(frame, renderer.capture(&target))?;
+    }
+    Ok(())
+}
+
+/// Render frames of the toy at `path` without a window, returning the written files
+pub fn render<P>(path: P, options: &RenderOptions) -> Result<Vec<PathBuf>, Box<dyn Error + 'static>>
+where
+    P: AsRef<Path>,
+{
+    if options.record {
+        return record(path, options);
+    }
+    if options.frames > 1 && frame_path(&options.output, 0).is_none() {
+        return Err(format!(
+            "output {:?} needs a frame number pattern such as %04d to render several frames",
+            options.output
+        )
+        .into());
+    }
+
+    let toy = Toy::from_path(&path);
+    let headless = Headless::new()?;
+    let (w, h) = options.size;
+    let mut renderer = headless.renderer(w, h);
+
+    let mut written = vec![];
+    render_frames(&mut renderer, &toy, options, |frame, image| {
+        let out = frame_path(&options.output, frame).unwrap_or(options.output.clone().into());
+        if let Some(parent) = out.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        image.save(&out)?;
+        log::info!("Wrote frame {} to {:?}", frame, out);
+        written.push(out);
+        Ok(())
+    })?;
+    Ok(written)
+}
+
+/// Render frames of the toy at `path` into a recording, returning its file
+fn record<P>(path: P, options: &RenderOptions) -> Result<Vec<PathBuf>, Box<dyn Error + 'static>>
+where
+    P: AsRef<Path>,
+{
+    let toy = Toy::from_path(&path);
+    let headless = Headless::new()?;
+    let (w, h) = options.size;
+    let mut renderer = headless.renderer(w, h);
+
+    let out = PathBuf::from(&options.output);
+    if let Some(parent) = out.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut recorder = Recorder::create(&out, options.size, options.fps)?;
+    render_frames(&mut renderer, &toy, options, |_, image| {
+        Ok(recorder.push(&image)?)
+    })?;
+    let frames = recorder.finish()?;
+    log::info!("Recorded {} frames to {:?}", frames, out);
+    Ok(vec![out])
+}
+
+pub fn run(path: PathBuf, options: RenderOptions) {
+    if let Err(e) = render(&path, &options) {
+        log::error!("Error rendering {:?}: {}", path, e);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::runtime::headless::HeadlessError;
+    use crate::runtime::FrameTarget;
+    use crate::toy::{shader, BufferName, BuiltinName, Channel, ChannelConfig};
+    use tempdir::TempDir;
+
+    /// Headless context for tests, or `None` where EGL is not installed
+    pub fn headless() -> Option<Headless> {
+        match Headless::new() {
+            Ok(headless) => Some(headless),
+            Err(HeadlessError::Load(e)) => {
+                eprintln!("skipping GPU test, EGL unavailable: {}", e);
+                None
+            }
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    /// Channel without a data source, as read by keyboard, audio, video and
+    /// Cube A pass channels
+    pub fn channel(config: ChannelConfig) -> Channel {
+        Channel {
+            name: None,
+            path: None,
+            buffer: None,
+            config,
+        }
+    }
+
+    /// Renderer of `width` by `height` pixels with `toy` compiled, along with
+    /// a target its frames are captured into
+    pub fn compiled(
+        headless: &Headless,
+        toy: &Toy,
+        (width, height): (u32, u32),
+    ) -> (Renderer, FrameTarget) {
+        let mut renderer = headless.renderer(width, height);
+        renderer.compile(toy).unwrap();
+        let target = renderer.new_frame_target();
+        (renderer, target)
+    }
+
+    /// Options rendering `frames` small frames at 60 fps
+    pub fn options(frames: u32) -> RenderOptions {
+        RenderOptions {
+            frames,
+            size: (32, 16),
+            time: 0.0,
+            fps: 60.0,
+            output: "%d.png".into(),
+            record: false,
+        }
+    }
+
+    #[test]
+    fn size_parsing() {
+        assert_eq!(Ok((800, 450)), parse_size("800x450"));
+        assert!(parse_size("800").is_err());
+        assert!(parse_size("0x10").is_err());
+        assert!(parse_size("ax10").is_err());
+    }
+
+    #[test]
+    fn positive_parsing() {
+        assert_eq!(Ok(29.97), parse_positive("29.97"));
+        for invalid in ["0", "-30", "inf", "NaN", "fast"] {
+            assert!(parse_positive(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn frame_paths() {
+        assert_eq!(
+            Some(PathBuf::from("out/0007.png")),
+            frame_path("out/%04d.png", 7)
+        );
+        assert_eq!(Some(PathBuf::from("f12.png")), frame_path("f%d.png", 12));
+        assert_eq!(None, frame_path("out.png", 1));
+        assert_eq!(None, frame_path("out%s.png", 1));
+    }
+
+    #[test]
+    fn deterministic_time() {
+        let opts = RenderOptions {
+            time: 1.0,
+            fps: 10.0,
+            ..options(1)
+        };
+        let mut uniforms = Uniforms::new(1.0, 1.0);
+        opts.set_frame(&mut uniforms, 5);
+        assert_eq!(1.5, uniforms.iTime);
+        assert_eq!(0.1, uniforms.iTimeDelta);
+        assert_eq!(5, uniforms.iFrame);
+    }
+
+    #[test]
+    fn date_uniform() {
+        let date = chrono::NaiveDate::from_ymd_opt(2024, 3, 9)
+            .unwrap()
+            .and_hms_milli_opt(1, 2, 3, 500)
+            .unwrap();
+        let mut uniforms = Uniforms::new(1.0, 1.0);
+        uniforms.set_date(&date);
+        assert_eq!((2024.0, 2.0, 9.0, 3723.5), uniforms.iDate);
+    }
+
+    #[test]
+    fn render_default_toy() {
+        let Some(headless) = headless() else {
+            return;
+        };
+        let mut renderer = headless.renderer(32, 16);
+        let mut frames = vec![];
+        render_frames(&mut renderer, &Toy::default(), &options(1), |_, im| {
+            frames.push(im);
+            Ok(())
+        })
+        .unwrap();
+
+        // bottom left pixel of the default gradient at time 0
+        let im = &frames[0];
+        assert_eq!((32, 16), im.dimensions());
+        let (u, v) = (0.5 / 32.0, 0.5 / 16.0);
+        let expected = [u, v + 2.0, u + 4.0].map(|c: f32| 255.0 * (0.5 + 0.5 * c.cos()));
+        let pixel = im.get_pixel(0, 15);
+        for (value, expected) in pixel.0.iter().zip(expected) {
+            assert!((*value as f32 - expected).abs() <= 1.0, "{:?}", pixel);
+        }
+    }
+
+    #[test]
+    fn compile_errors_refer_to_toy_files() {
+        let Some(headless) = headless() else {
+            return;
+        };
+        let mut toy = Toy::default();
+        toy.buffers.insert(
+            BufferName::A,
+            "void mainImage(out vec4 c, in vec2 p) {\n    c = vec4(1.0)\n}".into(),
+        );
+        let mut renderer = headless.renderer(32, 16);
+        let err = renderer.compile(&toy).unwrap_err().to_string();
+        assert!(err.contains("bufa.glsl:3"), "{}", err);
+
+        // including errors in included files
+        let tmp_dir = TempDir::new("compile_errors_refer_to_toy_files")
+            .unwrap()
+            .into_path();
+        fs::write(tmp_dir.join("lib.glsl"), "float a;\nfloat b = ;").unwrap();
+        let toy = Toy {
+            root: tmp_dir,
+            main_image: format!("#include \"lib.glsl\"\n{}", shader::MAIN_IMAGE),
+            ..Default::default()
+        };
+        let err = renderer.compile(&toy).unwrap_err().to_string();
+        assert!(err.contains("lib.glsl:2"), "{}", err);
+    }
+
+    /// Toy whose buffer adds 0.1 to its previous output every frame
+    pub fn feedback_toy() -> Toy {
+        let mut toy = Toy::default();
+        toy.buffers.insert(
+            BufferName::A,
+            "void mainImage(out vec4 c, in vec2 p) {
+                c = texelFetch(iChannel0, ivec2(p), 0) + vec4(0.1);
+            }"
+            .into(),
+        );
+        toy.main_image = "void mainImage(out vec4 c, in vec2 p) {
+            c = vec4(texelFetch(iChannel0, ivec2(p), 0).rgb, 1.0);
+        }"
+        .into();
+        let feedback = Channel {
+            name: None,
+            path: None,
+            buffer: Some(BufferName::A),
+            config: ChannelConfig::default(),
+        };
+        toy.config.channels = vec![feedback.clone()];
+        toy.config.buffers.insert(
+            BufferName::A,
+            crate::toy::PassConfig {
+                channels: vec![feedback],
+            },
+        );
+        toy
+    }
+
+    #[test]
+    fn render_buffer_feedback() {
+        let Some(headless) = headless() else {
+            return;
+        };
+        let toy = feedback_toy();
+        let mut renderer = headless.renderer(32, 16);
+        let mut frames = vec![];
+        render_frames(&mut renderer, &toy, &options(3), |_, im| {
+            frames.push(im);
+            Ok(())
+        })
+        .unwrap();
+
+        // buffer accumulates across frames
+        let red: Vec<i32> = frames
+            .iter()
+            .map(|im| im.get_pixel(4, 4)[0] as i32)
+            .collect();
+        for (value, expected) in red.iter().zip([26, 51, 77]) {
+            assert!((value - expected).abs() <= 1, "{:?}", red);
+        }
+    }
+
+    #[test]
+    fn render_channel_uniforms() {
+        let Some(headless) = headless() else {
+            return;
+        };
+        let mut toy = Toy {
+            main_image: "void mainImage(out vec4 c, in vec2 p) {
+                c = vec4(iChannelResolution[0].x, iChannelResolution[1].y,
+                         iChannelResolution[2].x, iSampleRate / 1000.0 + iChannelTime[0]
+                         + iDate.x) / 255.0;
+            }"
+            .into(),
+            ..Default::default()
+        };
+        toy.buffers.insert(BufferName::A, shader::MAIN_IMAGE.into());
+        let channel = |name, buffer, config| Channel {
+            name,
+            path: None,
+            buffer,
+            config,
+        };
+        toy.config.channels = vec![
+            channel(
+                Some(BuiltinName::RgbaNoiseSmall),
+                None,
+                ChannelConfig::default(),
+            ),
+            channel(None, None, ChannelConfig::Keyboard),
+            channel(None, Some(BufferName::A), ChannelConfig::default()),
+        ];
+
+        let mut renderer = headless.renderer(32, 16);
+        renderer.compile(&toy).unwrap();
+        let target = renderer.new_frame_target();
+        let pixel = renderer.capture(&target).get_pixel(0, 0).0;
+        let expected = [64, 3, 32, 44];
+        for (value, expected) in pixel.iter().zip(expected) {
+            assert!(value.abs_diff(expected) <= 1, "{:?}", pixel);
+        }
+    }
+
+    #[test]
+    fn render_to_files() {
+        if headless().is_none() {
+            return;
+        }
+        let tmp_dir = TempDir::new("render_to_files").unwrap().into_path();
+        let opts = RenderOptions {
+            output: tmp_dir.join("frames/%02d.png").to_string_lossy().into(),
+            ..options(2)
+        };
+        let written = render(&tmp_dir, &opts).unwrap();
+        assert_eq!(
+            vec![tmp_dir.join("frames/00.png"), tmp_dir.join("frames/01.png")],
+            written
+        );
+        let im = image::open(&written[1]).unwrap();
+        assert_eq!((32, 16), (im.width(), im.height()));
+
+        // several frames need a pattern
+        let opts = RenderOptions {
+            output: tmp_dir.join("out.png").to_string_lossy().into(),
+            ..options(2)
+        };
+        assert!(render(&tmp_dir, &opts).is_err());
+
+        // unless they are recorded to an animation
+        let opts = RenderOptions {
+            record: true,
+            ..opts
+        };
+        let written = render(&tmp_dir, &opts).unwrap();
+        assert_eq!(vec![tmp_dir.join("out.png")], written);
+        let file = std::io::BufReader::new(fs::File::open(&written[0]).unwrap());
+        let decoder = image::codecs::png::PngDecoder::new(file).unwrap();
+        assert!(decoder.is_apng().unwrap());
+        let frames = image::AnimationDecoder::into_frames(decoder.apng().unwrap());
+        assert_eq!(2, frames.count());
+    }
+}
@@ -1,54 +1,43 @@
-use core::panic;
-use miniquad::TextureWrap;
-use std::time::SystemTime;
+use std::path::PathBuf;
+use std::thread;
+use std::time::Instant;
 
-use image::ImageFormat;
-use image::ImageReader;
 use miniquad::*;
-use std::io::Cursor;
 
 use futures::channel::mpsc::Receiver;
 
-use crate::toy::Channel;
-use crate::toy::TextureFilter;
-use crate::toy::TextureWrap as ToyTextureWrap;
-use crate::toy::{shader, ChannelConfig, Toy};
+use crate::record::Recorder;
+use crate::toy::Toy;
+use crate::watch::WatchOptions;
+use playback::{Clock, Control};
+
+pub mod headless;
+mod keyboard;
+mod overlay;
+pub mod playback;
+mod renderer;
+mod spectrum;
+
+pub use renderer::{FrameTarget, Renderer, Uniforms};
 
 /// The runtime interface for toy execution
 pub trait IRuntime {
     /// Initialize the runtime with a toy definition and optional update channel
-    fn start(config: Toy, receiver: Option<Receiver<Toy>>);
+    fn start(config: Toy, receiver: Option<Receiver<Toy>>, options: WatchOptions);
 
     /// Compile the runtime for a given toy definition
     fn compile(&mut self, config: &Toy) -> Result<(), Box<dyn std::error::Error + 'static>>;
 }
 
-#[repr(C)]
-struct Vec2 {
-    x: f32,
-    y: f32,
-}
-
-#[repr(C)]
-struct Vertex {
-    pos: Vec2,
-    uv: Vec2,
-}
+/// Largest side of the tiles stills are rendered in
+const STILL_TILE: u32 = 2048;
 
-#[repr(C)]
-#[allow(non_snake_case)]
-pub struct Uniforms {
-    pub iResolution: (f32, f32, f32),
-    pub iMouse: (f32, f32, f32, f32),
-    pub iTime: f32,
-    pub iTimeDelta: f32,
-    pub iFrame: i32,
-    pub iFrameRate: f32,
-    // TODO
-    // iChannelTime
-    // iChannelResolution
-    // iSampleRate
-    // iDate
+/// Frames of the window being recorded
+struct Recording {
+    recorder: Recorder,
+    /// Target frames are drawn into before being shown
+    target: FrameTarget,
+    path: PathBuf,
 }
 
 enum MouseState {
@@ -57,272 +46,334 @@ enum MouseState {
 }
 
 pub struct Runtime {
-    context: Box<dyn RenderingBackend>,
-    pipeline: Option<Pipeline>,
-    bindings: Bindings,
-    uniforms: Uniforms,
-    start: SystemTime,
-    last_frame: SystemTime,
+    renderer: Renderer,
+    overlay: overlay::Overlay,
+    options: WatchOptions,
+    /// Directory of the toy, where stills are saved
+    root: PathBuf,
+    clock: Clock,
+    last_frame: Instant,
+    /// Whether the clock advanced since the last frame was drawn, rather
+    /// than being paused
+    advanced: bool,
+    /// Error compiling the latest toy, shown over the last working one
+    error: Option<String>,
+    /// Time typed after `Ctrl+J`, until it is confirmed with Enter
+    prompt: Option<String>,
+    recording: Option<Recording>,
     mouse_state: MouseState,
     receiver: Option<Receiver<Toy>>,
 }
 
 impl Runtime {
-    pub fn new(rx: Option<Receiver<Toy>>) -> Runtime {
-        let mut ctx: Box<dyn RenderingBackend> = window::new_rendering_backend();
+    pub fn new(rx: Option<Receiver<Toy>>, options: WatchOptions) -> Runtime {
+        let ctx: Box<dyn RenderingBackend> = window::new_rendering_backend();
         window::show_mouse(false);
 
-        #[rustfmt::skip]
-        let vertices: [Vertex; 4] = [
-            Vertex { pos : Vec2 { x: -1.0, y: -1.0 }, uv: Vec2 { x: 0., y: 0. } },
-            Vertex { pos : Vec2 { x:  1.0, y: -1.0 }, uv: Vec2 { x: 1., y: 0. } },
-            Vertex { pos : Vec2 { x:  1.0, y:  1.0 }, uv: Vec2 { x: 1., y: 1. } },
-            Vertex { pos : Vec2 { x: -1.0, y:  1.0 }, uv: Vec2 { x: 0., y: 1. } },
-        ];
-        let vertex_buffer = ctx.new_buffer(
-            BufferType::VertexBuffer,
-            BufferUsage::Immutable,
-            BufferSource::slice(&vertices),
-        );
-
-        let indices: [u16; 6] = [0, 1, 2, 0, 2, 3];
-        let index_buffer = ctx.new_buffer(
-            BufferType::IndexBuffer,
-            BufferUsage::Immutable,
-            BufferSource::slice(&indices),
-        );
-
-        let bindings = Bindings {
-            vertex_buffers: vec![vertex_buffer],
-            index_buffer,
-            images: vec![],
-        };
-
         let (w, h) = window::screen_size();
+        let mut renderer = Renderer::new(ctx, w, h);
+        let quad = renderer.quad_bindings();
+        let overlay = overlay::Overlay::new(renderer.context(), quad);
         Runtime {
-            pipeline: None,
-            bindings,
-            context: ctx,
-            uniforms: Uniforms {
-                iResolution: (w, h, 1.0),
-                iMouse: (0.0, 0.0, 0.0, 0.0),
-                iTime: 0.0,
-                iTimeDelta: 0.0,
-                iFrame: 0,
-                iFrameRate: 0.0,
-            },
-            start: SystemTime::now(),
-            last_frame: SystemTime::now(),
+            renderer,
+            overlay,
+            options,
+            root: PathBuf::new(),
+            clock: Clock::default(),
+            last_frame: Instant::now(),
+            advanced: false,
+            error: None,
+            prompt: None,
+            recording: None,
             mouse_state: MouseState::Up,
             receiver: rx,
         }
     }
-
-    fn add_channel(&mut self, channel: &Channel) -> TextureId {
-        let b = channel.get_bytes();
-        match channel.config {
-            ChannelConfig::Texture {
-                vflip,
-                filter,
-                wrap,
-            } => {
-                let mut reader = ImageReader::new(Cursor::new(b));
-                reader.set_format(ImageFormat::Png);
-                let mut im = reader.decode().unwrap();
-                if vflip {
-                    im = im.flipv();
-                }
-                let image = im.into_rgba8();
-                let tex_id = self.context.new_texture_from_rgba8(
-                    image.width() as _,
-                    image.height() as _,
-                    image.into_raw().as_slice(),
-                );
-                match filter {
-                    TextureFilter::Mipmap => self.context.texture_set_filter(
-                        tex_id,
-                        FilterMode::Linear,
-                        MipmapFilterMode::Nearest,
-                    ),
-                    TextureFilter::Linear => self.context.texture_set_filter(
-                        tex_id,
-                        FilterMode::Linear,
-                        MipmapFilterMode::None,
-                    ),
-                    TextureFilter::Nearest => self.context.texture_set_filter(
-                        tex_id,
-                        FilterMode::Nearest,
-                        MipmapFilterMode::None,
-                    ),
-                }
-                match wrap {
-                    ToyTextureWrap::Repeat => self.context.texture_set_wrap(
-                        tex_id,
-                        TextureWrap::Repeat,
-                        TextureWrap::Repeat,
-                    ),
-                    ToyTextureWrap::Clamp => self.context.texture_set_wrap(
-                        tex_id,
-                        TextureWrap::Clamp,
-                        TextureWrap::Clamp,
-                    ),
-                }
-                return tex_id;
-            }
-        }
-    }
 }
 
 impl IRuntime for Runtime {
-    fn start(toy: Toy, rx: Option<Receiver<Toy>>) {
+    fn start(toy: Toy, rx: Option<Receiver<Toy>>, options: WatchOptions) {
         let mut conf = conf::Conf::default();
         conf.platform.apple_gfx_api = conf::AppleGfxApi::OpenGl;
 
         miniquad::start(conf, move || {
-            let mut runtime = Self::new(rx);
-            let _ = runtime.compile(&toy);
+            let record = options.record.clone();
+            let mut runtime = Self::new(rx, options);
+            runtime.recompile(&toy);
+            if let Some(path) = record {
+                runtime.start_recording(path);
+            }
             Box::new(runtime)
         });
     }
 
     fn compile(&mut self, toy: &Toy) -> Result<(), Box<dyn std::error::Error + 'static>> {
-        self.bindings.images = toy
-            .config
-            .channels
-            .iter()
-            .map(|c| self.add_channel(c))
+        self.renderer.compile(toy)
+    }
+}
+
+impl Runtime {
+    /// Compile a toy, showing any error over the last successfully compiled one
+    fn recompile(&mut self, toy: &Toy) {
+        self.root = toy.root.clone();
+        self.error = match self.compile(toy) {
+            Ok(()) => {
+                log::debug!("Successfully recompiled shader");
+                None
+            }
+            Err(e) => {
+                log::error!("Error compiling: {:}", e);
+                Some(format!("Error compiling toy:\n{}", e))
+            }
+        };
+        self.update_overlay();
+    }
+
+    /// Show the compile error, the jump prompt and the playback status
+    fn update_overlay(&mut self) {
+        let prompt = self
+            .prompt
+            .as_ref()
+            .map(|input| format!("Jump to time (s): {}_", input));
+        let recording = self
+            .recording
+            .as_ref()
+            .map(|r| format!("Recording to {}", r.path.display()));
+        let lines: Vec<_> = [self.error.clone(), prompt, self.clock.status(), recording]
+            .into_iter()
+            .flatten()
             .collect();
-        log::debug!(
-            "Image definitions: {:?}",
-            self.bindings
-                .images
-                .iter()
-                .map(|tid| self.context.texture_params(*tid))
-                .collect::<Vec<_>>()
-        );
-
-        let fragment = toy.fragment_shader();
-
-        let meta = ShaderMeta {
-            images: (0..self.bindings.images.len())
-                .map(|i| format!("iChannel{i}"))
-                .collect(),
-            uniforms: UniformBlockLayout {
-                uniforms: vec![
-                    UniformDesc::new("iResolution", UniformType::Float3),
-                    UniformDesc::new("iMouse", UniformType::Float4),
-                    UniformDesc::new("iTime", UniformType::Float1),
-                    UniformDesc::new("iTimeDelta", UniformType::Float1),
-                    UniformDesc::new("iFrame", UniformType::Int1),
-                    UniformDesc::new("iFrameRate", UniformType::Float1),
-                ],
-            },
+        let text = (!lines.is_empty()).then(|| lines.join("\n"));
+        let (w, h, _) = self.renderer.uniforms.iResolution;
+        self.overlay.set_text(self.renderer.context(), text, w, h);
+    }
+
+    /// Timestamped file in the toy directory for a still or recording
+    fn timestamped(&self, (width, height): (u32, u32), extension: &str) -> PathBuf {
+        let time = chrono::Local::now().format("%Y%m%d-%H%M%S%.3f");
+        let name = format!("tasty-{}-{}x{}.{}", time, width, height, extension);
+        self.root.join(name)
+    }
+
+    /// Save the frame shown as a timestamped PNG in the toy directory, at the
+    /// window size or, for a `large` still, at the still size
+    fn save_still(&mut self, large: bool) {
+        let (w, h, _) = self.renderer.uniforms.iResolution;
+        let (width, height) = match (large, self.options.still_size) {
+            (false, _) => (w as u32, h as u32),
+            (true, Some(size)) => size,
+            (true, None) => (w as u32 * 4, h as u32 * 4),
+        };
+        if (width, height) != (w as u32, h as u32) && self.renderer.has_offscreen_passes() {
+            log::warn!(
+                "Buffers and Cube A are not rendered again for a {}x{} still, \
+                 the main image reads them at the window size",
+                width,
+                height
+            );
+        }
+        let image = self.renderer.render_still(width, height, STILL_TILE);
+        let path = self.timestamped((width, height), "png");
+        // encoding large stills takes a while, so it is left to a thread
+        thread::spawn(move || match image.save(&path) {
+            Ok(()) => log::info!("Saved {}", path.display()),
+            Err(e) => log::error!("Error saving {}: {}", path.display(), e),
+        });
+    }
+
+    /// Record the frames drawn from now on to `path`, at the window size
+    fn start_recording(&mut self, path: PathBuf) {
+        let (w, h, _) = self.renderer.uniforms.iResolution;
+        match Recorder::create(&path, (w as u32, h as u32), self.options.record_fps) {
+            Ok(recorder) => {
+                log::info!("Recording to {}", path.display());
+                let target = self.renderer.new_frame_target();
+                self.recording = Some(Recording {
+                    recorder,
+                    target,
+                    path,
+                });
+            }
+            Err(e) => log::error!("Error recording to {}: {}", path.display(), e),
+        }
+        self.update_overlay();
+    }
+
+    /// Complete the current recording, if any
+    fn stop_recording(&mut self) {
+        let Some(recording) = self.recording.take() else {
+            return;
         };
+        self.renderer.delete_frame_target(recording.target);
+        let path = recording.path.display();
+        match recording.recorder.finish() {
+            Ok(frames) => log::info!("Recorded {} frames to {}", frames, path),
+            Err(e) => log::error!("Error completing recording {}: {}", path, e),
+        }
+        self.update_overlay();
+    }
 
-        match self.context.new_shader(
-            match self.context.info().backend {
-                Backend::OpenGl => ShaderSource::Glsl {
-                    vertex: shader::VERTEX,
-                    fragment: fragment.as_str(),
-                },
-                Backend::Metal => panic!("Metal not supported"),
-            },
-            meta,
-        ) {
-            Ok(shader) => {
-                self.pipeline = Some(self.context.new_pipeline(
-                    &[BufferLayout::default()],
-                    &[
-                        VertexAttribute::new("in_pos", VertexFormat::Float2),
-                        VertexAttribute::new("in_uv", VertexFormat::Float2),
-                    ],
-                    shader,
-                    PipelineParams::default(),
-                ));
-                Ok(())
+    /// Start a timestamped recording in the toy directory, or stop the
+    /// current one
+    fn toggle_recording(&mut self) {
+        if self.recording.is_some() {
+            self.stop_recording();
+            return;
+        }
+        let extension = self
+            .options
+            .record
+            .as_ref()
+            .and_then(|path| path.extension())
+            .and_then(|extension| extension.to_str())
+            .unwrap_or("gif")
+            .to_owned();
+        let (w, h, _) = self.renderer.uniforms.iResolution;
+        let path = self.timestamped((w as u32, h as u32), &extension);
+        self.start_recording(path);
+    }
+
+    /// Handle a key typed while prompting for a time to jump to
+    fn prompt_key(&mut self, keycode: KeyCode) {
+        match keycode {
+            KeyCode::Enter | KeyCode::KpEnter => {
+                let input = self.prompt.take().unwrap_or_default();
+                match input.parse::<f32>() {
+                    Ok(time) => self.clock.jump(time),
+                    Err(_) => log::error!("Invalid time to jump to: {:?}", input),
+                }
+            }
+            KeyCode::Escape => self.prompt = None,
+            KeyCode::Backspace => {
+                self.prompt.as_mut().map(String::pop);
             }
-            Err(err) => Err(Box::new(err)),
+            _ => return,
         }
+        self.update_overlay();
     }
 }
 
 impl EventHandler for Runtime {
     fn update(&mut self) {
-        let now = (SystemTime::now()
-            .duration_since(self.start)
-            .expect("Linear time")
-            .as_millis() as f32)
-            / 1000.0;
-        let dt = (SystemTime::now()
-            .duration_since(self.last_frame)
-            .expect("Linear time")
-            .as_millis() as f32)
-            / 1000.0;
-        self.last_frame = SystemTime::now();
-        self.uniforms.iTime = now;
-        self.uniforms.iTimeDelta = dt;
-        self.uniforms.iFrame += 1;
-        self.uniforms.iFrameRate = 1.0 / dt;
-
-        match &mut self.receiver {
-            Some(rec) => match rec.try_next() {
-                Ok(Some(cfg)) => match self.compile(&cfg) {
-                    Ok(()) => log::debug!("Successfully recompiled shader"),
-                    // TODO: add visual indicator of error
-                    Err(e) => log::error!("Error compiling: {:}", e),
-                },
+        let now = Instant::now();
+        let mut elapsed = now.duration_since(self.last_frame).as_secs_f32();
+        self.last_frame = now;
+        // recordings advance by a fixed step, however long frames take
+        if self.recording.is_some() {
+            elapsed = 1.0 / self.options.record_fps;
+        }
+        let tick = self.clock.tick(elapsed);
+        if let Some(tick) = tick {
+            tick.apply(&mut self.renderer.uniforms);
+            if self.clock.paused() {
+                // the frame number shown changed
+                self.update_overlay();
+            }
+        }
+        self.advanced = tick.is_some();
+        self.renderer.uniforms.set_date(&chrono::Local::now());
+
+        if let Some(rec) = &mut self.receiver {
+            match rec.try_next() {
+                Ok(Some(cfg)) => self.recompile(&cfg),
                 Ok(None) => log::info!("Channel closed"),
                 Err(_e) => (),
-            },
-            None => (),
+            }
         }
     }
 
     fn draw(&mut self) {
-        match self.pipeline {
-            Some(pipeline) => {
-                self.context.begin_default_pass(Default::default());
-
-                self.context.apply_pipeline(&pipeline);
-                self.context.apply_bindings(&self.bindings);
-                self.context
-                    .apply_uniforms(UniformsSource::table(&self.uniforms));
-                self.context.draw(0, 6, 1);
-                self.context.end_render_pass();
-
-                self.context.commit_frame();
+        // while paused the buffers hold still, and only the image is drawn
+        // again, e.g. to follow the mouse
+        match &mut self.recording {
+            Some(recording) if self.advanced => {
+                // the frame is recorded from its own target, then shown
+                let frame = self.renderer.capture(&recording.target);
+                self.renderer.redraw(None);
+                if let Err(e) = recording.recorder.push(&frame) {
+                    log::error!("Error recording frame: {}", e);
+                    self.stop_recording();
+                }
             }
-            None => (),
+            _ if self.advanced => self.renderer.draw(None),
+            _ => self.renderer.redraw(None),
         }
+        self.overlay.draw(self.renderer.context());
+        self.renderer.context().commit_frame();
     }
 
     fn resize_event(&mut self, _width: f32, _height: f32) {
-        self.uniforms.iResolution = (_width, _height, 1.0);
+        // frames of a recording all have the same size
+        if self.recording.is_some() {
+            log::info!("Window resized, stopping the recording");
+            self.stop_recording();
+        }
+        self.renderer.resize(_width, _height);
+        self.overlay
+            .resize(self.renderer.context(), _width, _height);
     }
 
-    fn mouse_motion_event(&mut self, _x: f32, _y: f32) {
-        match self.mouse_state {
-            MouseState::Down { x, y } => {
-                let h = self.uniforms.iResolution.1;
-                self.uniforms.iMouse = (_x, h - _y, x, h - y);
+    fn key_down_event(&mut self, keycode: KeyCode, keymods: KeyMods, _repeat: bool) {
+        if self.prompt.is_some() {
+            self.prompt_key(keycode);
+            return;
+        }
+        if keymods.ctrl && keycode == KeyCode::S {
+            self.save_still(keymods.shift);
+            return;
+        }
+        if keymods.ctrl && keymods.shift && keycode == KeyCode::R {
+            self.toggle_recording();
+            return;
+        }
+        if let Some(control) = playback::control(keycode, keymods) {
+            match control {
+                Control::Jump => self.prompt = Some(String::new()),
+                control => self.clock.apply(control),
             }
-            _ => (),
+            self.update_overlay();
+            return;
+        }
+        if let Some(code) = keyboard::key_code(keycode) {
+            self.renderer.keyboard.key_down(code);
         }
     }
 
-    fn mouse_button_down_event(&mut self, _button: MouseButton, _x: f32, _y: f32) {
-        match _button {
-            MouseButton::Left => {
-                self.mouse_state = MouseState::Down { x: _x, y: _y };
+    fn char_event(&mut self, character: char, _keymods: KeyMods, _repeat: bool) {
+        if let Some(input) = &mut self.prompt {
+            if character.is_ascii_digit() || character == '.' {
+                input.push(character);
+                self.update_overlay();
             }
-            _ => (),
+        }
+    }
+
+    fn key_up_event(&mut self, keycode: KeyCode, _keymods: KeyMods) {
+        if let Some(code) = keyboard::key_code(keycode) {
+            self.renderer.keyboard.key_up(code);
+        }
+    }
+
+    fn quit_requested_event(&mut self) {
+        self.stop_recording();
+    }
+
+    fn mouse_motion_event(&mut self, _x: f32, _y: f32) {
+        if let MouseState::Down { x, y } = self.mouse_state {
+            let h = self.renderer.uniforms.iResolution.1;
+            self.renderer.uniforms.iMouse = (_x, h - _y, x, h - y);
+        }
+    }
+
+    fn mouse_button_down_event(&mut self, _button: MouseButton, _x: f32, _y: f32) {
+        if _button == MouseButton::Left {
+            self.mouse_state = MouseState::Down { x: _x, y: _y };
         }
     }
 
     fn mouse_button_up_event(&mut self, _button: MouseButton, _x: f32, _y: f32) {
-        match _button {
-            MouseButton::Left => self.mouse_state = MouseState::Up,
-            _ => (),
+        if _button == MouseButton::Left {
+            self.mouse_state = MouseState::Up;
         }
     }
 }
@@ -0,0 +1,131 @@
+use std::fmt;
+
+use khronos_egl as egl;
+use miniquad::{gl, GlContext};
+
+use super::Renderer;
+
+/// `EGL_PLATFORM_SURFACELESS_MESA`, rendering without any display server
+const PLATFORM_SURFACELESS_MESA: egl::Enum = 0x31DD;
+
+/// `EGL_CONTEXT_OPENGL_COMPATIBILITY_PROFILE_BIT`, needed for the legacy
+/// `attribute`/`varying` qualifiers of the vertex shader
+const CONTEXT_OPENGL_COMPATIBILITY_PROFILE_BIT: egl::Int = 0x2;
+
+/// Error creating a headless OpenGL context
+#[derive(Debug)]
+pub enum HeadlessError {
+    /// The EGL library could not be loaded
+    Load(String),
+    /// No EGL display is available
+    Display,
+    /// No EGL config supports OpenGL without a surface
+    Config,
+    /// An EGL call failed
+    Egl(egl::Error),
+}
+
+impl fmt::Display for HeadlessError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HeadlessError::Load(e) => write!(f, "error loading EGL: {}", e),
+            HeadlessError::Display => write!(f, "no EGL display available"),
+            HeadlessError::Config => write!(f, "no EGL config supports surfaceless OpenGL"),
+            HeadlessError::Egl(e) => write!(f, "EGL error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for HeadlessError {}
+
+impl From<egl::Error> for HeadlessError {
+    fn from(e: egl::Error) -> Self {
+        HeadlessError::Egl(e)
+    }
+}
+
+/// An OpenGL context current on the calling thread without any window, e.g.
+/// for rendering on machines without a display using Mesa's llvmpipe
+pub struct Headless {
+    egl: egl::DynamicInstance<egl::EGL1_4>,
+    display: egl::Display,
+    context: egl::Context,
+}
+
+impl Headless {
+    pub fn new() -> Result<Headless, HeadlessError> {
+        let egl = unsafe { egl::DynamicInstance::<egl::EGL1_4>::load_required() }
+            .map_err(|e| HeadlessError::Load(e.to_string()))?;
+
+        // prefer the surfaceless platform, which needs no display server at all
+        let surfaceless = egl.upcast::<egl::EGL1_5>().and_then(|egl| unsafe {
+            egl.get_platform_display(
+                PLATFORM_SURFACELESS_MESA,
+                egl::DEFAULT_DISPLAY,
+                &[egl::ATTRIB_NONE],
+            )
+            .ok()
+        });
+        let display = match surfaceless {
+            Some(display) => display,
+            None => {
+                unsafe { egl.get_display(egl::DEFAULT_DISPLAY) }.ok_or(HeadlessError::Display)?
+            }
+        };
+        egl.initialize(display)?;
+        egl.bind_api(egl::OPENGL_API)?;
+
+        let config = egl
+            .choose_first_config(
+                display,
+                &[
+                    egl::SURFACE_TYPE,
+                    0,
+                    egl::RENDERABLE_TYPE,
+                    egl::OPENGL_BIT,
+                    egl::NONE,
+                ],
+            )?
+            .ok_or(HeadlessError::Config)?;
+        let context = egl.create_context(
+            display,
+            config,
+            None,
+            &[
+                egl::CONTEXT_MAJOR_VERSION,
+                3,
+                egl::CONTEXT_MINOR_VERSION,
+                3,
+                egl::CONTEXT_OPENGL_PROFILE_MASK,
+                CONTEXT_OPENGL_COMPATIBILITY_PROFILE_BIT,
+                egl::NONE,
+            ],
+        )?;
+        egl.make_current(display, None, None, Some(context))?;
+
+        gl::load_gl_funcs(|name| {
+            egl.get_proc_address(name)
+                .map(|f| unsafe { std::mem::transmute::<extern "system" fn(), _>(f) })
+        });
+
+        Ok(Headless {
+            egl,
+            display,
+            context,
+        })
+    }
+
+    /// Create a renderer drawing with this context
+    pub fn renderer(&self, width: u32, height: u32) -> Renderer {
+        Renderer::new(Box::new(GlContext::new()), width as f32, height as f32)
+    }
+}
+
+impl Drop for Headless {
+    fn drop(&mut self) {
+        // the display is shared by every context of the process, so it is
+        // left initialized
+        let _ = self.egl.make_current(self.display, None, None, None);
+        let _ = self.egl.destroy_context(self.display, self.context);
+    }
+}
@@ -0,0 +1,251 @@
+use miniquad::KeyCode;
+
+/// Width of the keyboard texture, one texel per key code
+pub const WIDTH: usize = 256;
+
+/// Rows of the keyboard texture: keys held down, keys pressed this frame and
+/// keys toggled by every press
+pub const ROWS: usize = 3;
+
+/// Key state read by keyboard channels, laid out like Shadertoy's keyboard
+/// texture and indexed by JavaScript key codes
+#[derive(Debug, Clone)]
+pub struct Keyboard {
+    state: [[u8; WIDTH]; ROWS],
+    /// Whether the state changed since the texture was last updated
+    dirty: bool,
+}
+
+impl Default for Keyboard {
+    fn default() -> Self {
+        Keyboard {
+            state: [[0; WIDTH]; ROWS],
+            dirty: true,
+        }
+    }
+}
+
+impl Keyboard {
+    pub fn key_down(&mut self, code: u8) {
+        let code = code as usize;
+        // repeats of a held key are not new presses
+        if self.state[0][code] == 0 {
+            self.state[0][code] = 255;
+            self.state[1][code] = 255;
+            self.state[2][code] ^= 255;
+            self.dirty = true;
+        }
+    }
+
+    pub fn key_up(&mut self, code: u8) {
+        self.state[0][code as usize] = 0;
+        self.dirty = true;
+    }
+
+    /// Clear presses once a frame has been drawn with them
+    pub fn end_frame(&mut self) {
+        if self.state[1].iter().any(|&v| v != 0) {
+            self.state[1] = [0; WIDTH];
+            self.dirty = true;
+        }
+    }
+
+    /// RGBA texture data of the state if it changed since the last call
+    pub fn take_update(&mut self) -> Option<Vec<u8>> {
+        if !self.dirty {
+            return None;
+        }
+        self.dirty = false;
+        Some(
+            self.state
+                .iter()
+                .flatten()
+                .flat_map(|&v| [v, v, v, 255])
+                .collect(),
+        )
+    }
+}
+
+/// JavaScript key code of a key, as used by Shadertoy
+pub fn key_code(key: KeyCode) -> Option<u8> {
+    let code = match key {
+        KeyCode::Backspace => 8,
+        KeyCode::Tab => 9,
+        KeyCode::Enter | KeyCode::KpEnter => 13,
+        KeyCode::LeftShift | KeyCode::RightShift => 16,
+        KeyCode::LeftControl | KeyCode::RightControl => 17,
+        KeyCode::LeftAlt | KeyCode::RightAlt => 18,
+        KeyCode::Pause => 19,
+        KeyCode::CapsLock => 20,
+        KeyCode::Escape => 27,
+        KeyCode::Space => 32,
+        KeyCode::PageUp => 33,
+        KeyCode::PageDown => 34,
+        KeyCode::End => 35,
+        KeyCode::Home => 36,
+        KeyCode::Left => 37,
+        KeyCode::Up => 38,
+        KeyCode::Right => 39,
+        KeyCode::Down => 40,
+        KeyCode::PrintScreen => 44,
+        KeyCode::Insert => 45,
+        KeyCode::Delete => 46,
+        // digits and letters match their ASCII codes
+        KeyCode::Key0
+        | KeyCode::Key1
+        | KeyCode::Key2
+        | KeyCode::Key3
+        | KeyCode::Key4
+        | KeyCode::Key5
+        | KeyCode::Key6
+        | KeyCode::Key7
+        | KeyCode::Key8
+        | KeyCode::Key9
+        | KeyCode::A
+        | KeyCode::B
+        | KeyCode::C
+        | KeyCode::D
+        | KeyCode::E
+        | KeyCode::F
+        | KeyCode::G
+        | KeyCode::H
+        | KeyCode::I
+        | KeyCode::J
+        | KeyCode::K
+        | KeyCode::L
+        | KeyCode::M
+        | KeyCode::N
+        | KeyCode::O
+        | KeyCode::P
+        | KeyCode::Q
+        | KeyCode::R
+        | KeyCode::S
+        | KeyCode::T
+        | KeyCode::U
+        | KeyCode::V
+        | KeyCode::W
+        | KeyCode::X
+        | KeyCode::Y
+        | KeyCode::Z => key as u8,
+        KeyCode::LeftSuper => 91,
+        KeyCode::RightSuper => 92,
+        KeyCode::Menu => 93,
+        KeyCode::Kp0 => 96,
+        KeyCode::Kp1 => 97,
+        KeyCode::Kp2 => 98,
+        KeyCode::Kp3 => 99,
+        KeyCode::Kp4 => 100,
+        KeyCode::Kp5 => 101,
+        KeyCode::Kp6 => 102,
+        KeyCode::Kp7 => 103,
+        KeyCode::Kp8 => 104,
+        KeyCode::Kp9 => 105,
+        KeyCode::KpMultiply => 106,
+        KeyCode::KpAdd => 107,
+        KeyCode::KpSubtract => 109,
+        KeyCode::KpDecimal => 110,
+        KeyCode::KpDivide => 111,
+        KeyCode::F1 => 112,
+        KeyCode::F2 => 113,
+        KeyCode::F3 => 114,
+        KeyCode::F4 => 115,
+        KeyCode::F5 => 116,
+        KeyCode::F6 => 117,
+        KeyCode::F7 => 118,
+        KeyCode::F8 => 119,
+        KeyCode::F9 => 120,
+        KeyCode::F10 => 121,
+        KeyCode::F11 => 122,
+        KeyCode::F12 => 123,
+        KeyCode::NumLock => 144,
+        KeyCode::ScrollLock => 145,
+        KeyCode::Semicolon => 186,
+        KeyCode::Equal | KeyCode::KpEqual => 187,
+        KeyCode::Comma => 188,
+        KeyCode::Minus => 189,
+        KeyCode::Period => 190,
+        KeyCode::Slash => 191,
+        KeyCode::GraveAccent => 192,
+        KeyCode::LeftBracket => 219,
+        KeyCode::Backslash => 220,
+        KeyCode::RightBracket => 221,
+        KeyCode::Apostrophe => 222,
+        _ => return None,
+    };
+    Some(code)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::render::test::{channel, compiled, headless};
+    use crate::toy::{ChannelConfig, Toy};
+
+    #[test]
+    fn key_codes() {
+        assert_eq!(Some(65), key_code(KeyCode::A));
+        assert_eq!(Some(57), key_code(KeyCode::Key9));
+        assert_eq!(Some(37), key_code(KeyCode::Left));
+        assert_eq!(Some(32), key_code(KeyCode::Space));
+        assert_eq!(None, key_code(KeyCode::F25));
+    }
+
+    #[test]
+    fn key_state() {
+        let mut keyboard = Keyboard::default();
+        assert_eq!(
+            Some(WIDTH * ROWS * 4),
+            keyboard.take_update().map(|d| d.len())
+        );
+        assert_eq!(None, keyboard.take_update());
+
+        keyboard.key_down(65);
+        let data = keyboard.take_update().unwrap();
+        let texel = |data: &[u8], x: usize, y: usize| data[(y * WIDTH + x) * 4];
+        assert_eq!([255, 255, 255], [0, 1, 2].map(|row| texel(&data, 65, row)));
+
+        // presses last a single frame, and repeats are not new presses
+        keyboard.end_frame();
+        keyboard.key_down(65);
+        let data = keyboard.take_update().unwrap();
+        assert_eq!([255, 0, 255], [0, 1, 2].map(|row| texel(&data, 65, row)));
+
+        // toggles flip on every press
+        keyboard.key_up(65);
+        keyboard.key_down(65);
+        keyboard.key_up(65);
+        let data = keyboard.take_update().unwrap();
+        assert_eq!([0, 255, 0], [0, 1, 2].map(|row| texel(&data, 65, row)));
+    }
+
+    #[test]
+    fn render_keyboard() {
+        let Some(headless) = headless() else {
+            return;
+        };
+        let mut toy = Toy {
+            main_image: "void mainImage(out vec4 c, in vec2 p) {
+                c = vec4(texelFetch(iChannel0, ivec2(65, 0), 0).x,
+                         texelFetch(iChannel0, ivec2(65, 1), 0).x,
+                         texelFetch(iChannel0, ivec2(66, 2), 0).x, 1.0);
+            }"
+            .into(),
+            ..Default::default()
+        };
+        toy.config.channels = vec![channel(ChannelConfig::Keyboard)];
+
+        let (mut renderer, target) = compiled(&headless, &toy, (4, 4));
+        assert_eq!([0, 0, 0], renderer.capture(&target).get_pixel(0, 0).0[..3]);
+
+        // presses are seen for a single frame
+        renderer.keyboard.key_down(65);
+        assert_eq!(
+            [255, 255, 0],
+            renderer.capture(&target).get_pixel(0, 0).0[..3]
+        );
+        assert_eq!(
+            [255, 0, 0],
+            renderer.capture(&target).get_pixel(0, 0).0[..3]
+        );
+    }
+}
@@ -0,0 +1,214 @@
+use font8x8::{UnicodeFonts, BASIC_FONTS};
+use image::{Rgba, RgbaImage};
+use miniquad::*;
+
+use crate::toy::shader;
+
+const FRAGMENT: &str = r#"#version 330
+varying lowp vec2 texcoord;
+
+uniform sampler2D overlay;
+
+out vec4 outColor;
+
+void main() {
+    // overlay rows are stored top first
+    outColor = texture(overlay, vec2(texcoord.x, 1.0 - texcoord.y));
+}"#;
+
+/// Size of a rendered font pixel in screen pixels
+const SCALE: u32 = 2;
+const GLYPH_SIZE: u32 = 8 * SCALE;
+const LINE_HEIGHT: u32 = GLYPH_SIZE + 2;
+const MARGIN: u32 = 8;
+const BACKGROUND: Rgba<u8> = Rgba([0, 0, 0, 200]);
+const FOREGROUND: Rgba<u8> = Rgba([255, 96, 96, 255]);
+
+/// Split text into lines of at most `columns` characters
+fn wrap(text: &str, columns: usize) -> Vec<String> {
+    text.lines()
+        .flat_map(|line| {
+            let chars: Vec<char> = line.replace('\t', "    ").chars().collect();
+            if chars.is_empty() {
+                return vec![String::new()];
+            }
+            chars.chunks(columns).map(|c| c.iter().collect()).collect()
+        })
+        .collect()
+}
+
+/// Draw text at the top of a transparent image, over a dark backdrop
+pub fn rasterize(text: &str, width: u32, height: u32) -> RgbaImage {
+    let columns = (width.saturating_sub(2 * MARGIN) / GLYPH_SIZE).max(1) as usize;
+    let lines = wrap(text, columns);
+
+    let mut image = RgbaImage::new(width, height);
+    let backdrop = (lines.len() as u32 * LINE_HEIGHT + 2 * MARGIN).min(height);
+    for y in 0..backdrop {
+        for x in 0..width {
+            image.put_pixel(x, y, BACKGROUND);
+        }
+    }
+
+    for (row, line) in lines.iter().enumerate() {
+        let top = MARGIN + row as u32 * LINE_HEIGHT;
+        for (col, c) in line.chars().enumerate() {
+            let left = MARGIN + col as u32 * GLYPH_SIZE;
+            let Some(glyph) = BASIC_FONTS.get(c).or(BASIC_FONTS.get('?')) else {
+                continue;
+            };
+            for (gy, bits) in glyph.iter().enumerate() {
+                for gx in 0..8 {
+                    if bits & (1 << gx) == 0 {
+                        continue;
+                    }
+                    for dy in 0..SCALE {
+                        for dx in 0..SCALE {
+                            let x = left + gx * SCALE + dx;
+                            let y = top + gy as u32 * SCALE + dy;
+                            if x < width && y < height {
+                                image.put_pixel(x, y, FOREGROUND);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+    image
+}
+
+/// Text drawn over the rendered frame, e.g. to show compile errors
+pub struct Overlay {
+    pipeline: Pipeline,
+    bindings: Bindings,
+    text: Option<String>,
+}
+
+impl Overlay {
+    pub fn new(ctx: &mut dyn RenderingBackend, bindings: Bindings) -> Overlay {
+        let shader = ctx
+            .new_shader(
+                ShaderSource::Glsl {
+                    vertex: shader::VERTEX,
+                    fragment: FRAGMENT,
+                },
+                ShaderMeta {
+                    images: vec!["overlay".into()],
+                    uniforms: UniformBlockLayout { uniforms: vec![] },
+                },
+            )
+            .expect("overlay shader compiles");
+        let pipeline = ctx.new_pipeline(
+            &[BufferLayout::default()],
+            &[
+                VertexAttribute::new("in_pos", VertexFormat::Float2),
+                VertexAttribute::new("in_uv", VertexFormat::Float2),
+            ],
+            shader,
+            PipelineParams {
+                color_blend: Some(BlendState::new(
+                    Equation::Add,
+                    BlendFactor::Value(BlendValue::SourceAlpha),
+                    BlendFactor::OneMinusValue(BlendValue::SourceAlpha),
+                )),
+                ..Default::default()
+            },
+        );
+        Overlay {
+            pipeline,
+            bindings,
+            text: None,
+        }
+    }
+
+    /// Show `text` over a frame of the given size, or nothing when `None`
+    pub fn set_text(
+        &mut self,
+        ctx: &mut dyn RenderingBackend,
+        text: Option<String>,
+        width: f32,
+        height: f32,
+    ) {
+        if text == self.text {
+            return;
+        }
+        self.text = text;
+        self.resize(ctx, width, height);
+    }
+
+    /// Redraw the overlay texture for a new window size
+    pub fn resize(&mut self, ctx: &mut dyn RenderingBackend, width: f32, height: f32) {
+        for tex in self.bindings.images.drain(..) {
+            ctx.delete_texture(tex);
+        }
+        if let Some(text) = &self.text {
+            let image = rasterize(text, width as u32, height as u32);
+            let tex =
+                ctx.new_texture_from_rgba8(image.width() as _, image.height() as _, image.as_raw());
+            ctx.texture_set_filter(tex, FilterMode::Nearest, MipmapFilterMode::None);
+            self.bindings.images.push(tex);
+        }
+    }
+
+    /// Draw the overlay on top of the window contents
+    pub fn draw(&self, ctx: &mut dyn RenderingBackend) {
+        if self.bindings.images.is_empty() {
+            return;
+        }
+        ctx.begin_default_pass(PassAction::Nothing);
+        ctx.apply_pipeline(&self.pipeline);
+        ctx.apply_bindings(&self.bindings);
+        ctx.draw(0, 6, 1);
+        ctx.end_render_pass();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::render::test::headless;
+
+    #[test]
+    fn text_wrapping() {
+        assert_eq!(vec!["abc", "de", "", "f"], wrap("abcde\n\nf", 3));
+    }
+
+    #[test]
+    fn text_rasterizing() {
+        let image = rasterize("I\nI", 64, 64);
+
+        // backdrop covers both lines only
+        assert_eq!(&BACKGROUND, image.get_pixel(0, 0));
+        let bottom = 2 * LINE_HEIGHT + 2 * MARGIN;
+        assert_eq!(&BACKGROUND, image.get_pixel(0, bottom - 1));
+        assert_eq!(0, image.get_pixel(0, bottom)[3]);
+
+        // glyphs are drawn on both lines
+        for top in [MARGIN, MARGIN + LINE_HEIGHT] {
+            let drawn = (top..top + GLYPH_SIZE)
+                .flat_map(|y| (MARGIN..MARGIN + GLYPH_SIZE).map(move |x| (x, y)))
+                .filter(|&(x, y)| image.get_pixel(x, y) == &FOREGROUND)
+                .count();
+            assert!(drawn > 0);
+        }
+    }
+
+    #[test]
+    fn overlay_texture() {
+        let Some(headless) = headless() else {
+            return;
+        };
+        let mut renderer = headless.renderer(64, 32);
+        let quad = renderer.quad_bindings();
+        let ctx = renderer.context();
+        let mut overlay = Overlay::new(ctx, quad);
+
+        overlay.set_text(ctx, Some("error".into()), 64.0, 32.0);
+        assert_eq!(1, overlay.bindings.images.len());
+        assert_eq!((64, 32), ctx.texture_size(overlay.bindings.images[0]));
+
+        overlay.set_text(ctx, None, 64.0, 32.0);
+        assert!(overlay.bindings.images.is_empty());
+    }
+}
@@ -0,0 +1,237 @@
+use miniquad::{KeyCode, KeyMods};
+
+use super::Uniforms;
+
+/// Slowest playback speed
+pub const MIN_SPEED: f32 = 0.25;
+
+/// Fastest playback speed
+pub const MAX_SPEED: f32 = 4.0;
+
+/// Time step of frames stepped through while paused, at normal speed
+pub const STEP: f32 = 1.0 / 60.0;
+
+/// Playback control bound to a key, pressed along with Ctrl
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Control {
+    /// Pause or resume time
+    Pause,
+    /// Advance a single frame, pausing time
+    Step,
+    /// Rewind `iTime` and `iFrame` to zero
+    Reset,
+    /// Prompt for a time to jump to
+    Jump,
+    /// Halve the playback speed
+    Slower,
+    /// Double the playback speed
+    Faster,
+    /// Play back at normal speed
+    NormalSpeed,
+}
+
+/// Playback control bound to a key, if any
+pub fn control(key: KeyCode, mods: KeyMods) -> Option<Control> {
+    if !mods.ctrl {
+        return None;
+    }
+    match key {
+        KeyCode::Space => Some(Control::Pause),
+        KeyCode::Right => Some(Control::Step),
+        KeyCode::R => Some(Control::Reset),
+        KeyCode::J => Some(Control::Jump),
+        KeyCode::Down => Some(Control::Slower),
+        KeyCode::Up => Some(Control::Faster),
+        KeyCode::Key0 => Some(Control::NormalSpeed),
+        _ => None,
+    }
+}
+
+/// Time uniforms of a frame to draw
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Tick {
+    pub time: f32,
+    pub delta: f32,
+    pub frame: i32,
+    pub rate: f32,
+}
+
+impl Tick {
+    /// Set the time uniforms of the frame
+    pub fn apply(&self, uniforms: &mut Uniforms) {
+        uniforms.iTime = self.time;
+        uniforms.iTimeDelta = self.delta;
+        uniforms.iFrame = self.frame;
+        uniforms.iFrameRate = self.rate;
+    }
+}
+
+/// Time of a toy in the watch window, following the wall clock at an
+/// adjustable speed unless paused
+#[derive(Debug, Clone, PartialEq)]
+pub struct Clock {
+    /// `iTime` of the next frame
+    time: f32,
+    /// `iFrame` of the next frame
+    frame: i32,
+    speed: f32,
+    paused: bool,
+    /// Frames left to step through while paused
+    steps: u32,
+    /// Whether the next frame shows `time` as is, after a reset or jump
+    hold: bool,
+}
+
+impl Default for Clock {
+    fn default() -> Self {
+        Clock {
+            time: 0.0,
+            frame: 0,
+            speed: 1.0,
+            paused: false,
+            steps: 0,
+            hold: true,
+        }
+    }
+}
+
+impl Clock {
+    /// Advance by `elapsed` seconds of wall clock time, returning the frame to
+    /// draw, or `None` while paused
+    pub fn tick(&mut self, elapsed: f32) -> Option<Tick> {
+        let (delta, rate) = if !self.paused {
+            (elapsed * self.speed, 1.0 / elapsed)
+        } else if self.steps > 0 {
+            self.steps -= 1;
+            (STEP * self.speed, 1.0 / STEP)
+        } else {
+            return None;
+        };
+        if self.hold {
+            self.hold = false;
+        } else {
+            self.time += delta;
+        }
+        let tick = Tick {
+            time: self.time,
+            delta,
+            frame: self.frame,
+            rate,
+        };
+        self.frame += 1;
+        Some(tick)
+    }
+
+    /// Apply a control other than `Jump`, which needs a time
+    pub fn apply(&mut self, control: Control) {
+        match control {
+            Control::Pause => {
+                self.paused = !self.paused;
+                self.steps = 0;
+            }
+            Control::Step => {
+                self.paused = true;
+                self.steps += 1;
+            }
+            Control::Reset => {
+                self.frame = 0;
+                self.jump(0.0);
+            }
+            Control::Jump => (),
+            Control::Slower => self.speed = (self.speed / 2.0).max(MIN_SPEED),
+            Control::Faster => self.speed = (self.speed * 2.0).min(MAX_SPEED),
+            Control::NormalSpeed => self.speed = 1.0,
+        }
+    }
+
+    /// Show `time` in the next frame, drawing it even while paused
+    pub fn jump(&mut self, time: f32) {
+        self.time = time.max(0.0);
+        self.hold = true;
+        if self.paused {
+            self.steps = self.steps.max(1);
+        }
+    }
+
+    pub fn paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Description of playback when it differs from playing at normal speed
+    pub fn status(&self) -> Option<String> {
+        let speed = (self.speed != 1.0).then(|| format!(", {}x speed", self.speed));
+        if self.paused {
+            // the frame shown is the one before the next frame
+            let frame = (self.frame - 1).max(0);
+            let speed = speed.unwrap_or_default();
+            Some(format!(
+                "Paused at {:.3}s, frame {}{}",
+                self.time, frame, speed
+            ))
+        } else {
+            speed.map(|speed| format!("Playing{}", speed))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn clock_controls() {
+        let mut clock = Clock::default();
+        let times = |clock: &mut Clock, elapsed: f32| {
+            let tick = clock.tick(elapsed).unwrap();
+            (tick.time, tick.frame)
+        };
+        // the first frame is drawn at zero
+        assert_eq!((0.0, 0), times(&mut clock, 0.5));
+        assert_eq!((0.5, 1), times(&mut clock, 0.5));
+        assert_eq!(None, clock.status());
+
+        clock.apply(Control::Slower);
+        clock.apply(Control::Slower);
+        clock.apply(Control::Slower);
+        assert_eq!((0.625, 2), times(&mut clock, 0.5));
+        assert_eq!(Some("Playing, 0.25x speed".into()), clock.status());
+        clock.apply(Control::NormalSpeed);
+
+        clock.apply(Control::Pause);
+        assert!(clock.paused());
+        assert_eq!(None, clock.tick(0.5));
+        clock.apply(Control::Step);
+        clock.apply(Control::Step);
+        assert_eq!((0.625 + STEP, 3), times(&mut clock, 0.5));
+        let (time, frame) = times(&mut clock, 0.5);
+        assert!((time - 0.625 - 2.0 * STEP).abs() < 1e-6 && frame == 4);
+        assert_eq!(None, clock.tick(0.5));
+        assert_eq!(Some("Paused at 0.658s, frame 4".into()), clock.status());
+
+        // jumps and resets are shown while paused
+        clock.jump(10.0);
+        assert_eq!((10.0, 5), times(&mut clock, 0.5));
+        clock.apply(Control::Reset);
+        assert_eq!((0.0, 0), times(&mut clock, 0.5));
+        assert_eq!(None, clock.tick(0.5));
+
+        clock.apply(Control::Pause);
+        for _ in 0..4 {
+            clock.apply(Control::Faster);
+        }
+        assert_eq!((2.0, 1), times(&mut clock, 0.5));
+    }
+
+    #[test]
+    fn control_bindings() {
+        let ctrl = KeyMods {
+            ctrl: true,
+            ..Default::default()
+        };
+        assert_eq!(Some(Control::Pause), control(KeyCode::Space, ctrl));
+        assert_eq!(Some(Control::Reset), control(KeyCode::R, ctrl));
+        // keys without Ctrl are left to keyboard channels
+        assert_eq!(None, control(KeyCode::Space, KeyMods::default()));
+        assert_eq!(None, control(KeyCode::A, ctrl));
+    }
+}
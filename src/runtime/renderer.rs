@@ -0,0 +1,1500 @@
+use core::panic;
+use miniquad::TextureWrap;
+use std::cell::Cell;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::time::SystemTime;
+
+use chrono::{Datelike, Timelike};
+use image::{imageops, RgbaImage};
+use miniquad::*;
+
+use crate::toy::cubemap::CubeLayout;
+use crate::toy::shader::Fragment;
+use crate::toy::volume::Volume;
+use crate::toy::BufferName;
+use crate::toy::Channel;
+use crate::toy::ChannelError;
+use crate::toy::TextureFilter;
+use crate::toy::TextureWrap as ToyTextureWrap;
+use crate::toy::{shader, track::Track, video::Sequence, ChannelConfig, Toy};
+
+use super::keyboard::{self, Keyboard};
+use super::spectrum::{self, Analyser};
+
+#[repr(C)]
+struct Vec2 {
+    x: f32,
+    y: f32,
+}
+
+#[repr(C)]
+struct Vertex {
+    pos: Vec2,
+    uv: Vec2,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[allow(non_snake_case)]
+pub struct Uniforms {
+    pub iResolution: (f32, f32, f32),
+    pub iMouse: (f32, f32, f32, f32),
+    pub iTime: f32,
+    pub iTimeDelta: f32,
+    pub iFrame: i32,
+    pub iFrameRate: f32,
+    /// Playback time of each channel, in seconds
+    pub iChannelTime: [f32; 4],
+    /// Resolution of each channel, set for every pass from its bound textures
+    pub iChannelResolution: [(f32, f32, f32); 4],
+    /// Year, month (from 0), day (from 1) and seconds since midnight
+    pub iDate: (f32, f32, f32, f32),
+    pub iSampleRate: f32,
+    /// Position of the tile being rendered within the frame, added to
+    /// `fragCoord`, and zero unless a still is rendered in tiles
+    pub iTileOffset: (f32, f32),
+}
+
+impl Uniforms {
+    pub fn new(width: f32, height: f32) -> Self {
+        Uniforms {
+            iResolution: (width, height, 1.0),
+            iMouse: (0.0, 0.0, 0.0, 0.0),
+            iTime: 0.0,
+            iTimeDelta: 0.0,
+            iFrame: 0,
+            iFrameRate: 0.0,
+            iChannelTime: [0.0; 4],
+            iChannelResolution: [(0.0, 0.0, 0.0); 4],
+            iDate: (0.0, 0.0, 0.0, 0.0),
+            iSampleRate: 44100.0,
+            iTileOffset: (0.0, 0.0),
+        }
+    }
+
+    fn layout() -> UniformBlockLayout {
+        UniformBlockLayout {
+            uniforms: vec![
+                UniformDesc::new("iResolution", UniformType::Float3),
+                UniformDesc::new("iMouse", UniformType::Float4),
+                UniformDesc::new("iTime", UniformType::Float1),
+                UniformDesc::new("iTimeDelta", UniformType::Float1),
+                UniformDesc::new("iFrame", UniformType::Int1),
+                UniformDesc::new("iFrameRate", UniformType::Float1),
+                UniformDesc::new("iChannelTime", UniformType::Float1).array(4),
+                UniformDesc::new("iChannelResolution", UniformType::Float3).array(4),
+                UniformDesc::new("iDate", UniformType::Float4),
+                UniformDesc::new("iSampleRate", UniformType::Float1),
+                UniformDesc::new("iTileOffset", UniformType::Float2),
+            ],
+        }
+    }
+
+    /// Set `iDate` from a wall clock time
+    pub fn set_date<T: Datelike + Timelike>(&mut self, now: &T) {
+        let seconds = now.num_seconds_from_midnight() as f32 + now.nanosecond() as f32 * 1e-9;
+        self.iDate = (
+            now.year() as f32,
+            now.month0() as f32,
+            now.day() as f32,
+            seconds,
+        );
+    }
+}
+
+/// Uniforms of the sound pass, the uniforms of every pass along with where
+/// the block of samples being rendered starts
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[allow(non_snake_case)]
+struct SoundUniforms {
+    uniforms: Uniforms,
+    iTimeOffset: f32,
+    iSampleOffset: i32,
+}
+
+impl SoundUniforms {
+    fn layout() -> UniformBlockLayout {
+        let mut layout = Uniforms::layout();
+        layout.uniforms.extend([
+            UniformDesc::new("iTimeOffset", UniformType::Float1),
+            UniformDesc::new("iSampleOffset", UniformType::Int1),
+        ]);
+        layout
+    }
+}
+
+/// Uniforms of the Cube A pass, which renders one face of its cube map at a
+/// time
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[allow(non_snake_case)]
+struct CubeUniforms {
+    uniforms: Uniforms,
+    iCubeFace: i32,
+}
+
+impl CubeUniforms {
+    fn layout() -> UniformBlockLayout {
+        let mut layout = Uniforms::layout();
+        layout
+            .uniforms
+            .push(UniformDesc::new("iCubeFace", UniformType::Int1));
+        layout
+    }
+}
+
+/// Source of the texture bound to a pass channel
+#[derive(Debug, Clone, Copy)]
+enum ChannelSource {
+    Texture(TextureId),
+    Buffer(BufferName),
+    /// The output of the Cube A pass
+    Cube,
+    Volume(VolumeTexture),
+}
+
+/// A 3D texture, created and bound with raw OpenGL calls as miniquad only
+/// supports 2D textures and cube maps
+#[derive(Debug, Clone, Copy)]
+struct VolumeTexture {
+    raw: u32,
+    size: (u32, u32, u32),
+}
+
+impl VolumeTexture {
+    fn new(volume: &Volume) -> VolumeTexture {
+        let (internal_format, format) = match volume.channels {
+            1 => (gl::GL_R8, gl::GL_RED),
+            _ => (gl::GL_RGBA8, gl::GL_RGBA),
+        };
+        let mut raw = 0;
+        unsafe {
+            gl::glGenTextures(1, &mut raw);
+            gl::glActiveTexture(gl::GL_TEXTURE0);
+            gl::glBindTexture(gl::GL_TEXTURE_3D, raw);
+            gl::glPixelStorei(gl::GL_UNPACK_ALIGNMENT, 1);
+            gl::glTexImage3D(
+                gl::GL_TEXTURE_3D,
+                0,
+                internal_format as _,
+                volume.width as _,
+                volume.height as _,
+                volume.depth as _,
+                0,
+                format,
+                gl::GL_UNSIGNED_BYTE,
+                volume.data.as_ptr() as _,
+            );
+            for (param, value) in [
+                (gl::GL_TEXTURE_MIN_FILTER, gl::GL_LINEAR),
+                (gl::GL_TEXTURE_MAG_FILTER, gl::GL_LINEAR),
+                (gl::GL_TEXTURE_WRAP_S, gl::GL_REPEAT),
+                (gl::GL_TEXTURE_WRAP_T, gl::GL_REPEAT),
+                (gl::GL_TEXTURE_WRAP_R, gl::GL_REPEAT),
+            ] {
+                gl::glTexParameteri(gl::GL_TEXTURE_3D, param, value as _);
+            }
+            gl::glBindTexture(gl::GL_TEXTURE_3D, 0);
+        }
+        VolumeTexture {
+            raw,
+            size: (volume.width, volume.height, volume.depth),
+        }
+    }
+
+    /// Bind the texture to texture unit `unit`, alongside the 2D texture
+    /// miniquad bound there
+    fn bind(&self, unit: usize) {
+        unsafe {
+            gl::glActiveTexture(gl::GL_TEXTURE0 + unit as u32);
+            gl::glBindTexture(gl::GL_TEXTURE_3D, self.raw);
+        }
+    }
+
+    fn delete(&self) {
+        unsafe { gl::glDeleteTextures(1, &self.raw) };
+    }
+}
+
+/// A channel bound to a pass, along with the sampler state used to read it
+#[derive(Debug, Clone, Copy)]
+struct PassChannel {
+    source: ChannelSource,
+    filter: TextureFilter,
+    wrap: ToyTextureWrap,
+}
+
+/// Media decoded along with a channel texture
+enum Media {
+    /// An audio file, analysed into the texture before every draw
+    Track(Track),
+    /// A video, whose texture is replaced by the frame shown at the current
+    /// time before every draw
+    Video {
+        sequence: Sequence,
+        /// Frame currently held by the texture
+        frame: Cell<usize>,
+    },
+}
+
+/// A channel of a pass along with the definition it was loaded from, so that
+/// its texture can be kept when the toy is recompiled
+struct LoadedChannel {
+    channel: Channel,
+    /// Latest modification time of the channel's files when loaded
+    modified: Option<SystemTime>,
+    bound: PassChannel,
+    media: Option<Media>,
+}
+
+impl LoadedChannel {
+    /// Delete the texture created for the channel
+    fn delete(self, context: &mut dyn RenderingBackend) {
+        match self.bound.source {
+            // keyboard channels share the renderer's texture
+            ChannelSource::Texture(_) if self.channel.config == ChannelConfig::Keyboard => (),
+            ChannelSource::Texture(texture) => context.delete_texture(texture),
+            ChannelSource::Volume(volume) => volume.delete(),
+            ChannelSource::Buffer(_) | ChannelSource::Cube => (),
+        }
+    }
+}
+
+/// A compiled render pass
+struct Pass {
+    pipeline: Pipeline,
+    shader: ShaderId,
+    /// Fragment shader source the pipeline was compiled from
+    source: String,
+    channels: Vec<LoadedChannel>,
+}
+
+/// Passes of a toy, compiled or being compiled
+struct Passes<P = Pass> {
+    buffers: Vec<(BufferName, P)>,
+    image: Option<P>,
+    sound: Option<P>,
+    cube: Option<P>,
+}
+
+impl<P> Default for Passes<P> {
+    fn default() -> Self {
+        Passes {
+            buffers: vec![],
+            image: None,
+            sound: None,
+            cube: None,
+        }
+    }
+}
+
+impl<P> Passes<P> {
+    /// Passes in a fixed order, which indices of passes refer to
+    fn iter(&self) -> impl Iterator<Item = &P> {
+        let buffers = self.buffers.iter().map(|(_, pass)| pass);
+        buffers
+            .chain(&self.image)
+            .chain(&self.sound)
+            .chain(&self.cube)
+    }
+
+    fn into_vec(self) -> Vec<P> {
+        let buffers = self.buffers.into_iter().map(|(_, pass)| pass);
+        buffers
+            .chain(self.image)
+            .chain(self.sound)
+            .chain(self.cube)
+            .collect()
+    }
+
+    fn map<Q>(self, mut f: impl FnMut(P) -> Q) -> Passes<Q> {
+        Passes {
+            buffers: self
+                .buffers
+                .into_iter()
+                .map(|(buffer, pass)| (buffer, f(pass)))
+                .collect(),
+            image: self.image.map(&mut f),
+            sound: self.sound.map(&mut f),
+            cube: self.cube.map(&mut f),
+        }
+    }
+}
+
+/// Passes of a toy compiled together
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Stage {
+    /// Buffers, the main image and the Cube A pass, drawn every frame
+    Visual,
+    /// The sound pass alone, rendered into blocks of samples
+    Sound,
+}
+
+/// Part of a pass being compiled, either kept from the previous passes or
+/// newly created
+enum Planned<K, T> {
+    Kept(K),
+    New(T),
+}
+
+/// A pass being compiled, referring to the pipeline and channels it keeps by
+/// their index in the previous passes
+struct PassPlan {
+    pipeline: Planned<usize, (Pipeline, ShaderId)>,
+    source: String,
+    channels: Vec<Planned<(usize, usize), LoadedChannel>>,
+}
+
+impl PassPlan {
+    /// Delete the resources created for the pass
+    fn delete(self, context: &mut dyn RenderingBackend) {
+        if let Planned::New((pipeline, shader)) = self.pipeline {
+            context.delete_pipeline(pipeline);
+            context.delete_shader(shader);
+        }
+        delete_channels(context, self.channels);
+    }
+}
+
+/// Delete the channels created for a pass being compiled
+fn delete_channels(
+    context: &mut dyn RenderingBackend,
+    channels: Vec<Planned<(usize, usize), LoadedChannel>>,
+) {
+    for channel in channels {
+        if let Planned::New(channel) = channel {
+            channel.delete(context);
+        }
+    }
+}
+
+/// Pipelines and channels of the previous passes already kept by a pass
+#[derive(Default)]
+struct Kept {
+    pipelines: Vec<usize>,
+    channels: Vec<(usize, usize)>,
+}
+
+/// Ping-pong render targets holding the output of a buffer pass
+struct BufferTarget {
+    textures: [TextureId; 2],
+    passes: [RenderPass; 2],
+    /// Index of the target holding the most recent output
+    current: usize,
+}
+
+impl BufferTarget {
+    fn output(&self) -> TextureId {
+        self.textures[self.current]
+    }
+
+    fn delete(self, context: &mut dyn RenderingBackend) {
+        // deleting a render pass deletes its texture
+        for pass in self.passes {
+            context.delete_render_pass(pass);
+        }
+    }
+}
+
+/// Ping-pong cube maps holding the output of the Cube A pass, with a render
+/// pass per face
+struct CubeTarget {
+    textures: [TextureId; 2],
+    passes: [[RenderPass; 6]; 2],
+    /// Index of the cube map holding the most recent output
+    current: usize,
+}
+
+impl CubeTarget {
+    fn output(&self) -> TextureId {
+        self.textures[self.current]
+    }
+
+    fn delete(self, context: &mut dyn RenderingBackend) {
+        for pass in self.passes.into_iter().flatten() {
+            context.delete_render_pass(pass);
+        }
+    }
+}
+
+/// Render targets of the passes other channels can read
+#[derive(Default)]
+struct Targets {
+    buffers: BTreeMap<BufferName, BufferTarget>,
+    cube: Option<CubeTarget>,
+}
+
+fn filter_modes(filter: TextureFilter) -> (FilterMode, MipmapFilterMode) {
+    match filter {
+        TextureFilter::Mipmap => (FilterMode::Linear, MipmapFilterMode::Nearest),
+        TextureFilter::Linear => (FilterMode::Linear, MipmapFilterMode::None),
+        TextureFilter::Nearest => (FilterMode::Nearest, MipmapFilterMode::None),
+    }
+}
+
+fn wrap_mode(wrap: ToyTextureWrap) -> TextureWrap {
+    match wrap {
+        ToyTextureWrap::Repeat => TextureWrap::Repeat,
+        ToyTextureWrap::Clamp => TextureWrap::Clamp,
+    }
+}
+
+/// Error compiling the shader of a pass, with the driver's log referring to
+/// lines of the toy's files
+#[derive(Debug)]
+pub struct CompileError(pub String);
+
+impl std::fmt::Display for CompileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for CompileError {}
+
+/// Offscreen target the main image can be drawn into and read back from
+pub struct FrameTarget {
+    texture: TextureId,
+    pass: RenderPass,
+}
+
+/// Compiled passes of a toy and the GPU resources needed to draw them, shared
+/// by windowed and headless execution
+pub struct Renderer {
+    context: Box<dyn RenderingBackend>,
+    passes: Passes,
+    /// Target sound blocks are rendered to, created when first needed
+    sound_target: Option<FrameTarget>,
+    targets: Targets,
+    bindings: Bindings,
+    pub uniforms: Uniforms,
+    /// Key state read by keyboard channels
+    pub keyboard: Keyboard,
+    keyboard_texture: TextureId,
+    analyser: Analyser,
+}
+
+impl Renderer {
+    pub fn new(mut ctx: Box<dyn RenderingBackend>, width: f32, height: f32) -> Renderer {
+        #[rustfmt::skip]
+        let vertices: [Vertex; 4] = [
+            Vertex { pos : Vec2 { x: -1.0, y: -1.0 }, uv: Vec2 { x: 0., y: 0. } },
+            Vertex { pos : Vec2 { x:  1.0, y: -1.0 }, uv: Vec2 { x: 1., y: 0. } },
+            Vertex { pos : Vec2 { x:  1.0, y:  1.0 }, uv: Vec2 { x: 1., y: 1. } },
+            Vertex { pos : Vec2 { x: -1.0, y:  1.0 }, uv: Vec2 { x: 0., y: 1. } },
+        ];
+        let vertex_buffer = ctx.new_buffer(
+            BufferType::VertexBuffer,
+            BufferUsage::Immutable,
+            BufferSource::slice(&vertices),
+        );
+
+        let indices: [u16; 6] = [0, 1, 2, 0, 2, 3];
+        let index_buffer = ctx.new_buffer(
+            BufferType::IndexBuffer,
+            BufferUsage::Immutable,
+            BufferSource::slice(&indices),
+        );
+
+        let bindings = Bindings {
+            vertex_buffers: vec![vertex_buffer],
+            index_buffer,
+            images: vec![],
+        };
+
+        let keyboard_texture = ctx.new_texture(
+            TextureAccess::Static,
+            TextureSource::Empty,
+            TextureParams {
+                format: TextureFormat::RGBA8,
+                width: keyboard::WIDTH as _,
+                height: keyboard::ROWS as _,
+                min_filter: FilterMode::Nearest,
+                mag_filter: FilterMode::Nearest,
+                ..Default::default()
+            },
+        );
+
+        Renderer {
+            context: ctx,
+            passes: Passes::default(),
+            sound_target: None,
+            targets: Targets::default(),
+            bindings,
+            uniforms: Uniforms::new(width, height),
+            keyboard: Keyboard::default(),
+            keyboard_texture,
+            analyser: Analyser::default(),
+        }
+    }
+
+    pub fn context(&mut self) -> &mut dyn RenderingBackend {
+        self.context.as_mut()
+    }
+
+    /// Bindings of the full screen quad every pass is drawn with
+    pub fn quad_bindings(&self) -> Bindings {
+        Bindings {
+            vertex_buffers: self.bindings.vertex_buffers.clone(),
+            index_buffer: self.bindings.index_buffer,
+            images: vec![],
+        }
+    }
+
+    /// Compile the passes of a toy that are drawn, all but the sound pass,
+    /// keeping the current passes on error. Pipelines whose shader source is
+    /// unchanged, channels whose definition and files are unchanged and the
+    /// contents of buffers are kept from the previous passes, and resources no
+    /// longer used are deleted.
+    pub fn compile(&mut self, toy: &Toy) -> Result<(), Box<dyn std::error::Error + 'static>> {
+        self.compile_stage(toy, Stage::Visual)
+    }
+
+    /// Compile only the sound pass of a toy, for `render_sound`, replacing the
+    /// passes that are drawn. The other passes need not compile.
+    pub fn compile_sound(&mut self, toy: &Toy) -> Result<(), Box<dyn std::error::Error + 'static>> {
+        self.compile_stage(toy, Stage::Sound)
+    }
+
+    fn compile_stage(
+        &mut self,
+        toy: &Toy,
+        stage: Stage,
+    ) -> Result<(), Box<dyn std::error::Error + 'static>> {
+        toy.validate()?;
+
+        let old = std::mem::take(&mut self.passes);
+        let mut kept = Kept::default();
+        let mut plans = Passes::default();
+        if let Err(e) = self.plan_passes(toy, stage, &old, &mut kept, &mut plans) {
+            for plan in plans.into_vec() {
+                plan.delete(self.context.as_mut());
+            }
+            self.passes = old;
+            return Err(e);
+        }
+
+        // move what is kept into the new passes, and delete the rest
+        let mut old: Vec<_> = old
+            .into_vec()
+            .into_iter()
+            .map(|pass| {
+                let channels: Vec<_> = pass.channels.into_iter().map(Some).collect();
+                (Some((pass.pipeline, pass.shader)), channels)
+            })
+            .collect();
+        self.passes = plans.map(|plan| {
+            let (pipeline, shader) = match plan.pipeline {
+                Planned::Kept(pass) => old[pass].0.take().expect("pipeline kept once"),
+                Planned::New(pipeline) => pipeline,
+            };
+            let channels = plan
+                .channels
+                .into_iter()
+                .map(|channel| match channel {
+                    Planned::Kept((pass, index)) => {
+                        old[pass].1[index].take().expect("channel kept once")
+                    }
+                    Planned::New(channel) => channel,
+                })
+                .collect();
+            Pass {
+                pipeline,
+                shader,
+                source: plan.source,
+                channels,
+            }
+        });
+        for (pipeline, channels) in old {
+            if let Some((pipeline, shader)) = pipeline {
+                self.context.delete_pipeline(pipeline);
+                self.context.delete_shader(shader);
+            }
+            for channel in channels.into_iter().flatten() {
+                channel.delete(self.context.as_mut());
+            }
+        }
+
+        let mut buffers = std::mem::take(&mut self.targets.buffers);
+        for &buffer in toy.buffers.keys() {
+            let target = match buffers.remove(&buffer) {
+                Some(target) => target,
+                None => self.new_buffer_target(),
+            };
+            self.targets.buffers.insert(buffer, target);
+        }
+        for target in buffers.into_values() {
+            target.delete(self.context.as_mut());
+        }
+        // channels of the sound pass may read Cube A without it being drawn
+        self.targets.cube = match (toy.cube.is_some(), self.targets.cube.take()) {
+            (true, Some(target)) => Some(target),
+            (true, None) => Some(self.new_cube_target()),
+            (false, target) => {
+                if let Some(target) = target {
+                    target.delete(self.context.as_mut());
+                }
+                None
+            }
+        };
+        Ok(())
+    }
+
+    /// Plan the passes of a toy in `stage` into `plans`, keeping resources of
+    /// `old`
+    fn plan_passes(
+        &mut self,
+        toy: &Toy,
+        stage: Stage,
+        old: &Passes,
+        kept: &mut Kept,
+        plans: &mut Passes<PassPlan>,
+    ) -> Result<(), Box<dyn std::error::Error + 'static>> {
+        if stage == Stage::Sound {
+            if let Some(fragment) = toy.sound_shader() {
+                plans.sound = Some(self.plan_pass(
+                    &fragment?,
+                    &toy.config.sound.channels,
+                    toy,
+                    SoundUniforms::layout(),
+                    old,
+                    kept,
+                )?);
+            }
+            return Ok(());
+        }
+        for &buffer in toy.buffers.keys() {
+            let fragment = toy
+                .buffer_fragment_shader(buffer)
+                .expect("buffer is defined")?;
+            let channels = toy.config.buffer_channels(buffer);
+            let plan = self.plan_pass(&fragment, channels, toy, Uniforms::layout(), old, kept)?;
+            plans.buffers.push((buffer, plan));
+        }
+        plans.image = Some(self.plan_pass(
+            &toy.fragment_shader()?,
+            &toy.config.channels,
+            toy,
+            Uniforms::layout(),
+            old,
+            kept,
+        )?);
+        if let Some(fragment) = toy.cube_shader() {
+            plans.cube = Some(self.plan_pass(
+                &fragment?,
+                &toy.config.cube.channels,
+                toy,
+                CubeUniforms::layout(),
+                old,
+                kept,
+            )?);
+        }
+        Ok(())
+    }
+
+    /// Render buffers, the Cube A pass and then the main image into `target`,
+    /// or the window when `None`
+    pub fn draw(&mut self, target: Option<RenderPass>) {
+        if self.passes.image.is_none() {
+            return;
+        }
+        self.update_keyboard();
+        self.draw_passes();
+        self.draw_image(target);
+    }
+
+    /// Render the main image again from the current output of the buffers and
+    /// the Cube A pass, without advancing them, e.g. while time is paused
+    pub fn redraw(&mut self, target: Option<RenderPass>) {
+        if self.passes.image.is_none() {
+            return;
+        }
+        self.update_keyboard();
+        self.draw_image(target);
+    }
+
+    fn update_keyboard(&mut self) {
+        if let Some(data) = self.keyboard.take_update() {
+            self.context.texture_update(self.keyboard_texture, &data);
+        }
+    }
+
+    /// Render the buffers and the Cube A pass into their back targets
+    fn draw_passes(&mut self) {
+        let Renderer {
+            context,
+            passes: Passes { buffers, cube, .. },
+            targets,
+            bindings,
+            uniforms,
+            analyser,
+            ..
+        } = self;
+
+        // buffers render in order, each writing to its back target so that
+        // passes read this frame's output of earlier buffers and last frame's
+        // output of themselves and later buffers
+        for (buffer, pass) in buffers.iter() {
+            let target = &targets.buffers[buffer];
+            let next = 1 - target.current;
+            context.begin_pass(Some(target.passes[next]), PassAction::Nothing);
+            let uniforms = Self::apply_pass(
+                context.as_mut(),
+                bindings,
+                targets,
+                analyser,
+                uniforms,
+                pass,
+            );
+            context.apply_uniforms(UniformsSource::table(&uniforms));
+            context.draw(0, 6, 1);
+            context.end_render_pass();
+            targets
+                .buffers
+                .get_mut(buffer)
+                .expect("target exists")
+                .current = next;
+        }
+
+        // the cube pass renders each face at the face resolution, after the
+        // buffers and into its back cube map like them
+        if let (Some(pass), Some(target)) = (cube.as_ref(), targets.cube.as_ref()) {
+            let next = 1 - target.current;
+            let size = shader::CUBE_SIZE as f32;
+            let uniforms = Uniforms {
+                iResolution: (size, size, 1.0),
+                ..*uniforms
+            };
+            for (face, render_pass) in target.passes[next].iter().enumerate() {
+                context.begin_pass(Some(*render_pass), PassAction::Nothing);
+                let uniforms = CubeUniforms {
+                    uniforms: Self::apply_pass(
+                        context.as_mut(),
+                        bindings,
+                        targets,
+                        analyser,
+                        &uniforms,
+                        pass,
+                    ),
+                    iCubeFace: face as i32,
+                };
+                context.apply_uniforms(UniformsSource::table(&uniforms));
+                context.draw(0, 6, 1);
+                context.end_render_pass();
+            }
+            targets.cube.as_mut().expect("target exists").current = next;
+        }
+    }
+
+    /// Render the main image into `target`, or the window when `None`
+    fn draw_image(&mut self, target: Option<RenderPass>) {
+        let Renderer {
+            context,
+            passes: Passes { image, .. },
+            targets,
+            bindings,
+            uniforms,
+            keyboard,
+            analyser,
+            ..
+        } = self;
+        let image = image.as_ref().expect("image pass compiled");
+        context.begin_pass(target, Default::default());
+        let uniforms = Self::apply_pass(
+            context.as_mut(),
+            bindings,
+            targets,
+            analyser,
+            uniforms,
+            image,
+        );
+        context.apply_uniforms(UniformsSource::table(&uniforms));
+        context.draw(0, 6, 1);
+        context.end_render_pass();
+        keyboard.end_frame();
+    }
+
+    /// Create an RGBA8 target at the current resolution
+    pub fn new_frame_target(&mut self) -> FrameTarget {
+        let (w, h, _) = self.uniforms.iResolution;
+        self.new_target(w as _, h as _)
+    }
+
+    fn new_target(&mut self, width: u32, height: u32) -> FrameTarget {
+        let texture = self.context.new_render_texture(TextureParams {
+            format: TextureFormat::RGBA8,
+            width,
+            height,
+            ..Default::default()
+        });
+        let pass = self.context.new_render_pass(texture, None);
+        FrameTarget { texture, pass }
+    }
+
+    /// Render a block of `SOUND_BLOCK * SOUND_BLOCK` stereo samples of the
+    /// sound pass starting at sample `offset`, or `None` without a sound pass
+    pub fn render_sound(&mut self, offset: u32, sample_rate: f32) -> Option<Vec<[i16; 2]>> {
+        self.passes.sound.as_ref()?;
+        let target = match self.sound_target.take() {
+            Some(target) => target,
+            None => self.new_target(shader::SOUND_BLOCK, shader::SOUND_BLOCK),
+        };
+        let time_offset = (offset as f64 / sample_rate as f64) as f32;
+        let block = shader::SOUND_BLOCK as f32;
+        let uniforms = Uniforms {
+            iResolution: (block, block, 1.0),
+            iTime: time_offset,
+            iSampleRate: sample_rate,
+            ..self.uniforms
+        };
+
+        let sound = self.passes.sound.as_ref().expect("sound pass compiled");
+        self.context
+            .begin_pass(Some(target.pass), PassAction::Nothing);
+        let uniforms = SoundUniforms {
+            uniforms: Self::apply_pass(
+                self.context.as_mut(),
+                &mut self.bindings,
+                &self.targets,
+                &self.analyser,
+                &uniforms,
+                sound,
+            ),
+            iTimeOffset: time_offset,
+            iSampleOffset: offset as i32,
+        };
+        self.context
+            .apply_uniforms(UniformsSource::table(&uniforms));
+        self.context.draw(0, 6, 1);
+        self.context.end_render_pass();
+
+        let mut bytes = vec![0; (shader::SOUND_BLOCK * shader::SOUND_BLOCK * 4) as usize];
+        self.context.texture_read_pixels(target.texture, &mut bytes);
+        self.sound_target = Some(target);
+
+        // rows of the block are in sample order, as GL rows start at the bottom
+        let decode = |lo: u8, hi: u8| (u16::from_le_bytes([lo, hi]) ^ 0x8000) as i16;
+        Some(
+            bytes
+                .chunks_exact(4)
+                .map(|p| [decode(p[0], p[1]), decode(p[2], p[3])])
+                .collect(),
+        )
+    }
+
+    /// Draw a frame into `target` and read it back
+    pub fn capture(&mut self, target: &FrameTarget) -> RgbaImage {
+        self.draw(Some(target.pass));
+        self.read_target(target)
+    }
+
+    /// Delete a target created with `new_frame_target`
+    pub fn delete_frame_target(&mut self, target: FrameTarget) {
+        // deleting a render pass deletes its texture
+        self.context.delete_render_pass(target.pass);
+    }
+
+    /// Whether the main image reads passes drawn before it, buffers or the
+    /// Cube A pass, which `render_still` does not render again
+    pub fn has_offscreen_passes(&self) -> bool {
+        !self.passes.buffers.is_empty() || self.passes.cube.is_some()
+    }
+
+    /// Render the main image of the current frame again at another size, from
+    /// the current output of the buffers and the Cube A pass, in tiles of at
+    /// most `tile` pixels a side. `iMouse` is scaled along with the frame.
+    pub fn render_still(&mut self, width: u32, height: u32, tile: u32) -> RgbaImage {
+        let frame = self.uniforms;
+        let (w, h, _) = frame.iResolution;
+        let (sx, sy) = (width as f32 / w, height as f32 / h);
+        let (x, y, z, w) = frame.iMouse;
+        self.uniforms.iResolution = (width as f32, height as f32, 1.0);
+        self.uniforms.iMouse = (x * sx, y * sy, z * sx, w * sy);
+
+        let mut still = RgbaImage::new(width, height);
+        for y in (0..height).step_by(tile as usize) {
+            for x in (0..width).step_by(tile as usize) {
+                let target = self.new_target(tile.min(width - x), tile.min(height - y));
+                self.uniforms.iTileOffset = (x as f32, y as f32);
+                self.redraw(Some(target.pass));
+                let image = self.read_target(&target);
+                // tiles are offset from the bottom, where GL rows start
+                let top = height - y - image.height();
+                imageops::replace(&mut still, &image, x as i64, top as i64);
+                self.context.delete_render_pass(target.pass);
+            }
+        }
+        self.uniforms = frame;
+        still
+    }
+
+    fn read_target(&mut self, target: &FrameTarget) -> RgbaImage {
+        let (w, h) = self.context.texture_size(target.texture);
+        let mut bytes = vec![0; (w * h * 4) as usize];
+        self.context.texture_read_pixels(target.texture, &mut bytes);
+        let image = RgbaImage::from_raw(w, h, bytes).expect("buffer matches texture size");
+        // GL rows start at the bottom of the image
+        imageops::flip_vertical(&image)
+    }
+
+    /// Change the render resolution, discarding buffer contents
+    pub fn resize(&mut self, width: f32, height: f32) {
+        self.uniforms.iResolution = (width, height, 1.0);
+
+        for target in self.targets.buffers.values() {
+            for (tex, pass) in target.textures.iter().zip(target.passes) {
+                self.context
+                    .texture_resize(*tex, width as _, height as _, None);
+                self.context
+                    .begin_pass(Some(pass), PassAction::clear_color(0.0, 0.0, 0.0, 0.0));
+                self.context.end_render_pass();
+            }
+        }
+    }
+
+    /// Create the texture of a channel, along with the media of audio and
+    /// video channels
+    fn add_channel(
+        &mut self,
+        channel: &Channel,
+        root: &Path,
+    ) -> Result<(PassChannel, Option<Media>), ChannelError> {
+        match channel.config {
+            ChannelConfig::Texture {
+                vflip,
+                filter,
+                wrap,
+            } => {
+                // buffer outputs are shared between passes, so their sampler
+                // state is applied when binding rather than here
+                if let Some(buffer) = channel.buffer {
+                    let channel = PassChannel {
+                        source: ChannelSource::Buffer(buffer),
+                        filter,
+                        wrap,
+                    };
+                    return Ok((channel, None));
+                }
+
+                let mut im = channel.load_image(root)?;
+                if vflip {
+                    im = im.flipv();
+                }
+                let image = im.into_rgba8();
+                let tex_id = self.context.new_texture_from_rgba8(
+                    image.width() as _,
+                    image.height() as _,
+                    image.into_raw().as_slice(),
+                );
+                let (min_mag, mipmap) = filter_modes(filter);
+                self.context.texture_set_filter(tex_id, min_mag, mipmap);
+                self.context
+                    .texture_set_wrap(tex_id, wrap_mode(wrap), wrap_mode(wrap));
+                let channel = PassChannel {
+                    source: ChannelSource::Texture(tex_id),
+                    filter,
+                    wrap,
+                };
+                Ok((channel, None))
+            }
+            ChannelConfig::Keyboard => {
+                let channel = PassChannel {
+                    source: ChannelSource::Texture(self.keyboard_texture),
+                    filter: TextureFilter::Nearest,
+                    wrap: ToyTextureWrap::Clamp,
+                };
+                Ok((channel, None))
+            }
+            ChannelConfig::Audio { .. } => {
+                let track = channel.load_audio(root)?;
+                let tex_id = self.context.new_texture(
+                    TextureAccess::Static,
+                    TextureSource::Empty,
+                    TextureParams {
+                        format: TextureFormat::RGBA8,
+                        width: spectrum::WIDTH as _,
+                        height: spectrum::ROWS as _,
+                        min_filter: FilterMode::Linear,
+                        mag_filter: FilterMode::Linear,
+                        ..Default::default()
+                    },
+                );
+                let channel = PassChannel {
+                    source: ChannelSource::Texture(tex_id),
+                    filter: TextureFilter::Linear,
+                    wrap: ToyTextureWrap::Clamp,
+                };
+                Ok((channel, Some(Media::Track(track))))
+            }
+            ChannelConfig::Video { .. } => {
+                let sequence = channel.load_video(root)?;
+                let image = sequence
+                    .load(0)
+                    .map_err(ChannelError::Image)?
+                    .flipv()
+                    .into_rgba8();
+                let tex_id = self.context.new_texture_from_rgba8(
+                    image.width() as _,
+                    image.height() as _,
+                    image.into_raw().as_slice(),
+                );
+                self.context
+                    .texture_set_filter(tex_id, FilterMode::Linear, MipmapFilterMode::None);
+                let channel = PassChannel {
+                    source: ChannelSource::Texture(tex_id),
+                    filter: TextureFilter::Linear,
+                    wrap: ToyTextureWrap::Clamp,
+                };
+                let video = Media::Video {
+                    sequence,
+                    frame: Cell::new(0),
+                };
+                Ok((channel, Some(video)))
+            }
+            ChannelConfig::Cubemap {
+                layout: CubeLayout::Pass,
+            } => {
+                let channel = PassChannel {
+                    source: ChannelSource::Cube,
+                    filter: TextureFilter::Linear,
+                    wrap: ToyTextureWrap::Clamp,
+                };
+                Ok((channel, None))
+            }
+            ChannelConfig::Volume => {
+                let volume = channel.load_volume(root)?;
+                let channel = PassChannel {
+                    source: ChannelSource::Volume(VolumeTexture::new(&volume)),
+                    filter: TextureFilter::Linear,
+                    wrap: ToyTextureWrap::Repeat,
+                };
+                Ok((channel, None))
+            }
+            ChannelConfig::Cubemap { .. } => {
+                // faces are stored top row first, as cube maps expect
+                let faces = channel.load_cubemap(root)?;
+                let size = faces[0].width();
+                let data = faces.each_ref().map(|face| [face.as_raw().as_slice()]);
+                let tex_id = self.context.new_texture(
+                    TextureAccess::Static,
+                    TextureSource::Array(&data.each_ref().map(|face| face.as_slice())),
+                    TextureParams {
+                        kind: TextureKind::CubeMap,
+                        format: TextureFormat::RGBA8,
+                        width: size,
+                        height: size,
+                        min_filter: FilterMode::Linear,
+                        mag_filter: FilterMode::Linear,
+                        ..Default::default()
+                    },
+                );
+                let channel = PassChannel {
+                    source: ChannelSource::Texture(tex_id),
+                    filter: TextureFilter::Linear,
+                    wrap: ToyTextureWrap::Clamp,
+                };
+                Ok((channel, None))
+            }
+        }
+    }
+
+    /// Plan a pass, keeping a pipeline compiled from the same source and
+    /// channels with the same definition and files from `old` when they are
+    /// not already kept by another pass
+    fn plan_pass(
+        &mut self,
+        fragment: &Fragment,
+        channels: &[Channel],
+        toy: &Toy,
+        uniforms: UniformBlockLayout,
+        old: &Passes,
+        kept: &mut Kept,
+    ) -> Result<PassPlan, Box<dyn std::error::Error + 'static>> {
+        let mut planned = vec![];
+        for channel in channels {
+            let modified = channel.modified(&toy.root);
+            let found = old
+                .iter()
+                .enumerate()
+                .flat_map(|(p, pass)| {
+                    pass.channels
+                        .iter()
+                        .enumerate()
+                        .map(move |(c, l)| ((p, c), l))
+                })
+                .find(|(key, loaded)| {
+                    !kept.channels.contains(key)
+                        && loaded.channel == *channel
+                        && loaded.modified == modified
+                });
+            if let Some((key, _)) = found {
+                kept.channels.push(key);
+                planned.push(Planned::Kept(key));
+                continue;
+            }
+            match self.add_channel(channel, &toy.root) {
+                Ok((bound, media)) => planned.push(Planned::New(LoadedChannel {
+                    channel: channel.clone(),
+                    modified,
+                    bound,
+                    media,
+                })),
+                Err(e) => {
+                    delete_channels(self.context.as_mut(), planned);
+                    return Err(Box::new(e));
+                }
+            }
+        }
+
+        // channels are bound by index, so the pipeline is only kept for the
+        // same number of channels
+        let found = old.iter().enumerate().position(|(p, pass)| {
+            !kept.pipelines.contains(&p)
+                && pass.source == fragment.source
+                && pass.channels.len() == channels.len()
+        });
+        let pipeline = match found {
+            Some(pass) => {
+                kept.pipelines.push(pass);
+                Planned::Kept(pass)
+            }
+            None => match self.compile_pipeline(fragment, channels.len(), uniforms) {
+                Ok(pipeline) => Planned::New(pipeline),
+                Err(e) => {
+                    delete_channels(self.context.as_mut(), planned);
+                    return Err(e);
+                }
+            },
+        };
+        Ok(PassPlan {
+            pipeline,
+            source: fragment.source.clone(),
+            channels: planned,
+        })
+    }
+
+    fn compile_pipeline(
+        &mut self,
+        fragment: &Fragment,
+        channels: usize,
+        uniforms: UniformBlockLayout,
+    ) -> Result<(Pipeline, ShaderId), Box<dyn std::error::Error + 'static>> {
+        let meta = ShaderMeta {
+            images: (0..channels).map(|i| format!("iChannel{i}")).collect(),
+            uniforms,
+        };
+
+        let shader = self.context.new_shader(
+            match self.context.info().backend {
+                Backend::OpenGl => ShaderSource::Glsl {
+                    vertex: shader::VERTEX,
+                    fragment: fragment.source.as_str(),
+                },
+                Backend::Metal => panic!("Metal not supported"),
+            },
+            meta,
+        );
+        let shader = match shader {
+            Ok(shader) => shader,
+            Err(ShaderError::CompilationError { error_message, .. })
+            | Err(ShaderError::LinkError(error_message)) => {
+                return Err(Box::new(CompileError(
+                    fragment.map.remap_log(&error_message),
+                )))
+            }
+            Err(e) => return Err(Box::new(e)),
+        };
+        let pipeline = self.context.new_pipeline(
+            &[BufferLayout::default()],
+            &[
+                VertexAttribute::new("in_pos", VertexFormat::Float2),
+                VertexAttribute::new("in_uv", VertexFormat::Float2),
+            ],
+            shader,
+            PipelineParams::default(),
+        );
+        Ok((pipeline, shader))
+    }
+
+    fn new_buffer_target(&mut self) -> BufferTarget {
+        let (w, h, _) = self.uniforms.iResolution;
+        let params = TextureParams {
+            format: TextureFormat::RGBA16F,
+            width: w as _,
+            height: h as _,
+            ..Default::default()
+        };
+        let textures = [
+            self.context.new_render_texture(params),
+            self.context.new_render_texture(params),
+        ];
+        let passes = textures.map(|tex| self.context.new_render_pass(tex, None));
+        for pass in passes {
+            self.context
+                .begin_pass(Some(pass), PassAction::clear_color(0.0, 0.0, 0.0, 0.0));
+            self.context.end_render_pass();
+        }
+        BufferTarget {
+            textures,
+            passes,
+            current: 0,
+        }
+    }
+
+    fn new_cube_target(&mut self) -> CubeTarget {
+        // RGBA16F data is uploaded as 32 bit floats
+        let zeros = vec![0; (shader::CUBE_SIZE * shader::CUBE_SIZE * 16) as usize];
+        let face: &[&[u8]] = &[&zeros];
+        let textures = [(); 2].map(|_| {
+            self.context.new_texture(
+                TextureAccess::RenderTarget,
+                TextureSource::Array(&[face; 6]),
+                TextureParams {
+                    kind: TextureKind::CubeMap,
+                    format: TextureFormat::RGBA16F,
+                    width: shader::CUBE_SIZE,
+                    height: shader::CUBE_SIZE,
+                    min_filter: FilterMode::Linear,
+                    mag_filter: FilterMode::Linear,
+                    ..Default::default()
+                },
+            )
+        });
+        let passes = textures
+            .map(|texture| [0, 1, 2, 3, 4, 5].map(|face| self.new_cube_face_pass(texture, face)));
+        CubeTarget {
+            textures,
+            passes,
+            current: 0,
+        }
+    }
+
+    /// Create a render pass drawing into `face` of a cube map. miniquad only
+    /// attaches 2D textures to render passes, so the face is attached to the
+    /// pass's framebuffer directly.
+    fn new_cube_face_pass(&mut self, texture: TextureId, face: u32) -> RenderPass {
+        let pass = self.context.new_render_pass(texture, None);
+        #[allow(irrefutable_let_patterns)]
+        let RawId::OpenGl(raw) = (unsafe { self.context.texture_raw_id(texture) }) else {
+            panic!("Metal not supported");
+        };
+        self.context.begin_pass(Some(pass), PassAction::Nothing);
+        unsafe {
+            gl::glFramebufferTexture2D(
+                gl::GL_FRAMEBUFFER,
+                gl::GL_COLOR_ATTACHMENT0,
+                gl::GL_TEXTURE_CUBE_MAP_POSITIVE_X + face,
+                raw,
+                0,
+            );
+        }
+        self.context.end_render_pass();
+        pass
+    }
+
+    /// Set the textures of `bindings` to the channels of a pass, returning the
+    /// resolution of each channel
+    fn bind_channels(
+        context: &mut dyn RenderingBackend,
+        bindings: &mut Bindings,
+        targets: &Targets,
+        pass: &Pass,
+    ) -> Vec<(f32, f32, f32)> {
+        bindings.images = pass
+            .channels
+            .iter()
+            .map(|c| match c.bound.source {
+                ChannelSource::Texture(tex_id) => tex_id,
+                ChannelSource::Cube => targets.cube.as_ref().expect("cube pass exists").output(),
+                // the default 2D texture fills the unit until the volume is
+                // bound next to it by `bind_volumes`
+                ChannelSource::Volume(_) => TextureId::from_raw_id(RawId::OpenGl(0)),
+                ChannelSource::Buffer(buffer) => {
+                    let tex_id = targets.buffers[&buffer].output();
+                    // render targets have no mipmaps, so fall back to linear
+                    let (min_mag, mipmap) = match c.bound.filter {
+                        TextureFilter::Mipmap => filter_modes(TextureFilter::Linear),
+                        filter => filter_modes(filter),
+                    };
+                    context.texture_set_filter(tex_id, min_mag, mipmap);
+                    let wrap = wrap_mode(c.bound.wrap);
+                    context.texture_set_wrap(tex_id, wrap, wrap);
+                    tex_id
+                }
+            })
+            .collect();
+        bindings
+            .images
+            .iter()
+            .zip(&pass.channels)
+            .map(|(&tex_id, c)| match c.bound.source {
+                ChannelSource::Volume(volume) => {
+                    let (w, h, d) = volume.size;
+                    (w as f32, h as f32, d as f32)
+                }
+                _ => {
+                    let (w, h) = context.texture_size(tex_id);
+                    (w as f32, h as f32, 1.0)
+                }
+            })
+            .collect()
+    }
+
+    /// Bind the volumes of a pass, once miniquad has applied its bindings
+    fn bind_volumes(pass: &Pass) {
+        for (unit, channel) in pass.channels.iter().enumerate() {
+            if let ChannelSource::Volume(volume) = channel.bound.source {
+                volume.bind(unit);
+            }
+        }
+    }
+
+    /// Upload the audio and video textures of a pass at `time`, returning the
+    /// playback position of each along with its channel
+    fn update_media(
+        context: &mut dyn RenderingBackend,
+        analyser: &Analyser,
+        pass: &Pass,
+        time: f32,
+    ) -> Vec<(usize, f32)> {
+        let mut positions = vec![];
+        for (unit, channel) in pass.channels.iter().enumerate() {
+            let (Some(media), ChannelSource::Texture(texture)) =
+                (&channel.media, channel.bound.source)
+            else {
+                continue;
+            };
+            match media {
+                Media::Track(track) => {
+                    let position = track.position(time);
+                    context.texture_update(texture, &analyser.texture(track, position));
+                    positions.push((unit, position));
+                }
+                Media::Video { sequence, frame } => {
+                    let shown = sequence.frame(time).expect("videos have frames");
+                    if shown != frame.get() {
+                        // a frame that fails to decode leaves the previous one shown
+                        match sequence.load(shown) {
+                            Ok(image) => {
+                                let image = image.flipv().into_rgba8();
+                                let (w, h) = image.dimensions();
+                                context.texture_resize(texture, w, h, Some(image.as_raw()));
+                            }
+                            Err(e) => log::error!("Error decoding video frame {}: {}", shown, e),
+                        }
+                        frame.set(shown);
+                    }
+                    positions.push((unit, sequence.position(time)));
+                }
+            }
+        }
+        positions
+    }
+
+    /// Bind the pipeline and channels of a pass within the current render
+    /// pass, returning its uniforms with the state of its channels set
+    fn apply_pass(
+        context: &mut dyn RenderingBackend,
+        bindings: &mut Bindings,
+        targets: &Targets,
+        analyser: &Analyser,
+        uniforms: &Uniforms,
+        pass: &Pass,
+    ) -> Uniforms {
+        let mut uniforms = *uniforms;
+        for (channel, position) in Self::update_media(context, analyser, pass, uniforms.iTime) {
+            uniforms.iChannelTime[channel] = position;
+        }
+        let resolutions = Self::bind_channels(context, bindings, targets, pass);
+        for (uniform, resolution) in uniforms.iChannelResolution.iter_mut().zip(resolutions) {
+            *uniform = resolution;
+        }
+
+        context.apply_pipeline(&pass.pipeline);
+        context.apply_bindings(bindings);
+        Self::bind_volumes(pass);
+        uniforms
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::render::render_frames;
+    use crate::render::test::{feedback_toy, headless, options};
+
+    #[test]
+    fn recompile_keeps_buffers() {
+        let Some(headless) = headless() else {
+            return;
+        };
+        let pixel = |renderer: &mut Renderer, toy: &Toy| {
+            let mut pixel = [0; 4];
+            render_frames(renderer, toy, &options(1), |_, im| {
+                pixel = im.get_pixel(4, 4).0;
+                Ok(())
+            })
+            .map(|_| pixel)
+        };
+        let mut toy = feedback_toy();
+        let mut renderer = headless.renderer(32, 16);
+        assert!(pixel(&mut renderer, &toy).unwrap()[0].abs_diff(26) <= 1);
+
+        // changing the image shader keeps accumulating in the buffer
+        toy.main_image = "void mainImage(out vec4 c, in vec2 p) {
+            c = vec4(texelFetch(iChannel0, ivec2(p), 0).rrr, 1.0);
+        }"
+        .into();
+        let value = pixel(&mut renderer, &toy).unwrap();
+        assert!(value[0].abs_diff(51) <= 1 && value[2].abs_diff(51) <= 1);
+
+        // a compile error keeps the previous passes and their buffers
+        let broken = Toy {
+            main_image: "void mainImage(out vec4 c, in vec2 p) { c = nope; }".into(),
+            ..feedback_toy()
+        };
+        assert!(pixel(&mut renderer, &broken).is_err());
+        let value = pixel(&mut renderer, &toy).unwrap();
+        assert!(value[0].abs_diff(77) <= 1 && value[2].abs_diff(77) <= 1);
+
+        // a buffer removed and added back starts from scratch
+        pixel(&mut renderer, &Toy::default()).unwrap();
+        assert!(pixel(&mut renderer, &toy).unwrap()[0].abs_diff(26) <= 1);
+    }
+
+    #[test]
+    fn render_tiled_still() {
+        let Some(headless) = headless() else {
+            return;
+        };
+        let toy = Toy {
+            main_image: "void mainImage(out vec4 c, in vec2 p) {
+                c = vec4(texelFetch(iChannel0, ivec2(0), 0).r, p.x / iResolution.x,
+                         iMouse.x / iResolution.x, p.y / iResolution.y);
+            }"
+            .into(),
+            ..feedback_toy()
+        };
+        let mut renderer = headless.renderer(32, 16);
+        renderer.compile(&toy).unwrap();
+        assert!(renderer.has_offscreen_passes());
+        renderer.uniforms.iMouse = (8.0, 4.0, 8.0, 4.0);
+        let target = renderer.new_frame_target();
+        renderer.capture(&target);
+
+        let still = renderer.render_still(64, 32, 64);
+        assert_eq!((64, 32), still.dimensions());
+        assert_eq!(still, renderer.render_still(64, 32, 24));
+        assert_eq!((32.0, 16.0, 1.0), renderer.uniforms.iResolution);
+
+        // buffers are not advanced, the mouse keeps its relative position and
+        // the top row is the top of the frame
+        let close = |a: u8, b: u8| a.abs_diff(b) <= 1;
+        let pixel = still.get_pixel(48, 0).0;
+        assert!(close(pixel[0], 26) && close(pixel[1], 193), "{:?}", pixel);
+        assert!(close(pixel[2], 64) && close(pixel[3], 251), "{:?}", pixel);
+        assert!(close(still.get_pixel(48, 31)[3], 4));
+    }
+}
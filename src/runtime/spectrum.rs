@@ -0,0 +1,110 @@
+use std::f32::consts::PI;
+use std::sync::Arc;
+
+use rustfft::num_complex::Complex;
+use rustfft::{Fft, FftPlanner};
+
+use crate::toy::track::Track;
+
+/// Width of the audio texture, one texel per frequency bin and per sample
+pub const WIDTH: usize = 512;
+
+/// Rows of the audio texture: the spectrum and the waveform
+pub const ROWS: usize = 2;
+
+/// Samples analysed for the spectrum, giving `WIDTH` frequency bins
+const FFT_SIZE: usize = 2 * WIDTH;
+
+/// Range of decibels mapped to the values of the spectrum
+const MIN_DECIBELS: f32 = -100.0;
+const MAX_DECIBELS: f32 = -30.0;
+
+/// Computes the audio texture of a track like the Web Audio analyser used by
+/// Shadertoy, without smoothing between frames so that a frame only depends on
+/// the playback position
+pub struct Analyser {
+    fft: Arc<dyn Fft<f32>>,
+    /// Blackman window applied before the transform
+    window: Vec<f32>,
+}
+
+impl Default for Analyser {
+    fn default() -> Self {
+        let window = (0..FFT_SIZE)
+            .map(|i| {
+                let x = 2.0 * PI * i as f32 / FFT_SIZE as f32;
+                0.42 - 0.5 * x.cos() + 0.08 * (2.0 * x).cos()
+            })
+            .collect();
+        Analyser {
+            fft: FftPlanner::new().plan_fft_forward(FFT_SIZE),
+            window,
+        }
+    }
+}
+
+impl Analyser {
+    /// RGBA texture data of the spectrum and waveform of the samples preceding
+    /// `position` in `track`
+    pub fn texture(&self, track: &Track, position: f32) -> Vec<u8> {
+        let samples = track.window(position, FFT_SIZE);
+
+        let mut buffer: Vec<Complex<f32>> = samples
+            .iter()
+            .zip(&self.window)
+            .map(|(s, w)| Complex::new(s * w, 0.0))
+            .collect();
+        self.fft.process(&mut buffer);
+        let spectrum = buffer[..WIDTH].iter().map(|c| {
+            let decibels = 20.0 * (c.norm() / FFT_SIZE as f32).log10();
+            let value = (decibels - MIN_DECIBELS) / (MAX_DECIBELS - MIN_DECIBELS);
+            (value * 255.0).clamp(0.0, 255.0) as u8
+        });
+        let waveform = samples[FFT_SIZE - WIDTH..]
+            .iter()
+            .map(|s| (128.0 * (1.0 + s)).clamp(0.0, 255.0) as u8);
+
+        spectrum
+            .chain(waveform)
+            .flat_map(|v| [v, v, v, 255])
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn spectrum_and_waveform() {
+        // a sine wave completing 64 periods over the analysed samples
+        let sample_rate = 8192;
+        let frequency = 64.0 * sample_rate as f32 / FFT_SIZE as f32;
+        let track = Track {
+            sample_rate,
+            samples: (0..sample_rate)
+                .map(|i| 0.01 * (2.0 * PI * frequency * i as f32 / sample_rate as f32).sin())
+                .collect(),
+        };
+        let analyser = Analyser::default();
+        let data = analyser.texture(&track, 0.5);
+        assert_eq!(WIDTH * ROWS * 4, data.len());
+        let texel = |x, y| texel_of(&data, x, y);
+
+        let peak = (0..WIDTH).max_by_key(|&x| texel(x, 0)).unwrap();
+        assert_eq!(64, peak);
+        assert!(texel(64, 0) > 150);
+        assert_eq!(0, texel(200, 0));
+
+        // the waveform holds the last samples, with silence at 128
+        let last = track.samples[sample_rate as usize / 2 - 1];
+        assert_eq!((128.0 * (1.0 + last)) as u8, texel(WIDTH - 1, 1));
+        let silence = analyser.texture(&track, 0.0);
+        assert!((0..WIDTH).all(|x| texel_of(&silence, x, 0) == 0));
+        assert!((0..WIDTH).all(|x| texel_of(&silence, x, 1) == 128));
+    }
+
+    fn texel_of(data: &[u8], x: usize, y: usize) -> u8 {
+        data[(y * WIDTH + x) * 4]
+    }
+}
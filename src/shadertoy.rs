@@ -0,0 +1,993 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::{error, fs, io};
+
+use serde::{Deserialize, Serialize};
+
+use crate::toy::include::{self, IncludeError};
+use crate::toy::{
+    shader, BufferName, BuiltinName, Channel, ChannelConfig, ChannelError, PassConfig,
+    TextureFilter, TextureWrap, Toy,
+};
+
+/// Shadertoy media, identified by the file name of their `src`. File names are
+/// the hashes Shadertoy serves its media under, as listed on
+/// https://shadertoyunofficial.wordpress.com/2019/07/23/shadertoy-media-files/
+/// and not hashes of the built-ins standing in for them
+const MEDIA: [(&str, BuiltinName); 11] = [
+    // 64x64 RGBA noise
+    (
+        "f735bee5b64ef98879dc618b016ecf7939a5756040c2cde21ccb15e69a6e1cfb.png",
+        BuiltinName::RgbaNoiseSmall,
+    ),
+    // 256x256 RGBA noise
+    (
+        "3083c722c0c738cad0f468383167a0d246f91af2bfa373e9c5c094fb8c8413e0.png",
+        BuiltinName::RgbaNoiseMedium,
+    ),
+    // 64x64 gray noise
+    (
+        "0a40562379b63dfb89227e6d172f39fdce9022cba76623f1054a2c83d6c0ba5d.png",
+        BuiltinName::GrayNoiseSmall,
+    ),
+    // 256x256 gray noise
+    (
+        "0c7bf5fe9462d5bffbd11126e82908e39be3ce56220d900f633d58fb432e56f5.png",
+        BuiltinName::GrayNoiseMedium,
+    ),
+    (
+        "85a6d68622b36995ccb98a89bbb119edf167c914660e4450d313de049320005c.png",
+        BuiltinName::Bayer,
+    ),
+    (
+        "08b42b43ae9d3c0605da11d0eac86618ea888e62cdd9518ee8b9097488b31560.png",
+        BuiltinName::Font1,
+    ),
+    (
+        "27012b4eadd0c3ce12498b867058e4f717ce79e10a99568cca461682d84a4b04.bin",
+        BuiltinName::GreyNoise3D,
+    ),
+    (
+        "aea6b99da1d53055107966b59ac5444fc8bc7b3ce2d0bbb6a4a3cbae1d97f3aa.bin",
+        BuiltinName::RgbaNoise3D,
+    ),
+    // photos, read from the asset directory
+    (
+        "1f7dca9c22f324751f2a5a59c9b181dfe3b5564a04b724c657732d0bf09c99db.jpg",
+        BuiltinName::Organic1,
+    ),
+    (
+        "8de3a3924cb95bd0e95a443fff0326c869f9d4979cd1d5b6e94e2a01f5be53e9.jpg",
+        BuiltinName::Abstract1,
+    ),
+    (
+        "95b90082f799f48677b4f206d856ad572f1d178c676269eac6347631d4447258.jpg",
+        BuiltinName::London,
+    ),
+];
+
+/// Input id of Shadertoy's keyboard
+const KEYBOARD_ID: &str = "4dXGRr";
+
+/// Output id of the image pass
+const IMAGE_ID: &str = "4dfGRr";
+
+/// Output ids of Shadertoy's buffer passes, in both the current and the older
+/// numeric form
+const BUFFER_IDS: [(BufferName, &str, u64); 4] = [
+    (BufferName::A, "4dXGR8", 257),
+    (BufferName::B, "XsXGR8", 258),
+    (BufferName::C, "4sXGR8", 259),
+    (BufferName::D, "XdfGR8", 260),
+];
+
+/// Identifier of a pass output, a string in current exports and a number in
+/// older ones
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+#[serde(untagged)]
+pub enum Id {
+    Number(u64),
+    Text(String),
+}
+
+impl Id {
+    /// Buffer pass writing the output with this id
+    fn buffer(&self) -> Option<BufferName> {
+        BUFFER_IDS
+            .iter()
+            .find(|(_, text, number)| match self {
+                Id::Number(n) => n == number,
+                Id::Text(t) => t == text,
+            })
+            .map(|(buffer, _, _)| *buffer)
+    }
+}
+
+/// Boolean sampler setting, stored as `"true"`/`"false"` by Shadertoy
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+#[serde(untagged)]
+pub enum Flag {
+    Bool(bool),
+    Text(String),
+}
+
+impl Flag {
+    fn is_set(&self) -> bool {
+        match self {
+            Flag::Bool(b) => *b,
+            Flag::Text(t) => t == "true",
+        }
+    }
+}
+
+impl Default for Flag {
+    fn default() -> Self {
+        Flag::Bool(true)
+    }
+}
+
+impl From<bool> for Flag {
+    fn from(value: bool) -> Self {
+        Flag::Text(value.to_string())
+    }
+}
+
+fn default_srgb() -> Flag {
+    false.into()
+}
+
+fn default_internal() -> String {
+    "byte".into()
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+pub struct Sampler {
+    #[serde(default)]
+    pub filter: TextureFilter,
+    #[serde(default)]
+    pub wrap: TextureWrap,
+    #[serde(default)]
+    pub vflip: Flag,
+    #[serde(default = "default_srgb")]
+    pub srgb: Flag,
+    #[serde(default = "default_internal")]
+    pub internal: String,
+}
+
+/// A channel input of a render pass
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+pub struct Input {
+    pub id: Id,
+    #[serde(default, alias = "filepath")]
+    pub src: String,
+    #[serde(alias = "type")]
+    pub ctype: String,
+    pub channel: usize,
+    #[serde(default)]
+    pub sampler: Option<Sampler>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+pub struct Output {
+    pub id: Id,
+    #[serde(default)]
+    pub channel: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+pub struct RenderPass {
+    #[serde(default)]
+    pub inputs: Vec<Input>,
+    #[serde(default)]
+    pub outputs: Vec<Output>,
+    pub code: String,
+    #[serde(default)]
+    pub name: String,
+    #[serde(rename = "type")]
+    pub kind: String,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize, PartialEq, Clone)]
+pub struct Info {
+    #[serde(default)]
+    pub id: String,
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub username: String,
+    #[serde(default)]
+    pub description: String,
+}
+
+fn default_ver() -> String {
+    "0.1".into()
+}
+
+/// A shader as exported by Shadertoy
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+pub struct Shader {
+    #[serde(default = "default_ver")]
+    pub ver: String,
+    #[serde(default)]
+    pub info: Info,
+    pub renderpass: Vec<RenderPass>,
+}
+
+/// The layouts Shadertoy exports are found in: the API's `{"Shader": ...}`
+/// response, a bare shader, or a list of shaders from the site's export
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum Document {
+    Api {
+        #[serde(rename = "Shader")]
+        shader: Shader,
+    },
+    Shader(Shader),
+    List(Vec<Shader>),
+}
+
+/// Error importing a Shadertoy export
+#[derive(Debug)]
+pub enum ImportError {
+    /// The export could not be read
+    Io(PathBuf, io::Error),
+    /// The export is not a Shadertoy JSON document
+    Json(serde_json::Error),
+    /// The export contains no shader
+    Empty,
+    /// The shader has no image pass
+    MissingImage,
+    /// The target directory already contains a toy
+    Exists(PathBuf),
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImportError::Io(path, e) => write!(f, "error accessing {:?}: {}", path, e),
+            ImportError::Json(e) => write!(f, "error parsing Shadertoy JSON: {}", e),
+            ImportError::Empty => write!(f, "export contains no shader"),
+            ImportError::MissingImage => write!(f, "shader has no image pass"),
+            ImportError::Exists(path) => write!(
+                f,
+                "{:?} already contains a toy, pass --force to overwrite it",
+                path
+            ),
+        }
+    }
+}
+
+impl error::Error for ImportError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            ImportError::Io(_, e) => Some(e),
+            ImportError::Json(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// Parse a Shadertoy export, taking the first shader of lists
+pub fn parse(json: &str) -> Result<Shader, ImportError> {
+    match serde_json::from_str(json).map_err(ImportError::Json)? {
+        Document::Api { shader } | Document::Shader(shader) => Ok(shader),
+        Document::List(shaders) => shaders.into_iter().next().ok_or(ImportError::Empty),
+    }
+}
+
+/// A toy translated from a Shadertoy shader
+#[derive(Debug)]
+pub struct Import {
+    pub toy: Toy,
+    /// Parts of the shader that could not be translated faithfully
+    pub warnings: Vec<String>,
+}
+
+/// Buffer pass of a Shadertoy render pass, from its output id or its name
+fn pass_buffer(pass: &RenderPass) -> Option<BufferName> {
+    pass.outputs.iter().find_map(|o| o.id.buffer()).or_else(|| {
+        // e.g. "Buffer A" or "Buf A"
+        match pass.name.trim().chars().last()? {
+            'A' => Some(BufferName::A),
+            'B' => Some(BufferName::B),
+            'C' => Some(BufferName::C),
+            'D' => Some(BufferName::D),
+            _ => None,
+        }
+    })
+}
+
+/// Translate the inputs of a pass into channels, ordered by channel index
+fn channels(pass: &RenderPass, warnings: &mut Vec<String>) -> Vec<Channel> {
+    let mut channels: BTreeMap<usize, Channel> = BTreeMap::new();
+    for input in &pass.inputs {
+        let config = match &input.sampler {
+            Some(sampler) => ChannelConfig::Texture {
+                vflip: sampler.vflip.is_set(),
+                filter: sampler.filter,
+                wrap: sampler.wrap,
+            },
+            None => ChannelConfig::default(),
+        };
+        let mut channel = Channel {
+            name: None,
+            path: None,
+            buffer: None,
+            config,
+        };
+        match input.ctype.as_str() {
+            "keyboard" => channel.config = ChannelConfig::Keyboard,
+            "buffer" => match input.id.buffer() {
+                Some(buffer) => channel.buffer = Some(buffer),
+                None => {
+                    warnings.push(format!(
+                        "{} iChannel{}: unknown buffer {:?}",
+                        pass.name, input.channel, input.id
+                    ));
+                    continue;
+                }
+            },
+            ctype @ ("texture" | "volume") => {
+                let file = input.src.rsplit('/').next().unwrap_or_default();
+                match MEDIA.iter().find(|(src, _)| *src == file) {
+                    Some((_, name)) => channel.name = Some(*name),
+                    None => {
+                        warnings.push(format!(
+                            "{} iChannel{}: {} {:?} is not built in",
+                            pass.name, input.channel, ctype, input.src
+                        ));
+                        continue;
+                    }
+                }
+                if ctype == "volume" {
+                    channel.config = ChannelConfig::Volume;
+                }
+            }
+            ctype => {
+                warnings.push(format!(
+                    "{} iChannel{}: {} inputs are not supported",
+                    pass.name, input.channel, ctype
+                ));
+                continue;
+            }
+        }
+        channels.insert(input.channel, channel);
+    }
+
+    // channels bind in order, so gaps before used channels need a placeholder
+    let count = channels.keys().last().map(|c| c + 1).unwrap_or(0);
+    (0..count)
+        .map(|index| {
+            channels.remove(&index).unwrap_or_else(|| {
+                warnings.push(format!(
+                    "{} iChannel{}: bound to rgba_noise_small in place of the missing input",
+                    pass.name, index
+                ));
+                Channel {
+                    name: Some(BuiltinName::RgbaNoiseSmall),
+                    path: None,
+                    buffer: None,
+                    config: ChannelConfig::default(),
+                }
+            })
+        })
+        .collect()
+}
+
+/// Translate a Shadertoy shader into a toy
+pub fn import(shader: &Shader) -> Result<Import, ImportError> {
+    let mut warnings = vec![];
+    let mut toy = Toy::default();
+    let mut image = None;
+    for pass in &shader.renderpass {
+        let code = pass.code.clone();
+        match pass.kind.as_str() {
+            "image" => {
+                toy.config.channels = channels(pass, &mut warnings);
+                image = Some(code);
+            }
+            "buffer" => match pass_buffer(pass) {
+                Some(buffer) => {
+                    let channels = channels(pass, &mut warnings);
+                    if !channels.is_empty() {
+                        toy.config.buffers.insert(buffer, PassConfig { channels });
+                    }
+                    toy.buffers.insert(buffer, code);
+                }
+                None => warnings.push(format!("{}: unknown buffer pass, skipped", pass.name)),
+            },
+            "common" => toy.common = Some(code),
+            "sound" => {
+                toy.config.sound.channels = channels(pass, &mut warnings);
+                toy.sound = Some(code);
+            }
+            kind => warnings.push(format!(
+                "{}: {} passes are not supported, skipped",
+                pass.name, kind
+            )),
+        }
+    }
+    toy.main_image = image.ok_or(ImportError::MissingImage)?;
+    Ok(Import { toy, warnings })
+}
+
+/// Import the Shadertoy export `file` as a toy in `location`, returning warnings
+pub fn import_file<P, Q>(file: P, location: Q, force: bool) -> Result<Vec<String>, ImportError>
+where
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+{
+    let location = location.as_ref();
+    let json =
+        fs::read_to_string(&file).map_err(|e| ImportError::Io(file.as_ref().to_path_buf(), e))?;
+    let shader = parse(&json)?;
+    let Import { toy, warnings } = import(&shader)?;
+
+    if !force
+        && ["image.glsl", "toy.toml"]
+            .iter()
+            .any(|f| location.join(f).exists())
+    {
+        return Err(ImportError::Exists(location.to_path_buf()));
+    }
+    let io_err = |e| ImportError::Io(location.to_path_buf(), e);
+    toy.write(location, true).map_err(io_err)?;
+    if !shader.info.id.is_empty() {
+        let name = match shader.info.name.as_str() {
+            "" => shader.info.id.as_str(),
+            name => name,
+        };
+        let readme = format!(
+            "**Reference:** [{}](https://www.shadertoy.com/view/{})\n",
+            name, shader.info.id
+        );
+        fs::write(location.join("README.md"), readme).map_err(io_err)?;
+    }
+    Ok(warnings)
+}
+
+pub fn run_import(file: PathBuf, location: PathBuf, force: bool) {
+    match import_file(&file, &location, force) {
+        Ok(warnings) => {
+            for warning in warnings {
+                println!("Warning: {}", warning);
+            }
+        }
+        Err(e) => {
+            log::error!("Error importing {:?}: {}", file, e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Error exporting a toy to Shadertoy
+#[derive(Debug)]
+pub enum ExportError {
+    /// A channel of the toy is invalid
+    Channel(ChannelError),
+    /// An `#include` of a pass could not be expanded
+    Include(IncludeError),
+    /// A channel of a pass file reads a local file, which Shadertoy cannot host
+    LocalPath(&'static str, usize, String),
+    /// A channel reads built-in data that is not Shadertoy media
+    Builtin(BuiltinName),
+    /// The toy uses cube maps, which are not exported
+    Cubemap,
+    /// The export could not be written
+    Io(PathBuf, io::Error),
+}
+
+impl fmt::Display for ExportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExportError::Channel(e) => write!(f, "{}", e),
+            ExportError::Include(e) => write!(f, "{}", e),
+            ExportError::LocalPath(file, index, path) => write!(
+                f,
+                "iChannel{} of {} reads local file {:?}, which Shadertoy cannot represent",
+                index, file, path
+            ),
+            ExportError::Builtin(name) => {
+                write!(f, "built-in {:?} has no Shadertoy equivalent", name)
+            }
+            ExportError::Cubemap => write!(f, "cube map channels and passes are not exported"),
+            ExportError::Io(path, e) => write!(f, "error writing {:?}: {}", path, e),
+        }
+    }
+}
+
+impl error::Error for ExportError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            ExportError::Channel(e) => Some(e),
+            ExportError::Include(e) => Some(e),
+            ExportError::Io(_, e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// Output id of a buffer pass, along with the index Shadertoy numbers it by
+fn buffer_id(buffer: BufferName) -> (&'static str, usize) {
+    BUFFER_IDS
+        .iter()
+        .enumerate()
+        .find(|(_, (b, _, _))| *b == buffer)
+        .map(|(index, (_, id, _))| (*id, index))
+        .expect("every buffer has an id")
+}
+
+/// Translate the channels of the pass in `file` into inputs
+fn inputs(channels: &[Channel], file: &'static str) -> Result<Vec<Input>, ExportError> {
+    let mut inputs = vec![];
+    for (index, channel) in channels.iter().enumerate() {
+        channel.validate().map_err(ExportError::Channel)?;
+        match &channel.config {
+            ChannelConfig::Audio { path } | ChannelConfig::Video { path, .. } => {
+                return Err(ExportError::LocalPath(file, index, path.clone()));
+            }
+            ChannelConfig::Cubemap { .. } => return Err(ExportError::Cubemap),
+            _ => (),
+        }
+        let (id, src, ctype) = match (channel.name, &channel.path, channel.buffer) {
+            _ if channel.config == ChannelConfig::Keyboard => (
+                Id::Text(KEYBOARD_ID.into()),
+                "/presets/tex00.jpg".into(),
+                "keyboard",
+            ),
+            (Some(name), _, _) => {
+                let (media, _) = MEDIA
+                    .iter()
+                    .find(|(_, n)| *n == name)
+                    .ok_or(ExportError::Builtin(name))?;
+                let ctype = match channel.config {
+                    ChannelConfig::Volume => "volume",
+                    _ => "texture",
+                };
+                (
+                    Id::Number(index as u64),
+                    format!("/media/a/{}", media),
+                    ctype,
+                )
+            }
+            (_, Some(path), _) => {
+                return Err(ExportError::LocalPath(file, index, path.clone()));
+            }
+            (_, _, Some(buffer)) => {
+                let (id, number) = buffer_id(buffer);
+                let src = format!("/media/previz/buffer{:02}.png", number);
+                (Id::Text(id.into()), src, "buffer")
+            }
+            (None, None, None) => unreachable!("validated channel has a source"),
+        };
+        let (vflip, filter, wrap) = match &channel.config {
+            ChannelConfig::Texture {
+                vflip,
+                filter,
+                wrap,
+            } => (*vflip, *filter, *wrap),
+            ChannelConfig::Volume => (false, TextureFilter::Linear, TextureWrap::Repeat),
+            _ => (true, TextureFilter::Nearest, TextureWrap::Clamp),
+        };
+        inputs.push(Input {
+            id,
+            src,
+            ctype: ctype.into(),
+            channel: index,
+            sampler: Some(Sampler {
+                filter,
+                wrap,
+                vflip: vflip.into(),
+                srgb: default_srgb(),
+                internal: default_internal(),
+            }),
+        });
+    }
+    Ok(inputs)
+}
+
+/// Source of a pass with any `#include`s inlined, since Shadertoy has none
+fn expand(toy: &Toy, source: &str, file: &str) -> Result<String, ExportError> {
+    if source.lines().any(|l| include::directive(l).is_some()) {
+        let fragment = shader::preprocess(source, file, &toy.includes());
+        Ok(fragment.map_err(ExportError::Include)?.source)
+    } else {
+        Ok(source.into())
+    }
+}
+
+/// Translate a toy into a Shadertoy shader named `name`
+pub fn export(toy: &Toy, name: &str) -> Result<Shader, ExportError> {
+    toy.validate().map_err(ExportError::Channel)?;
+    if toy.cube.is_some() {
+        return Err(ExportError::Cubemap);
+    }
+    let mut renderpass = vec![];
+    if let Some(common) = &toy.common {
+        renderpass.push(RenderPass {
+            inputs: vec![],
+            outputs: vec![],
+            code: expand(toy, common, "common.glsl")?,
+            name: "Common".into(),
+            kind: "common".into(),
+        });
+    }
+    for (buffer, code) in &toy.buffers {
+        renderpass.push(RenderPass {
+            inputs: inputs(toy.config.buffer_channels(*buffer), buffer.file_name())?,
+            outputs: vec![Output {
+                id: Id::Text(buffer_id(*buffer).0.into()),
+                channel: 0,
+            }],
+            code: expand(toy, code, buffer.file_name())?,
+            name: format!("Buffer {:?}", buffer),
+            kind: "buffer".into(),
+        });
+    }
+    renderpass.push(RenderPass {
+        inputs: inputs(&toy.config.channels, "image.glsl")?,
+        outputs: vec![Output {
+            id: Id::Text(IMAGE_ID.into()),
+            channel: 0,
+        }],
+        code: expand(toy, &toy.main_image, "image.glsl")?,
+        name: "Image".into(),
+        kind: "image".into(),
+    });
+    if let Some(sound) = &toy.sound {
+        renderpass.push(RenderPass {
+            inputs: inputs(&toy.config.sound.channels, "sound.glsl")?,
+            outputs: vec![],
+            code: expand(toy, sound, "sound.glsl")?,
+            name: "Sound".into(),
+            kind: "sound".into(),
+        });
+    }
+    Ok(Shader {
+        ver: default_ver(),
+        info: Info {
+            name: name.into(),
+            ..Default::default()
+        },
+        renderpass,
+    })
+}
+
+/// Serialize a shader in the layout of the Shadertoy API
+pub fn to_json(shader: Shader) -> String {
+    serde_json::to_string_pretty(&Document::Api { shader }).expect("shader always serializable")
+}
+
+/// Export the toy in `location` as JSON, written to `output` or standard output
+pub fn export_file<P: AsRef<Path>>(location: P, output: Option<&Path>) -> Result<(), ExportError> {
+    let location = location.as_ref();
+    let toy = Toy::from_path(location);
+    let name = location
+        .canonicalize()
+        .ok()
+        .and_then(|p| p.file_name().map(|n| n.to_string_lossy().into_owned()))
+        .unwrap_or_default();
+    let json = to_json(export(&toy, &name)?);
+    match output {
+        Some(path) => fs::write(path, json).map_err(|e| ExportError::Io(path.to_path_buf(), e)),
+        None => {
+            println!("{}", json);
+            Ok(())
+        }
+    }
+}
+
+pub fn run_export(location: PathBuf, output: Option<PathBuf>) {
+    if let Err(e) = export_file(&location, output.as_deref()) {
+        log::error!("Error exporting {:?}: {}", location, e);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::toy::cubemap::CubeLayout;
+    use tempdir::TempDir;
+
+    const EXPORT: &str = r#"{
+        "Shader": {
+            "ver": "0.1",
+            "info": {"id": "abcd12", "name": "Feedback", "username": "someone", "description": ""},
+            "renderpass": [
+                {
+                    "inputs": [
+                        {"id": "4dXGR8", "src": "/media/previz/buffer00.png", "ctype": "buffer", "channel": 0,
+                         "sampler": {"filter": "linear", "wrap": "clamp", "vflip": "true", "srgb": "false", "internal": "byte"},
+                         "published": 1},
+                        {"id": "XdX3Rn", "src": "/media/a/0c7bf5fe9462d5bffbd11126e82908e39be3ce56220d900f633d58fb432e56f5.png",
+                         "ctype": "texture", "channel": 2,
+                         "sampler": {"filter": "nearest", "wrap": "repeat", "vflip": "false", "srgb": "false", "internal": "byte"},
+                         "published": 1},
+                        {"id": "4sfGRr", "src": "/media/a/27012b4eadd0c3ce12498b867058e4f717ce79e10a99568cca461682d84a4b04.bin",
+                         "ctype": "volume", "channel": 3,
+                         "sampler": {"filter": "mipmap", "wrap": "repeat", "vflip": "true", "srgb": "false", "internal": "byte"},
+                         "published": 1}
+                    ],
+                    "outputs": [{"id": "4dfGRr", "channel": 0}],
+                    "code": "void mainImage(out vec4 c, in vec2 p) { c = vec4(f()); }",
+                    "name": "Image",
+                    "description": "",
+                    "type": "image"
+                },
+                {
+                    "inputs": [{"id": 257, "filepath": "/presets/previz/buffer00.png", "type": "buffer", "channel": 0}],
+                    "outputs": [{"id": 257, "channel": 0}],
+                    "code": "void mainImage(out vec4 c, in vec2 p) { c = vec4(0.5); }",
+                    "name": "Buf A",
+                    "description": "",
+                    "type": "buffer"
+                },
+                {
+                    "inputs": [],
+                    "outputs": [],
+                    "code": "float f() { return 1.0; }",
+                    "name": "Common",
+                    "description": "",
+                    "type": "common"
+                }
+            ]
+        }
+    }"#;
+
+    #[test]
+    fn import_passes_and_channels() {
+        let Import { toy, warnings } = import(&parse(EXPORT).unwrap()).unwrap();
+        assert_eq!(Some("float f() { return 1.0; }"), toy.common.as_deref());
+        assert!(toy.main_image.starts_with("void mainImage"));
+        assert!(toy.buffers[&BufferName::A].contains("vec4(0.5)"));
+        assert!(toy.validate().is_ok());
+
+        let channels = &toy.config.channels;
+        assert_eq!(4, channels.len());
+        assert_eq!(Some(BufferName::A), channels[0].buffer);
+        assert_eq!(
+            ChannelConfig::Texture {
+                vflip: true,
+                filter: TextureFilter::Linear,
+                wrap: TextureWrap::Clamp
+            },
+            channels[0].config
+        );
+        assert_eq!(Some(BuiltinName::GrayNoiseMedium), channels[2].name);
+        assert_eq!(
+            ChannelConfig::Texture {
+                vflip: false,
+                filter: TextureFilter::Nearest,
+                wrap: TextureWrap::Repeat
+            },
+            channels[2].config
+        );
+        assert_eq!(Some(BuiltinName::GreyNoise3D), channels[3].name);
+        assert_eq!(ChannelConfig::Volume, channels[3].config);
+        assert_eq!(
+            Some(BufferName::A),
+            toy.config.buffer_channels(BufferName::A)[0].buffer
+        );
+
+        // the unused iChannel1 is filled in
+        assert_eq!(1, warnings.len(), "{:?}", warnings);
+        assert!(warnings[0].contains("iChannel1"));
+    }
+
+    #[test]
+    fn noise_matches_shadertoy_size() {
+        // sizes of the noise textures on Shadertoy's media list
+        let sizes = [
+            ("f735bee5", 64),
+            ("3083c722", 256),
+            ("0a405623", 64),
+            ("0c7bf5fe", 256),
+        ];
+        for (hash, size) in sizes {
+            let (_, name) = MEDIA.iter().find(|(src, _)| src.starts_with(hash)).unwrap();
+            let bytes = name.bytes(None).unwrap();
+            let image = image::load_from_memory(&bytes).unwrap();
+            assert_eq!((size, size), (image.width(), image.height()), "{:?}", name);
+        }
+    }
+
+    #[test]
+    fn import_photos() {
+        for (hash, name) in [
+            ("1f7dca9c", BuiltinName::Organic1),
+            ("8de3a392", BuiltinName::Abstract1),
+            ("95b90082", BuiltinName::London),
+        ] {
+            let (src, _) = MEDIA.iter().find(|(src, _)| src.starts_with(hash)).unwrap();
+            let json = format!(
+                r#"{{"renderpass": [{{
+                    "inputs": [{{"id": "Xsf3Rr", "src": "/media/a/{}", "ctype": "texture", "channel": 0}}],
+                    "code": "code",
+                    "name": "Image",
+                    "type": "image"
+                }}]}}"#,
+                src
+            );
+            let Import { toy, warnings } = import(&parse(&json).unwrap()).unwrap();
+            assert!(warnings.is_empty(), "{:?}", warnings);
+            assert_eq!(Some(name), toy.config.channels[0].name);
+        }
+    }
+
+    #[test]
+    fn import_unsupported_inputs() {
+        let json = r#"[{
+            "info": {},
+            "renderpass": [{
+                "inputs": [
+                    {"id": "4dXGRr", "src": "/presets/tex00.jpg", "ctype": "webcam", "channel": 0},
+                    {"id": "Xsf3Rr", "src": "/media/a/unknown.jpg", "ctype": "texture", "channel": 3}
+                ],
+                "outputs": [],
+                "code": "code",
+                "name": "Image",
+                "type": "image"
+            }, {
+                "code": "cubemap",
+                "name": "Cube A",
+                "type": "cubemap"
+            }]
+        }]"#;
+        let Import { toy, warnings } = import(&parse(json).unwrap()).unwrap();
+        assert_eq!("code", toy.main_image);
+        assert!(toy.config.channels.is_empty());
+        assert_eq!(3, warnings.len(), "{:?}", warnings);
+        assert!(warnings[0].contains("webcam"));
+        assert!(warnings[1].contains("unknown.jpg"));
+        assert!(warnings[2].contains("cubemap"));
+    }
+
+    #[test]
+    fn import_errors() {
+        assert!(matches!(parse("{}"), Err(ImportError::Json(_))));
+        assert!(matches!(parse("[]"), Err(ImportError::Empty)));
+        let shader = parse(r#"{"renderpass": []}"#).unwrap();
+        assert!(matches!(import(&shader), Err(ImportError::MissingImage)));
+    }
+
+    #[test]
+    fn import_to_directory() {
+        let tmp_dir = TempDir::new("import_to_directory").unwrap().into_path();
+        let file = tmp_dir.join("shader.json");
+        fs::write(&file, EXPORT).unwrap();
+        let location = tmp_dir.join("toy");
+
+        import_file(&file, &location, false).unwrap();
+        let toy = Toy::from_path(&location);
+        assert!(toy.buffers.contains_key(&BufferName::A));
+        assert_eq!(4, toy.config.channels.len());
+        let readme = fs::read_to_string(location.join("README.md")).unwrap();
+        assert!(readme.contains("https://www.shadertoy.com/view/abcd12"));
+
+        // existing toys are only replaced when forced
+        assert!(matches!(
+            import_file(&file, &location, false),
+            Err(ImportError::Exists(_))
+        ));
+        assert!(import_file(&file, &location, true).is_ok());
+    }
+
+    #[test]
+    fn export_round_trip() {
+        let mut toy = Toy {
+            common: Some("float common;".into()),
+            sound: Some("vec2 mainSound(int s, float t) { return vec2(0.0); }".into()),
+            ..Default::default()
+        };
+        toy.buffers.insert(BufferName::B, "buffer b".into());
+        let noise = Channel {
+            name: Some(BuiltinName::RgbaNoiseSmall),
+            path: None,
+            buffer: None,
+            config: ChannelConfig::Texture {
+                vflip: false,
+                filter: TextureFilter::Nearest,
+                wrap: TextureWrap::Repeat,
+            },
+        };
+        let feedback = Channel {
+            name: None,
+            path: None,
+            buffer: Some(BufferName::B),
+            config: ChannelConfig::default(),
+        };
+        let keyboard = Channel {
+            name: None,
+            path: None,
+            buffer: None,
+            config: ChannelConfig::Keyboard,
+        };
+        let volume = Channel {
+            name: Some(BuiltinName::RgbaNoise3D),
+            path: None,
+            buffer: None,
+            config: ChannelConfig::Volume,
+        };
+        toy.config.channels = vec![feedback.clone(), noise, keyboard, volume];
+        toy.config.buffers.insert(
+            BufferName::B,
+            PassConfig {
+                channels: vec![feedback],
+            },
+        );
+
+        let json = to_json(export(&toy, "round trip").unwrap());
+        let shader = parse(&json).unwrap();
+        assert_eq!("round trip", shader.info.name);
+        let Import {
+            toy: read,
+            warnings,
+        } = import(&shader).unwrap();
+        assert!(warnings.is_empty(), "{:?}", warnings);
+        assert_eq!(toy.main_image, read.main_image);
+        assert_eq!(toy.common, read.common);
+        assert_eq!(toy.sound, read.sound);
+        assert_eq!(toy.buffers, read.buffers);
+        assert_eq!(toy.config, read.config);
+    }
+
+    #[test]
+    fn export_inlines_includes() {
+        let tmp_dir = TempDir::new("export_inlines_includes").unwrap().into_path();
+        fs::write(tmp_dir.join("lib.glsl"), "float lib() { return 1.0; }").unwrap();
+        let toy = Toy {
+            root: tmp_dir.clone(),
+            main_image: "#include \"lib.glsl\"\nvoid mainImage(out vec4 c, in vec2 p) {}".into(),
+            ..Default::default()
+        };
+        let shader = export(&toy, "").unwrap();
+        let image = &shader.renderpass.last().unwrap().code;
+        assert!(image.starts_with("float lib()"), "{}", image);
+        assert!(!image.contains("#include"));
+
+        let toy = Toy {
+            main_image: "#include \"missing.glsl\"".into(),
+            ..toy
+        };
+        assert!(matches!(export(&toy, ""), Err(ExportError::Include(_))));
+    }
+
+    #[test]
+    fn export_errors() {
+        let mut toy = Toy::default();
+        let mut chan = Channel {
+            name: None,
+            path: Some("wood.jpg".into()),
+            buffer: None,
+            config: ChannelConfig::default(),
+        };
+        toy.config.channels = vec![chan.clone()];
+        let err = export(&toy, "").unwrap_err();
+        assert!(matches!(err, ExportError::LocalPath("image.glsl", 0, _)));
+        assert!(err.to_string().contains("wood.jpg"));
+
+        chan.path = None;
+        chan.buffer = Some(BufferName::A);
+        toy.config.channels = vec![chan.clone()];
+        assert!(matches!(
+            export(&toy, ""),
+            Err(ExportError::Channel(ChannelError::MissingBuffer(_)))
+        ));
+
+        chan.buffer = None;
+        chan.name = Some(BuiltinName::Sky);
+        toy.config.channels = vec![chan.clone()];
+        assert!(matches!(
+            export(&toy, ""),
+            Err(ExportError::Builtin(BuiltinName::Sky))
+        ));
+
+        chan.config = ChannelConfig::Cubemap {
+            layout: CubeLayout::Image,
+        };
+        toy.config.channels = vec![chan];
+        assert!(matches!(export(&toy, ""), Err(ExportError::Cubemap)));
+    }
+}
@@ -0,0 +1,258 @@
+use std::error;
+use std::f32::consts::TAU;
+use std::fmt;
+use std::fs;
+use std::io::{self, Cursor};
+use std::path::{Path, PathBuf};
+
+use clap::ValueEnum;
+
+/// Sample rate of the generated track of the audio visualizer
+const SAMPLE_RATE: u32 = 22050;
+
+/// Length of the generated track in seconds, looped by the audio channel
+const TRACK_SECONDS: u32 = 8;
+
+/// Starting point of a new toy
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Template {
+    /// Time varying gradient, Shadertoy's default shader
+    Basic,
+    /// Sphere traced distance field with lighting and soft shadows
+    Raymarcher,
+    /// Buffer A reading its own previous frame, shown by the main image
+    BufferFeedback,
+    /// Built-in noise texture sampled by the main image
+    TextureSample,
+    /// Spectrum and waveform of an audio channel, with a generated track
+    AudioVisualizer,
+}
+
+impl Template {
+    /// Files of the template, by name in the toy directory
+    pub fn files(self) -> Vec<(&'static str, Vec<u8>)> {
+        let text = |name, source: &str| (name, source.as_bytes().to_vec());
+        match self {
+            Template::Basic => vec![
+                text("image.glsl", include_str!("../template/basic/image.glsl")),
+                text("toy.toml", include_str!("../template/basic/toy.toml")),
+            ],
+            Template::Raymarcher => vec![
+                text(
+                    "image.glsl",
+                    include_str!("../template/raymarcher/image.glsl"),
+                ),
+                text("toy.toml", include_str!("../template/raymarcher/toy.toml")),
+            ],
+            Template::BufferFeedback => vec![
+                text(
+                    "image.glsl",
+                    include_str!("../template/buffer-feedback/image.glsl"),
+                ),
+                text(
+                    "bufa.glsl",
+                    include_str!("../template/buffer-feedback/bufa.glsl"),
+                ),
+                text(
+                    "toy.toml",
+                    include_str!("../template/buffer-feedback/toy.toml"),
+                ),
+            ],
+            Template::TextureSample => vec![
+                text(
+                    "image.glsl",
+                    include_str!("../template/texture-sample/image.glsl"),
+                ),
+                text(
+                    "toy.toml",
+                    include_str!("../template/texture-sample/toy.toml"),
+                ),
+            ],
+            Template::AudioVisualizer => vec![
+                text(
+                    "image.glsl",
+                    include_str!("../template/audio-visualizer/image.glsl"),
+                ),
+                text(
+                    "toy.toml",
+                    include_str!("../template/audio-visualizer/toy.toml"),
+                ),
+                ("music.wav", track()),
+            ],
+        }
+    }
+}
+
+/// A looping arpeggio over a kick drum, as a 16 bit mono WAV file
+fn track() -> Vec<u8> {
+    let spec = hound::WavSpec {
+        channels: 1,
+        sample_rate: SAMPLE_RATE,
+        bits_per_sample: 16,
+        sample_format: hound::SampleFormat::Int,
+    };
+    let mut wav = Cursor::new(vec![]);
+    let mut writer = hound::WavWriter::new(&mut wav, spec).expect("wav header writable");
+    // A minor, F major, C major and G major chords, two seconds each
+    let chords: [[f32; 3]; 4] = [
+        [220.0, 261.63, 329.63],
+        [174.61, 220.0, 261.63],
+        [261.63, 329.63, 392.0],
+        [196.0, 246.94, 293.66],
+    ];
+    for i in 0..SAMPLE_RATE * TRACK_SECONDS {
+        let t = i as f32 / SAMPLE_RATE as f32;
+        let chord = chords[(t / 2.0) as usize % chords.len()];
+
+        // eighth notes at 120 bpm, cycling through the chord
+        let note = (t * 4.0) as usize;
+        let beat = t * 4.0 - note as f32;
+        let freq = chord[note % 3] * if note % 6 >= 3 { 2.0 } else { 1.0 };
+        let arp = (TAU * freq * t).sin() * (-6.0 * beat).exp();
+        let pad: f32 = chord.iter().map(|f| (TAU * f * 0.5 * t).sin()).sum();
+
+        // kick on every quarter note, with a falling pitch
+        let kick_t = (t * 2.0).fract() / 2.0;
+        let kick = (TAU * (50.0 * kick_t + 60.0 * (1.0 - (-20.0 * kick_t).exp()) / 20.0)).sin()
+            * (-12.0 * kick_t).exp();
+
+        let sample = 0.35 * arp + 0.08 * pad + 0.5 * kick;
+        writer
+            .write_sample((sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16)
+            .expect("writing to memory");
+    }
+    writer.finalize().expect("writing to memory");
+    wav.into_inner()
+}
+
+/// Error creating a toy from a template
+#[derive(Debug)]
+pub enum NewError {
+    /// Files of the template already exist in the directory
+    Exists(Vec<PathBuf>),
+    /// A file could not be written
+    Io(PathBuf, io::Error),
+}
+
+impl fmt::Display for NewError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NewError::Exists(paths) => write!(
+                f,
+                "{:?} already exist, pass --force to overwrite them",
+                paths
+            ),
+            NewError::Io(path, e) => write!(f, "error writing {:?}: {}", path, e),
+        }
+    }
+}
+
+impl error::Error for NewError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            NewError::Io(_, e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// Write the files of `template` to the toy directory `location`, returning
+/// the written files. Existing files are only replaced with `force`.
+pub fn create<P>(location: P, template: Template, force: bool) -> Result<Vec<PathBuf>, NewError>
+where
+    P: AsRef<Path>,
+{
+    let location = location.as_ref();
+    let files = template.files();
+    let existing: Vec<_> = files
+        .iter()
+        .map(|(name, _)| location.join(name))
+        .filter(|path| path.exists())
+        .collect();
+    if !force && !existing.is_empty() {
+        return Err(NewError::Exists(existing));
+    }
+
+    fs::create_dir_all(location).map_err(|e| NewError::Io(location.to_path_buf(), e))?;
+    let mut written = vec![];
+    for (name, contents) in files {
+        let path = location.join(name);
+        fs::write(&path, contents).map_err(|e| NewError::Io(path.clone(), e))?;
+        written.push(path);
+    }
+    Ok(written)
+}
+
+pub fn run(location: PathBuf, template: Template, force: bool) {
+    match create(&location, template, force) {
+        Ok(written) => {
+            for path in written {
+                println!("Wrote {}", path.display());
+            }
+        }
+        Err(e) => {
+            log::error!("Error creating {:?}: {}", location, e);
+            std::process::exit(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::render::test::headless;
+    use crate::toy::Toy;
+    use tempdir::TempDir;
+
+    #[test]
+    fn refuse_overwrite() {
+        let tmp_dir = TempDir::new("refuse_overwrite").unwrap().into_path();
+        let toy = tmp_dir.join("toy");
+        let written = create(&toy, Template::BufferFeedback, false).unwrap();
+        assert_eq!(3, written.len());
+        assert!(toy.join("bufa.glsl").exists());
+
+        fs::write(toy.join("image.glsl"), "edited").unwrap();
+        match create(&toy, Template::Basic, false) {
+            Err(NewError::Exists(paths)) => {
+                assert_eq!(vec![toy.join("image.glsl"), toy.join("toy.toml")], paths)
+            }
+            other => panic!("expected existing files, got {:?}", other),
+        }
+        assert_eq!(
+            "edited",
+            fs::read_to_string(toy.join("image.glsl")).unwrap()
+        );
+
+        create(&toy, Template::Basic, true).unwrap();
+        assert_ne!(
+            "edited",
+            fs::read_to_string(toy.join("image.glsl")).unwrap()
+        );
+        // files the template does not write are kept
+        assert!(toy.join("bufa.glsl").exists());
+    }
+
+    #[test]
+    fn templates_compile() {
+        let Some(headless) = headless() else { return };
+        let mut renderer = headless.renderer(32, 16);
+        let tmp_dir = TempDir::new("templates_compile").unwrap().into_path();
+        for template in Template::value_variants() {
+            let name = template.to_possible_value().unwrap().get_name().to_owned();
+            let location = tmp_dir.join(&name);
+            create(&location, *template, false).unwrap();
+
+            let toy = Toy::from_path(&location);
+            if let Err(e) = renderer.compile(&toy) {
+                panic!("template {} does not compile: {}", name, e);
+            }
+            renderer.uniforms.iTime = 1.0;
+            let target = renderer.new_frame_target();
+            let image = renderer.capture(&target);
+            renderer.delete_frame_target(target);
+            // every template draws something other than black
+            assert!(image.pixels().any(|p| p[0] > 0 || p[1] > 0 || p[2] > 0));
+        }
+    }
+}
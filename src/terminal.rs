@@ -0,0 +1,471 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt::Write as _;
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use clap::ValueEnum;
+use futures::channel::mpsc::Receiver;
+use image::codecs::png::{CompressionType, FilterType, PngEncoder};
+use image::{ImageEncoder, RgbaImage};
+
+use crate::runtime::headless::Headless;
+use crate::runtime::playback::Clock;
+use crate::runtime::Renderer;
+use crate::toy::Toy;
+
+/// Frames per second streamed to the terminal
+const FPS: f32 = 30.0;
+
+/// Kitty frames are rendered at a fraction of the terminal's resolution and
+/// scaled up by the terminal, keeping frames small enough to stream
+const KITTY_SCALE: u32 = 2;
+
+/// Largest payload of a kitty graphics escape sequence
+const KITTY_CHUNK: usize = 4096;
+
+/// How frames are drawn in the terminal
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TerminalMode {
+    /// Detect the graphics protocol of the terminal from its environment
+    Auto,
+    /// Kitty's graphics protocol, also supported by WezTerm and Ghostty
+    Kitty,
+    /// Sixel graphics, with a 216 color palette
+    Sixel,
+    /// Truecolor half block characters, two pixels per cell
+    Blocks,
+}
+
+impl TerminalMode {
+    /// Resolve `Auto` from the environment variables given by `var`
+    fn resolve(self, var: impl Fn(&str) -> Option<String>) -> TerminalMode {
+        if self != TerminalMode::Auto {
+            return self;
+        }
+        let term = var("TERM").unwrap_or_default();
+        let program = var("TERM_PROGRAM").unwrap_or_default();
+        if var("KITTY_WINDOW_ID").is_some()
+            || term.contains("kitty")
+            || term.contains("ghostty")
+            || program == "WezTerm"
+            || program == "ghostty"
+        {
+            TerminalMode::Kitty
+        } else if ["foot", "mlterm", "sixel"].iter().any(|t| term.contains(t)) {
+            TerminalMode::Sixel
+        } else {
+            TerminalMode::Blocks
+        }
+    }
+}
+
+/// Size of the terminal in cells and, when reported, in pixels
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct TermSize {
+    cols: u32,
+    rows: u32,
+    width: u32,
+    height: u32,
+}
+
+impl TermSize {
+    /// Size of the terminal on standard output, or 80x24 cells if it is not
+    /// a terminal
+    fn current() -> TermSize {
+        let mut ws: libc::winsize = unsafe { std::mem::zeroed() };
+        let ok = unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut ws) } == 0;
+        if !ok || ws.ws_col == 0 || ws.ws_row == 0 {
+            return TermSize {
+                cols: 80,
+                rows: 24,
+                width: 0,
+                height: 0,
+            };
+        }
+        TermSize {
+            cols: ws.ws_col as u32,
+            rows: ws.ws_row as u32,
+            width: ws.ws_xpixel as u32,
+            height: ws.ws_ypixel as u32,
+        }
+    }
+
+    /// Size of a cell in pixels, assuming 8x16 cells when not reported
+    fn cell(&self) -> (u32, u32) {
+        match (self.width / self.cols, self.height / self.rows) {
+            (0, _) | (_, 0) => (8, 16),
+            cell => cell,
+        }
+    }
+
+    /// Resolution frames are rendered at, filling the terminal above the
+    /// status line
+    fn frame_size(&self, mode: TerminalMode) -> (u32, u32) {
+        let rows = self.rows.saturating_sub(1).max(1);
+        let (cw, ch) = self.cell();
+        let (w, h) = match mode {
+            TerminalMode::Kitty => (self.cols * cw / KITTY_SCALE, rows * ch / KITTY_SCALE),
+            TerminalMode::Sixel => (self.cols * cw, rows * ch),
+            TerminalMode::Blocks | TerminalMode::Auto => (self.cols, rows * 2),
+        };
+        (w.max(1), h.max(1))
+    }
+}
+
+/// Standard base64 encoding, with padding
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let byte = |i: usize| *chunk.get(i).unwrap_or(&0) as u32;
+        let n = byte(0) << 16 | byte(1) << 8 | byte(2);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+/// Display an image over `cols` x `rows` cells from the cursor with kitty's
+/// graphics protocol, replacing the previous frame. Frames are sent as PNG,
+/// which is a fraction of the size of raw pixels over SSH
+fn kitty(image: &RgbaImage, cols: u32, rows: u32) -> String {
+    let mut png = vec![];
+    PngEncoder::new_with_quality(&mut png, CompressionType::Fast, FilterType::Adaptive)
+        .write_image(
+            image.as_raw(),
+            image.width(),
+            image.height(),
+            image::ExtendedColorType::Rgba8,
+        )
+        .expect("encoding to memory");
+    let payload = base64(&png);
+    let chunks: Vec<_> = payload.as_bytes().chunks(KITTY_CHUNK).collect();
+    let mut out = String::new();
+    for (i, chunk) in chunks.iter().enumerate() {
+        let more = (i + 1 < chunks.len()) as u8;
+        let chunk = std::str::from_utf8(chunk).expect("base64 is ascii");
+        if i == 0 {
+            // the same image and placement ids replace the previous frame
+            // without moving the cursor
+            let _ = write!(
+                out,
+                "\x1b_Ga=T,f=100,i=1,p=1,c={},r={},C=1,q=2,m={};{}\x1b\\",
+                cols, rows, more, chunk
+            );
+        } else {
+            let _ = write!(out, "\x1b_Gm={};{}\x1b\\", more, chunk);
+        }
+    }
+    out
+}
+
+/// Display an image from the cursor as sixels, with colors quantized to a
+/// 6x6x6 cube
+fn sixel(image: &RgbaImage) -> String {
+    let (w, h) = (image.width() as usize, image.height() as usize);
+    let level = |c: u8| (c as usize * 5 + 127) / 255;
+    let colors: Vec<usize> = image
+        .pixels()
+        .map(|p| level(p[0]) * 36 + level(p[1]) * 6 + level(p[2]))
+        .collect();
+
+    let mut out = format!("\x1bPq\"1;1;{};{}", w, h);
+    let mut used = [false; 216];
+    for &color in &colors {
+        used[color] = true;
+    }
+    for color in (0..216).filter(|&c| used[c]) {
+        let percent = |level: usize| level * 20;
+        let (r, g, b) = (color / 36, color / 6 % 6, color % 6);
+        let _ = write!(
+            out,
+            "#{};2;{};{};{}",
+            color,
+            percent(r),
+            percent(g),
+            percent(b)
+        );
+    }
+
+    // each band of six rows is drawn once per color, returning to its start
+    for y in (0..h).step_by(6) {
+        let mut bands: BTreeMap<usize, Vec<u8>> = BTreeMap::new();
+        for dy in 0..6.min(h - y) {
+            for x in 0..w {
+                let color = colors[(y + dy) * w + x];
+                bands.entry(color).or_insert_with(|| vec![0; w])[x] |= 1 << dy;
+            }
+        }
+        for (i, (color, bits)) in bands.iter().enumerate() {
+            if i > 0 {
+                out.push('$');
+            }
+            let _ = write!(out, "#{}", color);
+            let mut x = 0;
+            while x < w {
+                let run = bits[x..].iter().take_while(|&&b| b == bits[x]).count();
+                let c = (63 + bits[x]) as char;
+                if run > 3 {
+                    let _ = write!(out, "!{}{}", run, c);
+                } else {
+                    out.extend(std::iter::repeat_n(c, run));
+                }
+                x += run;
+            }
+        }
+        out.push('-');
+    }
+    out.push_str("\x1b\\");
+    out
+}
+
+/// Draw an image from the top left corner with truecolor upper half blocks,
+/// the top pixel of each cell in the foreground and the bottom one in the
+/// background
+fn blocks(image: &RgbaImage) -> String {
+    let mut out = String::new();
+    let mut last = None;
+    for row in 0..image.height().div_ceil(2) {
+        let _ = write!(out, "\x1b[{};1H", row + 1);
+        for x in 0..image.width() {
+            let top = image.get_pixel(x, row * 2);
+            let bottom = image.get_pixel_checked(x, row * 2 + 1).unwrap_or(top);
+            let colors = (top, bottom);
+            if last != Some(colors) {
+                let _ = write!(
+                    out,
+                    "\x1b[38;2;{};{};{};48;2;{};{};{}m",
+                    top[0], top[1], top[2], bottom[0], bottom[1], bottom[2]
+                );
+                last = Some(colors);
+            }
+            out.push('▀');
+        }
+    }
+    out.push_str("\x1b[0m");
+    out
+}
+
+/// Set when the user interrupts the terminal output, e.g. with Ctrl+C
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn interrupt(_: libc::c_int) {
+    INTERRUPTED.store(true, Ordering::Relaxed);
+}
+
+/// Compile a toy, returning the error shown in the status line on failure
+fn compile(renderer: &mut Renderer, toy: &Toy) -> Option<String> {
+    match renderer.compile(toy) {
+        Ok(()) => None,
+        Err(e) => {
+            log::error!("Error compiling: {:}", e);
+            Some(format!("Error compiling toy: {}", e))
+        }
+    }
+}
+
+/// Render a toy offscreen and stream its frames to the terminal until
+/// interrupted, recompiling it when the watcher sends a new definition
+pub fn run(
+    toy: Toy,
+    mut receiver: Receiver<Toy>,
+    mode: TerminalMode,
+) -> Result<(), Box<dyn Error + 'static>> {
+    let mode = mode.resolve(|name| std::env::var(name).ok());
+    let headless = Headless::new()?;
+    let mut size = TermSize::current();
+    let (w, h) = size.frame_size(mode);
+    let mut renderer = headless.renderer(w, h);
+    let mut target = renderer.new_frame_target();
+    let mut error = compile(&mut renderer, &toy);
+    let name = toy.root.display().to_string();
+
+    unsafe {
+        libc::signal(libc::SIGINT, interrupt as *const () as libc::sighandler_t);
+    }
+    let mut out = io::stdout().lock();
+    // draw on the alternate screen, without a cursor
+    out.write_all(b"\x1b[?1049h\x1b[?25l\x1b[2J")?;
+
+    let mut clock = Clock::default();
+    let step = Duration::from_secs_f32(1.0 / FPS);
+    let mut last_frame = Instant::now();
+    let mut result = Ok(());
+    while !INTERRUPTED.load(Ordering::Relaxed) {
+        let now = Instant::now();
+        if let Ok(Some(toy)) = receiver.try_next() {
+            error = compile(&mut renderer, &toy);
+        }
+        let current = TermSize::current();
+        if current != size {
+            size = current;
+            let (w, h) = size.frame_size(mode);
+            renderer.resize(w as f32, h as f32);
+            renderer.delete_frame_target(target);
+            target = renderer.new_frame_target();
+            let _ = out.write_all(b"\x1b[2J");
+        }
+
+        let elapsed = now.duration_since(last_frame).as_secs_f32();
+        last_frame = now;
+        let tick = clock
+            .tick(elapsed)
+            .expect("terminal playback is never paused");
+        tick.apply(&mut renderer.uniforms);
+        renderer.uniforms.set_date(&chrono::Local::now());
+        let mut image = renderer.capture(&target);
+        for pixel in image.pixels_mut() {
+            pixel[3] = 255;
+        }
+
+        let rows = size.rows.saturating_sub(1).max(1);
+        let mut frame = String::from("\x1b[H");
+        frame.push_str(&match mode {
+            TerminalMode::Kitty => kitty(&image, size.cols, rows),
+            TerminalMode::Sixel => sixel(&image),
+            TerminalMode::Blocks | TerminalMode::Auto => blocks(&image),
+        });
+        let status = match &error {
+            Some(error) => error.lines().next().unwrap_or_default().to_string(),
+            None => format!(
+                "{}  {:.1}s  {}x{}",
+                name,
+                tick.time,
+                image.width(),
+                image.height()
+            ),
+        };
+        let status: String = status.chars().take(size.cols as usize).collect();
+        let _ = write!(frame, "\x1b[{};1H\x1b[2K{}", size.rows, status);
+        if let Err(e) = out.write_all(frame.as_bytes()).and_then(|_| out.flush()) {
+            result = Err(e.into());
+            break;
+        }
+        thread::sleep(step.saturating_sub(now.elapsed()));
+    }
+
+    if mode == TerminalMode::Kitty {
+        let _ = out.write_all(b"\x1b_Ga=d,q=2\x1b\\");
+    }
+    let _ = out.write_all(b"\x1b[?25h\x1b[?1049l");
+    let _ = out.flush();
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use image::Rgba;
+
+    #[test]
+    fn mode_detection() {
+        let env = |vars: &'static [(&'static str, &'static str)]| {
+            move |name: &str| {
+                let var = vars.iter().find(|(n, _)| *n == name);
+                var.map(|(_, value)| value.to_string())
+            }
+        };
+        let auto = TerminalMode::Auto;
+        assert_eq!(
+            TerminalMode::Kitty,
+            auto.resolve(env(&[("TERM", "xterm-kitty")]))
+        );
+        assert_eq!(
+            TerminalMode::Kitty,
+            auto.resolve(env(&[("TERM_PROGRAM", "WezTerm")]))
+        );
+        assert_eq!(TerminalMode::Sixel, auto.resolve(env(&[("TERM", "foot")])));
+        assert_eq!(
+            TerminalMode::Blocks,
+            auto.resolve(env(&[("TERM", "xterm-256color")]))
+        );
+        // explicit modes are kept
+        assert_eq!(
+            TerminalMode::Sixel,
+            TerminalMode::Sixel.resolve(env(&[("TERM", "xterm-kitty")]))
+        );
+    }
+
+    #[test]
+    fn frame_sizes() {
+        let size = TermSize {
+            cols: 80,
+            rows: 25,
+            width: 800,
+            height: 500,
+        };
+        assert_eq!((400, 240), size.frame_size(TerminalMode::Kitty));
+        assert_eq!((800, 480), size.frame_size(TerminalMode::Sixel));
+        assert_eq!((80, 48), size.frame_size(TerminalMode::Blocks));
+        // cells are assumed 8x16 without a pixel size
+        let size = TermSize {
+            width: 0,
+            height: 0,
+            ..size
+        };
+        assert_eq!((640, 384), size.frame_size(TerminalMode::Sixel));
+    }
+
+    #[test]
+    fn base64_encoding() {
+        assert_eq!("", base64(b""));
+        assert_eq!("Zg==", base64(b"f"));
+        assert_eq!("Zm8=", base64(b"fo"));
+        assert_eq!("Zm9vYmFy", base64(b"foobar"));
+    }
+
+    #[test]
+    fn kitty_frames() {
+        let image = RgbaImage::from_pixel(2, 1, Rgba([255, 0, 0, 255]));
+        let frame = kitty(&image, 4, 2);
+        // the payload is a PNG, starting with its signature
+        assert!(
+            frame.starts_with("\x1b_Ga=T,f=100,i=1,p=1,c=4,r=2,C=1,q=2,m=0;iVBORw0KGgo"),
+            "{:?}",
+            frame
+        );
+        assert!(frame.ends_with("\x1b\\"));
+        assert_eq!(1, frame.matches("\x1b_G").count());
+
+        // large frames are split into chunks
+        let noise = crate::toy::builtin::random_bytes(64 * 64 * 4, 0);
+        let image = RgbaImage::from_raw(64, 64, noise).unwrap();
+        let frame = kitty(&image, 4, 2);
+        let chunks = frame.matches("\x1b_G").count();
+        assert!(chunks > 1);
+        assert_eq!(chunks - 1, frame.matches("m=1;").count());
+        assert!(frame.contains("\x1b_Gm=0;"));
+    }
+
+    #[test]
+    fn sixel_frames() {
+        // a red column over a blue one, seven rows tall to span two bands
+        let image = RgbaImage::from_fn(2, 7, |x, _| match x {
+            0 => Rgba([255, 0, 0, 255]),
+            _ => Rgba([0, 0, 255, 255]),
+        });
+        assert_eq!(
+            "\x1bPq\"1;1;2;7#5;2;0;0;100#180;2;100;0;0\
+             #5?~$#180~?-#5?@$#180@?-\x1b\\",
+            sixel(&image)
+        );
+    }
+
+    #[test]
+    fn block_frames() {
+        let image = RgbaImage::from_fn(2, 3, |_, y| Rgba([y as u8, 0, 0, 255]));
+        assert_eq!(
+            "\x1b[1;1H\x1b[38;2;0;0;0;48;2;1;0;0m▀▀\
+             \x1b[2;1H\x1b[38;2;2;0;0;48;2;2;0;0m▀▀\x1b[0m",
+            blocks(&image)
+        );
+    }
+}
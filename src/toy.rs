@@ -1,11 +1,32 @@
-use log;
-use std::path::Path;
-use std::{fs, io};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use std::{error, fs, io};
+
+use image::{DynamicImage, ImageReader};
 
 use serde::Deserialize;
 use serde::Serialize;
 
+use cubemap::{CubeError, CubeLayout};
+use image::RgbaImage;
+use include::{IncludeError, Includes};
+use shader::SamplerType;
+use track::Track;
+use video::Sequence;
+use volume::{Volume, VolumeError};
+
+pub use builtin::BuiltinName;
+
+pub mod builtin;
+pub mod cubemap;
+pub mod include;
 pub mod shader;
+pub mod track;
+pub mod video;
+pub mod volume;
 
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Copy, Default)]
 #[serde(rename_all = "snake_case")]
@@ -25,14 +46,99 @@ pub enum TextureWrap {
 }
 
 /// Channel configuration (texture, video, etc)
-#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Copy)]
-#[serde(untagged)]
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[serde(untagged, deny_unknown_fields)]
 pub enum ChannelConfig {
     Texture {
         vflip: bool,
         filter: TextureFilter,
         wrap: TextureWrap,
     },
+    /// Shadertoy's 256x3 keyboard texture, written `config = "keyboard"` on a
+    /// channel without any data source
+    #[serde(
+        serialize_with = "serialize_keyboard",
+        deserialize_with = "deserialize_keyboard"
+    )]
+    Keyboard,
+    /// Shadertoy's 512x2 audio texture of a local audio file played along
+    /// with `iTime`, holding the spectrum in row 0 and the waveform in row 1.
+    /// Written `config = { audio = "song.ogg" }` on a channel without any
+    /// data source
+    Audio {
+        /// Audio file, relative to the toy directory
+        #[serde(rename = "audio")]
+        path: String,
+    },
+    /// Frames of a local video played along with `iTime`, read from a
+    /// directory of images. Written `config = { video = "frames" }` on a
+    /// channel without any data source
+    Video {
+        /// Directory of frames, relative to the toy directory
+        #[serde(rename = "video")]
+        path: String,
+        /// Restart after the last frame rather than holding it
+        #[serde(rename = "loop", default = "default_video_loop")]
+        looping: bool,
+        /// Frames per second of playback
+        #[serde(default = "default_video_fps")]
+        fps: f32,
+    },
+    /// Cube map read with a `samplerCube`, written e.g.
+    /// `config = { cubemap = "image" }`. Faces are read from the channel's
+    /// `path` or built-in `name`, or from the Cube A pass without any source
+    Cubemap {
+        #[serde(rename = "cubemap")]
+        layout: CubeLayout,
+    },
+    /// 3D texture read with a `sampler3D`, with linear filtering and
+    /// repeating coordinates. Written `config = "volume"` on a channel reading
+    /// a volume file `path` or a built-in volume `name`
+    #[serde(
+        serialize_with = "serialize_volume",
+        deserialize_with = "deserialize_volume"
+    )]
+    Volume,
+}
+
+fn default_video_loop() -> bool {
+    true
+}
+
+fn default_video_fps() -> f32 {
+    30.0
+}
+
+fn serialize_keyboard<S: serde::Serializer>(serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str("keyboard")
+}
+
+fn deserialize_keyboard<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<(), D::Error> {
+    deserialize_tag(deserializer, "keyboard")
+}
+
+fn serialize_volume<S: serde::Serializer>(serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str("volume")
+}
+
+fn deserialize_volume<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<(), D::Error> {
+    deserialize_tag(deserializer, "volume")
+}
+
+/// Accept only the string `tag`, for configurations without any options
+fn deserialize_tag<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+    tag: &'static str,
+) -> Result<(), D::Error> {
+    let value = String::deserialize(deserializer)?;
+    if value == tag {
+        Ok(())
+    } else {
+        Err(serde::de::Error::invalid_value(
+            serde::de::Unexpected::Str(&value),
+            &format!("{:?}", tag).as_str(),
+        ))
+    }
 }
 
 impl Default for ChannelConfig {
@@ -45,14 +151,37 @@ impl Default for ChannelConfig {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Copy)]
-#[serde(rename_all = "snake_case")]
-pub enum BuiltinName {
-    RgbaNoiseSmall,
+/// Offscreen buffer passes (Shadertoy's Buffer A-D), rendered in order before
+/// the main image
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum BufferName {
+    A,
+    B,
+    C,
+    D,
+}
+
+impl BufferName {
+    pub const ALL: [BufferName; 4] = [BufferName::A, BufferName::B, BufferName::C, BufferName::D];
+
+    /// File containing the pass source, relative to the toy directory
+    pub fn file_name(&self) -> &'static str {
+        match self {
+            BufferName::A => "bufa.glsl",
+            BufferName::B => "bufb.glsl",
+            BufferName::C => "bufc.glsl",
+            BufferName::D => "bufd.glsl",
+        }
+    }
 }
 
+/// Channels a pass reads, `iChannel0` to `iChannel3`
+pub const MAX_CHANNELS: usize = 4;
+
 /// Channel definition
-#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[serde(deny_unknown_fields)]
 pub struct Channel {
     /// Built-in data for channel
     #[serde(default)]
@@ -62,36 +191,445 @@ pub struct Channel {
     #[serde(default)]
     pub path: Option<String>,
 
+    /// Buffer pass whose output is read by the channel
+    #[serde(default)]
+    pub buffer: Option<BufferName>,
+
     /// Configuration for the channel
     #[serde(default)]
     pub config: ChannelConfig,
 }
 
+/// Error resolving the data of a channel
+#[derive(Debug)]
+pub enum ChannelError {
+    /// The channel does not set exactly one of `name`, `path` and `buffer`,
+    /// or sets any of them for a keyboard, audio file, video or the Cube A
+    /// pass, or reads a buffer as a cube map or volume
+    Source,
+    /// The channel reads a buffer pass that is not defined
+    MissingBuffer(BufferName),
+    /// The channel reads the Cube A pass, which is not defined
+    MissingCube,
+    /// The channel file could not be read
+    Io(PathBuf, io::Error),
+    /// The channel data could not be decoded as an image
+    Image(image::ImageError),
+    /// The channel file could not be decoded as audio
+    Audio(PathBuf, symphonia::core::errors::Error),
+    /// The video directory contains no frames
+    NoFrames(PathBuf),
+    /// The video is played back at a rate that is not a positive number
+    VideoFps(f32),
+    /// The channel images do not make up a cube map
+    Cube(CubeError),
+    /// The channel data could not be decoded as a volume
+    Volume(VolumeError),
+    /// The built-in is read from the asset directory, where it is missing
+    MissingAsset(BuiltinName, Option<PathBuf>),
+    /// A pass has more channels than `iChannel0` to `iChannel3`
+    TooMany(usize),
+}
+
+impl fmt::Display for ChannelError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChannelError::Source => {
+                write!(
+                    f,
+                    "channel must set exactly one of `name`, `path` or `buffer`, \
+                     or none for keyboard, audio, video and cube map pass channels"
+                )
+            }
+            ChannelError::MissingBuffer(buffer) => write!(
+                f,
+                "channel reads buffer {:?} but {} is not defined",
+                buffer,
+                buffer.file_name()
+            ),
+            ChannelError::MissingCube => {
+                write!(
+                    f,
+                    "channel reads the Cube A pass but cubea.glsl is not defined"
+                )
+            }
+            ChannelError::Io(path, e) => write!(f, "error reading {:?}: {}", path, e),
+            ChannelError::Image(e) => write!(f, "error decoding channel image: {}", e),
+            ChannelError::Audio(path, e) => write!(f, "error decoding audio {:?}: {}", path, e),
+            ChannelError::NoFrames(path) => write!(f, "no video frames found in {:?}", path),
+            ChannelError::VideoFps(fps) => {
+                write!(f, "video fps must be a positive number, not {}", fps)
+            }
+            ChannelError::Cube(e) => write!(f, "error reading cube map: {}", e),
+            ChannelError::Volume(e) => write!(f, "error decoding volume: {}", e),
+            ChannelError::MissingAsset(name, dir) => write!(
+                f,
+                "built-in {} is not included, add {}.jpg or .png to the asset directory {:?} \
+                 (set by {})",
+                name.key(),
+                name.key(),
+                dir.as_deref().unwrap_or(Path::new("")),
+                builtin::ASSETS_VAR
+            ),
+            ChannelError::TooMany(count) => write!(
+                f,
+                "pass has {} channels, but reads at most {} (iChannel0 to iChannel{})",
+                count,
+                MAX_CHANNELS,
+                MAX_CHANNELS - 1
+            ),
+        }
+    }
+}
+
+impl error::Error for ChannelError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            ChannelError::Io(_, e) => Some(e),
+            ChannelError::Image(e) => Some(e),
+            ChannelError::Audio(_, e) => Some(e),
+            ChannelError::Cube(e) => Some(e),
+            ChannelError::Volume(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
 impl Channel {
-    pub fn get_bytes(&self) -> Vec<u8> {
-        match self.name {
-            Some(name) => match name {
-                BuiltinName::RgbaNoiseSmall => {
-                    include_bytes!("toy/res/rgba-noise-small.png").into()
+    /// Check that the channel has exactly one data source, or none for
+    /// keyboards, audio files, videos and the Cube A pass, and that videos
+    /// play back at a positive rate
+    pub fn validate(&self) -> Result<(), ChannelError> {
+        let sources = [
+            self.name.is_some(),
+            self.path.is_some(),
+            self.buffer.is_some(),
+        ];
+        let count = sources.iter().filter(|&&s| s).count();
+        let valid = match &self.config {
+            ChannelConfig::Keyboard
+            | ChannelConfig::Audio { .. }
+            | ChannelConfig::Video { .. }
+            | ChannelConfig::Cubemap {
+                layout: CubeLayout::Pass,
+            } => count == 0,
+            ChannelConfig::Cubemap { .. } | ChannelConfig::Volume => {
+                count == 1 && self.buffer.is_none()
+            }
+            ChannelConfig::Texture { .. } => count == 1,
+        };
+        if !valid {
+            return Err(ChannelError::Source);
+        }
+        if let ChannelConfig::Video { fps, .. } = self.config {
+            if !(fps.is_finite() && fps > 0.0) {
+                return Err(ChannelError::VideoFps(fps));
+            }
+        }
+        Ok(())
+    }
+
+    /// Type of the sampler the channel is read with
+    pub fn sampler(&self) -> SamplerType {
+        match self.config {
+            ChannelConfig::Cubemap { .. } => SamplerType::Cube,
+            ChannelConfig::Volume => SamplerType::Texture3D,
+            _ => SamplerType::Texture2D,
+        }
+    }
+
+    /// Raw channel data, reading `path` relative to the toy directory `root`
+    pub fn get_bytes(&self, root: &Path) -> Result<Vec<u8>, ChannelError> {
+        self.validate()?;
+        match (self.name, &self.path) {
+            (Some(name), _) => name.bytes(builtin::asset_dir().as_deref()),
+            (None, Some(path)) => {
+                let path = root.join(path);
+                fs::read(&path).map_err(|e| ChannelError::Io(path, e))
+            }
+            (None, None) => Err(ChannelError::Source),
+        }
+    }
+
+    /// Decode the channel data as an image, detecting the format from its content
+    pub fn load_image(&self, root: &Path) -> Result<DynamicImage, ChannelError> {
+        self.validate()?;
+        // generated built-ins are copied rather than encoded and decoded again
+        if let Some(image) = self.name.and_then(|name| name.generated()) {
+            return Ok(image.clone().into());
+        }
+        let bytes = self.get_bytes(root)?;
+        ImageReader::new(Cursor::new(bytes))
+            .with_guessed_format()
+            .expect("reading from memory")
+            .decode()
+            .map_err(ChannelError::Image)
+    }
+
+    /// Local file or directory read by the channel, relative to the toy
+    /// directory
+    pub fn file(&self) -> Option<&str> {
+        match &self.config {
+            ChannelConfig::Audio { path } | ChannelConfig::Video { path, .. } => Some(path),
+            _ => self.path.as_deref(),
+        }
+    }
+
+    /// Latest modification time of the channel's file, or of the files of
+    /// its directory, or `None` for channels without files
+    pub fn modified(&self, root: &Path) -> Option<SystemTime> {
+        let path = root.join(self.file()?);
+        let mut modified = fs::metadata(&path).and_then(|m| m.modified()).ok();
+        if let Ok(entries) = fs::read_dir(&path) {
+            for entry in entries.flatten() {
+                let time = entry.metadata().and_then(|m| m.modified()).ok();
+                modified = modified.max(time);
+            }
+        }
+        modified
+    }
+
+    /// Decode the audio file of an audio channel
+    pub fn load_audio(&self, root: &Path) -> Result<Track, ChannelError> {
+        self.validate()?;
+        let ChannelConfig::Audio { path } = &self.config else {
+            return Err(ChannelError::Source);
+        };
+        let path = root.join(path);
+        Track::decode(&path).map_err(|e| ChannelError::Audio(path, e))
+    }
+
+    /// Read the six faces of a cube map channel, in the order of
+    /// `cubemap::FACES`
+    pub fn load_cubemap(&self, root: &Path) -> Result<[RgbaImage; 6], ChannelError> {
+        self.validate()?;
+        let faces = match &self.config {
+            ChannelConfig::Cubemap {
+                layout: CubeLayout::Faces,
+            } => {
+                let dir = root.join(self.path.as_ref().ok_or(ChannelError::Source)?);
+                let mut faces = vec![];
+                for path in cubemap::face_paths(&dir) {
+                    let face = ImageReader::open(&path)
+                        .map_err(|e| ChannelError::Io(path, e))?
+                        .with_guessed_format()
+                        .expect("reading from a file")
+                        .decode()
+                        .map_err(ChannelError::Image)?;
+                    faces.push(face.into_rgba8());
                 }
-            },
-            None => todo!("Load from path instead"),
+                faces.try_into().expect("six faces")
+            }
+            ChannelConfig::Cubemap {
+                layout: CubeLayout::Image,
+            } => cubemap::split(&self.load_image(root)?).map_err(ChannelError::Cube)?,
+            _ => return Err(ChannelError::Source),
+        };
+        cubemap::check_faces(&faces).map_err(ChannelError::Cube)?;
+        Ok(faces)
+    }
+
+    /// Decode the channel data as a volume in Shadertoy's format
+    pub fn load_volume(&self, root: &Path) -> Result<Volume, ChannelError> {
+        Volume::parse(&self.get_bytes(root)?).map_err(ChannelError::Volume)
+    }
+
+    /// List the frames of a video channel
+    pub fn load_video(&self, root: &Path) -> Result<Sequence, ChannelError> {
+        self.validate()?;
+        let ChannelConfig::Video { path, looping, fps } = &self.config else {
+            return Err(ChannelError::Source);
+        };
+        let path = root.join(path);
+        let frames = video::frames(&path).map_err(|e| ChannelError::Io(path.clone(), e))?;
+        if frames.is_empty() {
+            return Err(ChannelError::NoFrames(path));
         }
+        Ok(Sequence {
+            frames,
+            fps: *fps,
+            looping: *looping,
+        })
+    }
+}
+
+/// Configuration of a buffer pass
+#[derive(Debug, Default, Serialize, Deserialize, PartialEq, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct PassConfig {
+    /// Channels read by this pass
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub channels: Vec<Channel>,
+}
+
+impl PassConfig {
+    pub fn is_empty(&self) -> bool {
+        self.channels.is_empty()
     }
 }
 
-#[derive(Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+fn default_test_frames() -> u32 {
+    1
+}
+
+fn default_test_fps() -> f32 {
+    60.0
+}
+
+fn default_test_size() -> (u32, u32) {
+    (320, 180)
+}
+
+fn default_test_tolerance() -> u8 {
+    2
+}
+
+/// Golden image test comparing a rendered frame against a reference image
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct TestCase {
+    /// Reference image, relative to the toy directory
+    pub reference: String,
+
+    /// Value of `iTime` for the first rendered frame
+    #[serde(default)]
+    pub time: f32,
+
+    /// Number of frames to render, comparing the last one
+    #[serde(default = "default_test_frames")]
+    pub frames: u32,
+
+    /// Frames per second of the fixed timestep
+    #[serde(default = "default_test_fps")]
+    pub fps: f32,
+
+    /// Resolution to render at
+    #[serde(default = "default_test_size")]
+    pub size: (u32, u32),
+
+    /// Largest allowed difference of any color channel of a pixel
+    #[serde(default = "default_test_tolerance")]
+    pub tolerance: u8,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize, PartialEq, Clone)]
+#[serde(deny_unknown_fields)]
 pub struct Config {
-    /// Channels defined for this toy
+    /// Channels defined for the main image
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub channels: Vec<Channel>,
+
+    /// Configuration of buffer passes
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub buffers: BTreeMap<BufferName, PassConfig>,
+
+    /// Configuration of the sound pass
+    #[serde(default, skip_serializing_if = "PassConfig::is_empty")]
+    pub sound: PassConfig,
+
+    /// Configuration of the Cube A pass
+    #[serde(default, skip_serializing_if = "PassConfig::is_empty")]
+    pub cube: PassConfig,
+
+    /// Directories searched for `#include`d files after the toy directory,
+    /// relative to it
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub include_paths: Vec<String>,
+
+    /// Golden image tests, run with `tasty test`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tests: Vec<TestCase>,
 }
 
-/// The definition of a Shader Toy
+/// Error loading `toy.toml`
 #[derive(Debug)]
+pub enum ConfigError {
+    /// The file could not be read
+    Io(io::Error),
+    /// The file is not a valid configuration
+    Parse(toml::de::Error),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(e) => write!(f, "error reading toy config: {}", e),
+            ConfigError::Parse(e) => write!(f, "error parsing toy config: {}", e),
+        }
+    }
+}
+
+impl error::Error for ConfigError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            ConfigError::Io(e) => Some(e),
+            ConfigError::Parse(e) => Some(e),
+        }
+    }
+}
+
+impl Config {
+    /// Load the `toy.toml` of the toy directory `path`, or the default
+    /// configuration when there is none
+    pub fn read<P>(path: P) -> Result<Config, ConfigError>
+    where
+        P: AsRef<Path>,
+    {
+        let config_path = path.as_ref().join("toy.toml");
+        if !config_path.exists() {
+            return Ok(Config::default());
+        }
+        let conf = fs::read_to_string(config_path).map_err(ConfigError::Io)?;
+        toml::from_str(&conf).map_err(ConfigError::Parse)
+    }
+
+    /// Channels of the main image followed by those of every buffer pass, the
+    /// sound pass and the Cube A pass
+    pub fn all_channels(&self) -> impl Iterator<Item = &Channel> {
+        self.channels
+            .iter()
+            .chain(self.buffers.values().flat_map(|p| p.channels.iter()))
+            .chain(self.sound.channels.iter())
+            .chain(self.cube.channels.iter())
+    }
+
+    /// Channels of every pass, along with where they are defined in
+    /// `toy.toml`, e.g. `buffers.a.channels`
+    pub fn pass_channels(&self) -> Vec<(String, &[Channel])> {
+        let mut passes = vec![("channels".to_string(), self.channels.as_slice())];
+        for (name, pass) in &self.buffers {
+            let key = format!("buffers.{:?}.channels", name).to_lowercase();
+            passes.push((key, pass.channels.as_slice()));
+        }
+        passes.push(("sound.channels".into(), &self.sound.channels));
+        passes.push(("cube.channels".into(), &self.cube.channels));
+        passes
+    }
+
+    /// Channels read by a buffer pass
+    pub fn buffer_channels(&self, buffer: BufferName) -> &[Channel] {
+        match self.buffers.get(&buffer) {
+            Some(pass) => pass.channels.as_slice(),
+            None => &[],
+        }
+    }
+}
+
+/// The definition of a Shader Toy
+#[derive(Debug, Clone)]
 pub struct Toy {
+    /// Directory the toy was loaded from, used to resolve channel paths
+    pub root: PathBuf,
     /// Main image definition
     pub main_image: String,
+    /// Code shared by every pass, from `common.glsl`
+    pub common: Option<String>,
+    /// Buffer pass definitions
+    pub buffers: BTreeMap<BufferName, String>,
+    /// Sound pass definition, from `sound.glsl`
+    pub sound: Option<String>,
+    /// Cube A pass definition, from `cubea.glsl`
+    pub cube: Option<String>,
     /// Configuration
     pub config: Config,
 }
@@ -99,7 +637,12 @@ pub struct Toy {
 impl Default for Toy {
     fn default() -> Self {
         Toy {
+            root: PathBuf::new(),
             main_image: shader::MAIN_IMAGE.into(),
+            common: None,
+            buffers: BTreeMap::new(),
+            sound: None,
+            cube: None,
             config: Config::default(),
         }
     }
@@ -110,7 +653,10 @@ impl Toy {
     where
         P: AsRef<Path>,
     {
-        let mut toy = Toy::default();
+        let mut toy = Toy {
+            root: path.as_ref().to_path_buf(),
+            ..Default::default()
+        };
 
         // load image.glsl
         let image_path = path.as_ref().join("image.glsl");
@@ -121,19 +667,53 @@ impl Toy {
             }
         }
 
-        // load config
-        let config_path = path.as_ref().join("toy.toml");
-        if config_path.exists() {
-            match fs::read_to_string(config_path) {
-                Ok(conf) => match toml::from_str(conf.as_str()) {
-                    Ok(conf) => toy.config = conf,
-                    Err(e) => log::error!("Error parsing toy config: {}", e),
-                },
-                Err(e) => log::error!("Error reading toy config: {}", e),
+        // load common code
+        let common_path = path.as_ref().join("common.glsl");
+        if common_path.exists() {
+            match fs::read_to_string(common_path) {
+                Ok(common) => toy.common = Some(common),
+                Err(e) => log::error!("Error reading common code: {}", e),
+            }
+        }
+
+        // load buffer passes
+        for buffer in BufferName::ALL {
+            let buffer_path = path.as_ref().join(buffer.file_name());
+            if buffer_path.exists() {
+                match fs::read_to_string(buffer_path) {
+                    Ok(source) => {
+                        toy.buffers.insert(buffer, source);
+                    }
+                    Err(e) => log::error!("Error reading buffer {:?}: {}", buffer, e),
+                }
+            }
+        }
+
+        // load sound pass
+        let sound_path = path.as_ref().join("sound.glsl");
+        if sound_path.exists() {
+            match fs::read_to_string(sound_path) {
+                Ok(sound) => toy.sound = Some(sound),
+                Err(e) => log::error!("Error reading sound pass: {}", e),
+            }
+        }
+
+        // load cube map pass
+        let cube_path = path.as_ref().join("cubea.glsl");
+        if cube_path.exists() {
+            match fs::read_to_string(cube_path) {
+                Ok(cube) => toy.cube = Some(cube),
+                Err(e) => log::error!("Error reading cube map pass: {}", e),
             }
         }
 
-        return toy;
+        // load config
+        match Config::read(&path) {
+            Ok(conf) => toy.config = conf,
+            Err(e) => log::error!("{}", e),
+        }
+
+        toy
     }
 
     pub fn write<P>(&self, path: P, overwrite: bool) -> io::Result<()>
@@ -147,6 +727,30 @@ impl Toy {
         if !image_path.exists() | overwrite {
             fs::write(image_path, &self.main_image)?;
         }
+        if let Some(common) = &self.common {
+            let common_path = path.as_ref().join("common.glsl");
+            if !common_path.exists() | overwrite {
+                fs::write(common_path, common)?;
+            }
+        }
+        for (buffer, source) in &self.buffers {
+            let buffer_path = path.as_ref().join(buffer.file_name());
+            if !buffer_path.exists() | overwrite {
+                fs::write(buffer_path, source)?;
+            }
+        }
+        if let Some(sound) = &self.sound {
+            let sound_path = path.as_ref().join("sound.glsl");
+            if !sound_path.exists() | overwrite {
+                fs::write(sound_path, sound)?;
+            }
+        }
+        if let Some(cube) = &self.cube {
+            let cube_path = path.as_ref().join("cubea.glsl");
+            if !cube_path.exists() | overwrite {
+                fs::write(cube_path, cube)?;
+            }
+        }
         let config_path = path.as_ref().join("toy.toml");
         if !config_path.exists() | overwrite {
             fs::write(
@@ -154,14 +758,141 @@ impl Toy {
                 toml::to_string(&self.config).expect("toy always serializable"),
             )?;
         }
-        return Ok(());
+        Ok(())
+    }
+
+    /// Files the toy definition is read from, whether or not they exist yet
+    pub fn sources(&self) -> Vec<PathBuf> {
+        let mut paths = vec![
+            self.root.join("image.glsl"),
+            self.root.join("common.glsl"),
+            self.root.join("sound.glsl"),
+            self.root.join("cubea.glsl"),
+            self.root.join("toy.toml"),
+        ];
+        paths.extend(BufferName::ALL.map(|b| self.root.join(b.file_name())));
+        paths.extend(
+            self.config
+                .all_channels()
+                .filter_map(|c| c.file())
+                .map(|p| self.root.join(p)),
+        );
+        for channel in self.config.all_channels() {
+            if let ChannelConfig::Video { path, .. } = &channel.config {
+                paths.extend(video::frames(&self.root.join(path)).unwrap_or_default());
+            }
+        }
+        let includes = self.includes();
+        let passes = self.common.iter().chain([&self.main_image]);
+        let passes = passes.chain(self.buffers.values()).chain(&self.sound);
+        for source in passes.chain(&self.cube) {
+            includes.dependencies(source, &self.root, &mut paths);
+        }
+        paths
+    }
+
+    /// Directories `#include` directives of the toy are resolved in
+    pub fn includes(&self) -> Includes {
+        Includes::new(&self.root, &self.config.include_paths)
+    }
+
+    /// Check that every pass reads at most `MAX_CHANNELS` channels, and that
+    /// every channel has a single source and reads only defined passes
+    pub fn validate(&self) -> Result<(), ChannelError> {
+        for (_, channels) in self.config.pass_channels() {
+            validate_channel_count(channels)?;
+        }
+        self.config
+            .all_channels()
+            .try_for_each(|channel| self.validate_channel(channel))
+    }
+
+    /// Check that a channel has a single source and reads only defined passes
+    pub fn validate_channel(&self, channel: &Channel) -> Result<(), ChannelError> {
+        channel.validate()?;
+        if let Some(buffer) = channel.buffer {
+            if !self.buffers.contains_key(&buffer) {
+                return Err(ChannelError::MissingBuffer(buffer));
+            }
+        }
+        let reads_cube = ChannelConfig::Cubemap {
+            layout: CubeLayout::Pass,
+        };
+        if channel.config == reads_cube && self.cube.is_none() {
+            return Err(ChannelError::MissingCube);
+        }
+        Ok(())
+    }
+
+    /// Fragment shader of the main image, with lines mapped to `image.glsl`
+    /// and the files it includes
+    pub fn fragment_shader(&self) -> Result<shader::Fragment, IncludeError> {
+        shader::build_fragment_shader(
+            self.common.as_deref(),
+            &self.main_image,
+            "image.glsl",
+            &samplers(&self.config.channels),
+            &self.includes(),
+        )
+    }
+
+    /// Fragment shader of the sound pass, with lines mapped to `sound.glsl`
+    /// and the files it includes
+    pub fn sound_shader(&self) -> Option<Result<shader::Fragment, IncludeError>> {
+        self.sound.as_ref().map(|source| {
+            shader::build_sound_shader(
+                self.common.as_deref(),
+                source,
+                &samplers(&self.config.sound.channels),
+                &self.includes(),
+            )
+        })
+    }
+
+    /// Fragment shader of the Cube A pass, with lines mapped to `cubea.glsl`
+    /// and the files it includes
+    pub fn cube_shader(&self) -> Option<Result<shader::Fragment, IncludeError>> {
+        self.cube.as_ref().map(|source| {
+            shader::build_cube_shader(
+                self.common.as_deref(),
+                source,
+                &samplers(&self.config.cube.channels),
+                &self.includes(),
+            )
+        })
     }
 
-    pub fn fragment_shader(&self) -> String {
-        return shader::build_fragment_shader(self.main_image.as_str());
+    /// Fragment shader of a buffer pass, with lines mapped to its file and the
+    /// files it includes
+    pub fn buffer_fragment_shader(
+        &self,
+        buffer: BufferName,
+    ) -> Option<Result<shader::Fragment, IncludeError>> {
+        self.buffers.get(&buffer).map(|source| {
+            shader::build_fragment_shader(
+                self.common.as_deref(),
+                source,
+                buffer.file_name(),
+                &samplers(self.config.buffer_channels(buffer)),
+                &self.includes(),
+            )
+        })
     }
 }
 
+/// Check that a pass reads at most `MAX_CHANNELS` channels
+pub fn validate_channel_count(channels: &[Channel]) -> Result<(), ChannelError> {
+    if channels.len() > MAX_CHANNELS {
+        return Err(ChannelError::TooMany(channels.len()));
+    }
+    Ok(())
+}
+
+/// Sampler types of the channels of a pass
+fn samplers(channels: &[Channel]) -> Vec<SamplerType> {
+    channels.iter().map(Channel::sampler).collect()
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -176,17 +907,18 @@ mod test {
         let chan = Channel {
             name: Some(BuiltinName::RgbaNoiseSmall),
             path: None,
+            buffer: None,
             config: ChannelConfig::default(),
         };
         toy.config.channels = vec![chan];
 
         let tmp_dir = TempDir::new("example").unwrap().into_path();
         let _ = toy.write(&tmp_dir, false);
-        let data = fs::read_to_string(&tmp_dir.clone().join("image.glsl")).unwrap();
+        let data = fs::read_to_string(tmp_dir.join("image.glsl")).unwrap();
         assert_eq!(data, toy.main_image);
 
         let conf: Config = toml::from_str(
-            fs::read_to_string(&tmp_dir.clone().join("toy.toml"))
+            fs::read_to_string(tmp_dir.join("toy.toml"))
                 .unwrap()
                 .as_str(),
         )
@@ -210,11 +942,14 @@ mod test {
 
     #[test]
     fn toy_partial_def_err() {
-        let mut toy = Toy::default();
-        toy.main_image = "test".into();
+        let mut toy = Toy {
+            main_image: "test".into(),
+            ..Default::default()
+        };
         let chan = Channel {
             name: Some(BuiltinName::RgbaNoiseSmall),
             path: None,
+            buffer: None,
             config: ChannelConfig::default(),
         };
         toy.config.channels = vec![chan];
@@ -224,21 +959,509 @@ mod test {
 
         // successful partial read with broken config
         toy.write(&tmp_dir, true).unwrap();
-        fs::write(&tmp_dir.join("toy.toml"), "foobarbaz").unwrap();
+        fs::write(tmp_dir.join("toy.toml"), "foobarbaz").unwrap();
         let read = Toy::from_path(&tmp_dir);
         assert_eq!(String::from_str("test").unwrap(), read.main_image);
 
         // successful partial read with missing main image
         toy.write(&tmp_dir, true).unwrap();
-        fs::remove_file(&tmp_dir.join("image.glsl")).unwrap();
+        fs::remove_file(tmp_dir.join("image.glsl")).unwrap();
         let read = Toy::from_path(tmp_dir);
         assert_eq!(toy.config, read.config);
     }
 
+    #[test]
+    fn toy_buffers_round_trip() {
+        let mut toy = Toy::default();
+        toy.buffers.insert(BufferName::A, "buffer a".into());
+        toy.buffers.insert(BufferName::C, "buffer c".into());
+        let feedback = Channel {
+            name: None,
+            path: None,
+            buffer: Some(BufferName::A),
+            config: ChannelConfig::default(),
+        };
+        toy.config.channels = vec![feedback.clone()];
+        toy.config.buffers.insert(
+            BufferName::A,
+            PassConfig {
+                channels: vec![feedback],
+            },
+        );
+
+        let tmp_dir = TempDir::new("toy_buffers").unwrap().into_path();
+        toy.write(&tmp_dir, true).unwrap();
+        assert!(tmp_dir.join("bufa.glsl").exists());
+        assert!(!tmp_dir.join("bufb.glsl").exists());
+
+        let read = Toy::from_path(&tmp_dir);
+        assert_eq!(toy.buffers, read.buffers);
+        assert_eq!(toy.config, read.config);
+        assert_eq!(1, read.config.buffer_channels(BufferName::A).len());
+        assert!(read.config.buffer_channels(BufferName::C).is_empty());
+    }
+
+    #[test]
+    fn channel_validation() {
+        let mut chan = Channel {
+            name: Some(BuiltinName::RgbaNoiseSmall),
+            path: Some("noise.png".into()),
+            buffer: None,
+            config: ChannelConfig::default(),
+        };
+        assert!(matches!(chan.validate(), Err(ChannelError::Source)));
+
+        chan.name = None;
+        assert!(chan.validate().is_ok());
+
+        chan.path = None;
+        assert!(matches!(chan.validate(), Err(ChannelError::Source)));
+
+        let mut toy = Toy::default();
+        chan.buffer = Some(BufferName::B);
+        toy.config.channels = vec![chan];
+        assert!(matches!(
+            toy.validate(),
+            Err(ChannelError::MissingBuffer(BufferName::B))
+        ));
+        toy.buffers.insert(BufferName::B, "test".into());
+        assert!(toy.validate().is_ok());
+
+        // passes read at most iChannel0 to iChannel3
+        let keyboard = Channel {
+            name: None,
+            path: None,
+            buffer: None,
+            config: ChannelConfig::Keyboard,
+        };
+        toy.config.sound.channels = vec![keyboard; 5];
+        assert!(matches!(toy.validate(), Err(ChannelError::TooMany(5))));
+        toy.config.sound.channels.pop();
+        assert!(toy.validate().is_ok());
+    }
+
+    #[test]
+    fn keyboard_channel() {
+        let mut chan = Channel {
+            name: None,
+            path: None,
+            buffer: None,
+            config: ChannelConfig::Keyboard,
+        };
+        assert!(chan.validate().is_ok());
+
+        let conf: Config = toml::from_str("[[channels]]\nconfig = \"keyboard\"").unwrap();
+        assert_eq!(vec![chan.clone()], conf.channels);
+        let written = toml::to_string(&conf).unwrap();
+        assert_eq!(conf, toml::from_str(&written).unwrap());
+        assert!(toml::from_str::<Config>("[[channels]]\nconfig = \"mouse\"").is_err());
+
+        // keyboards have no data source
+        chan.name = Some(BuiltinName::RgbaNoiseSmall);
+        assert!(matches!(chan.validate(), Err(ChannelError::Source)));
+    }
+
+    #[test]
+    fn audio_channel() {
+        let conf: Config =
+            toml::from_str("[[channels]]\nconfig = { audio = \"music/song.ogg\" }").unwrap();
+        let chan = &conf.channels[0];
+        assert_eq!(
+            ChannelConfig::Audio {
+                path: "music/song.ogg".into()
+            },
+            chan.config
+        );
+        assert!(chan.validate().is_ok());
+        let written = toml::to_string(&conf).unwrap();
+        assert_eq!(conf, toml::from_str(&written).unwrap());
+
+        // the audio file is watched, and missing files are reported
+        let toy = Toy {
+            root: "toy".into(),
+            config: conf.clone(),
+            ..Default::default()
+        };
+        assert!(toy.sources().contains(&PathBuf::from("toy/music/song.ogg")));
+        assert!(matches!(
+            chan.load_audio(Path::new("toy")),
+            Err(ChannelError::Audio(_, _))
+        ));
+
+        // audio files are the only data source
+        let chan = Channel {
+            path: Some("noise.png".into()),
+            ..chan.clone()
+        };
+        assert!(matches!(chan.validate(), Err(ChannelError::Source)));
+    }
+
+    #[test]
+    fn video_channel() {
+        let tmp_dir = TempDir::new("video_channel").unwrap().into_path();
+        let conf: Config = toml::from_str("[[channels]]\nconfig = { video = \"frames\" }").unwrap();
+        let chan = &conf.channels[0];
+        assert_eq!(
+            ChannelConfig::Video {
+                path: "frames".into(),
+                looping: true,
+                fps: 30.0
+            },
+            chan.config
+        );
+        assert!(chan.validate().is_ok());
+        let written = toml::to_string(&conf).unwrap();
+        assert_eq!(conf, toml::from_str(&written).unwrap());
+
+        // playback rates must be positive
+        for fps in ["0", "-1", "nan", "inf"] {
+            let conf: Config = toml::from_str(&format!(
+                "[[channels]]\nconfig = {{ video = \"frames\", fps = {} }}",
+                fps
+            ))
+            .unwrap();
+            assert!(matches!(
+                conf.channels[0].validate(),
+                Err(ChannelError::VideoFps(_))
+            ));
+        }
+
+        // missing and empty directories are reported
+        assert!(matches!(
+            chan.load_video(&tmp_dir),
+            Err(ChannelError::Io(_, _))
+        ));
+        fs::create_dir(tmp_dir.join("frames")).unwrap();
+        assert!(matches!(
+            chan.load_video(&tmp_dir),
+            Err(ChannelError::NoFrames(_))
+        ));
+
+        // frames are watched along with their directory
+        fs::write(tmp_dir.join("frames/0001.png"), "").unwrap();
+        let toy = Toy {
+            root: tmp_dir.clone(),
+            config: conf.clone(),
+            ..Default::default()
+        };
+        let sources = toy.sources();
+        assert!(sources.contains(&tmp_dir.join("frames")));
+        assert!(sources.contains(&tmp_dir.join("frames/0001.png")));
+        let sequence = chan.load_video(&tmp_dir).unwrap();
+        assert_eq!(vec![tmp_dir.join("frames/0001.png")], sequence.frames);
+
+        // reloads compare the latest modification of the frames
+        let frame = fs::metadata(tmp_dir.join("frames/0001.png")).unwrap();
+        assert!(chan.modified(&tmp_dir) >= Some(frame.modified().unwrap()));
+        let keyboard: Config = toml::from_str("[[channels]]\nconfig = \"keyboard\"").unwrap();
+        assert_eq!(None, keyboard.channels[0].modified(&tmp_dir));
+    }
+
+    #[test]
+    fn cubemap_channel() {
+        let conf: Config =
+            toml::from_str("[[channels]]\nname = \"sky\"\nconfig = { cubemap = \"image\" }")
+                .unwrap();
+        let mut chan = conf.channels[0].clone();
+        assert_eq!(
+            ChannelConfig::Cubemap {
+                layout: CubeLayout::Image
+            },
+            chan.config
+        );
+        assert_eq!(
+            conf,
+            toml::from_str(&toml::to_string(&conf).unwrap()).unwrap()
+        );
+
+        // built-in panoramas and crosses split into square faces
+        let faces = chan.load_cubemap(Path::new("")).unwrap();
+        assert_eq!((128, 128), faces[0].dimensions());
+        chan.name = Some(BuiltinName::CubeFaces);
+        assert_eq!(
+            (64, 64),
+            chan.load_cubemap(Path::new("")).unwrap()[5].dimensions()
+        );
+        chan.name = Some(BuiltinName::RgbaNoiseSmall);
+        assert!(matches!(
+            chan.load_cubemap(Path::new("")),
+            Err(ChannelError::Cube(CubeError::Layout(_, _)))
+        ));
+
+        // cube maps are read from a single image or directory, and not from buffers
+        chan.name = None;
+        chan.buffer = Some(BufferName::A);
+        assert!(matches!(chan.validate(), Err(ChannelError::Source)));
+
+        // the cube pass is read without a source, and must be defined
+        let chan = Channel {
+            name: None,
+            path: None,
+            buffer: None,
+            config: ChannelConfig::Cubemap {
+                layout: CubeLayout::Pass,
+            },
+        };
+        assert!(chan.validate().is_ok());
+        let mut toy = Toy::default();
+        toy.config.channels = vec![chan.clone()];
+        assert!(matches!(toy.validate(), Err(ChannelError::MissingCube)));
+        assert!(toy
+            .fragment_shader()
+            .unwrap()
+            .source
+            .contains("uniform samplerCube iChannel0;"));
+
+        toy.cube = Some("void mainCubemap(out vec4 c, vec2 p, vec3 ro, vec3 rd) {}".into());
+        toy.config.cube.channels = vec![chan];
+        assert!(toy.validate().is_ok());
+        let shader = toy.cube_shader().unwrap().unwrap();
+        assert!(shader.source.contains("iCubeFace"));
+
+        // the pass is written to cubea.glsl
+        let tmp_dir = TempDir::new("cubemap_channel").unwrap().into_path();
+        toy.write(&tmp_dir, true).unwrap();
+        let read = Toy::from_path(&tmp_dir);
+        assert_eq!(toy.cube, read.cube);
+        assert_eq!(toy.config, read.config);
+        assert!(read.sources().contains(&tmp_dir.join("cubea.glsl")));
+    }
+
+    #[test]
+    fn volume_channel() {
+        let tmp_dir = TempDir::new("volume_channel").unwrap().into_path();
+        let conf: Config =
+            toml::from_str("[[channels]]\nname = \"grey_noise3d\"\nconfig = \"volume\"").unwrap();
+        let mut chan = conf.channels[0].clone();
+        assert_eq!(ChannelConfig::Volume, chan.config);
+        assert_eq!(SamplerType::Texture3D, chan.sampler());
+        assert_eq!(
+            conf,
+            toml::from_str(&toml::to_string(&conf).unwrap()).unwrap()
+        );
+        assert!(toml::from_str::<Config>("[[channels]]\nconfig = \"volumes\"").is_err());
+
+        let volume = chan.load_volume(&tmp_dir).unwrap();
+        assert_eq!(
+            (32, 32, 32, 1),
+            (volume.width, volume.height, volume.depth, volume.channels)
+        );
+
+        // volume files are read from paths, and images are not volumes
+        chan.name = None;
+        chan.path = Some("noise.bin".into());
+        fs::write(tmp_dir.join("noise.bin"), Volume::noise(2, 4).to_bytes()).unwrap();
+        assert_eq!(Volume::noise(2, 4), chan.load_volume(&tmp_dir).unwrap());
+        chan.path = None;
+        chan.name = Some(BuiltinName::RgbaNoiseSmall);
+        assert!(matches!(
+            chan.load_volume(&tmp_dir),
+            Err(ChannelError::Volume(VolumeError::Header))
+        ));
+
+        chan.name = None;
+        chan.buffer = Some(BufferName::A);
+        assert!(matches!(chan.validate(), Err(ChannelError::Source)));
+    }
+
+    #[test]
+    fn channel_from_path() {
+        let tmp_dir = TempDir::new("channel_from_path").unwrap().into_path();
+        let chan = Channel {
+            name: None,
+            path: Some("noise".into()),
+            buffer: None,
+            config: ChannelConfig::default(),
+        };
+
+        // missing files are reported
+        assert!(matches!(
+            chan.load_image(&tmp_dir),
+            Err(ChannelError::Io(_, _))
+        ));
+
+        // format is detected from content rather than extension
+        let builtin = Channel {
+            name: Some(BuiltinName::RgbaNoiseSmall),
+            path: None,
+            buffer: None,
+            config: ChannelConfig::default(),
+        };
+        let expected = builtin.load_image(&tmp_dir).unwrap();
+        let mut jpeg = Vec::new();
+        expected
+            .to_rgb8()
+            .write_to(&mut Cursor::new(&mut jpeg), image::ImageFormat::Jpeg)
+            .unwrap();
+        fs::write(tmp_dir.join("noise"), jpeg).unwrap();
+        let im = chan.load_image(&tmp_dir).unwrap();
+        assert_eq!(expected.width(), im.width());
+        assert_eq!(expected.height(), im.height());
+
+        // undecodable data is an error rather than a panic
+        fs::write(tmp_dir.join("noise"), "foobarbaz").unwrap();
+        assert!(matches!(
+            chan.load_image(&tmp_dir),
+            Err(ChannelError::Image(_))
+        ));
+    }
+
     #[test]
     fn create_frag_shader() {
         let toy = Toy::default();
-        let frag = toy.fragment_shader();
-        assert!(frag.contains(shader::MAIN_IMAGE));
+        let frag = toy.fragment_shader().unwrap();
+        assert!(frag.source.contains(shader::MAIN_IMAGE));
+        assert!(toy.buffer_fragment_shader(BufferName::A).is_none());
+    }
+
+    #[test]
+    fn toy_common_code() {
+        let mut toy = Toy {
+            common: Some("float common_a;\nfloat common_b;".into()),
+            ..Default::default()
+        };
+        toy.buffers.insert(BufferName::A, "float x;".into());
+
+        let tmp_dir = TempDir::new("toy_common_code").unwrap().into_path();
+        toy.write(&tmp_dir, true).unwrap();
+        let read = Toy::from_path(&tmp_dir);
+        assert_eq!(toy.common, read.common);
+        assert!(Toy::from_path(TempDir::new("empty").unwrap().path())
+            .common
+            .is_none());
+
+        // common code precedes the pass source in every pass
+        for frag in [
+            read.fragment_shader().unwrap(),
+            read.buffer_fragment_shader(BufferName::A).unwrap().unwrap(),
+        ] {
+            let lines: Vec<&str> = frag.source.lines().collect();
+            let common = lines.iter().position(|l| *l == "float common_b;").unwrap() + 1;
+            assert!(common < lines.iter().position(|l| l.contains("mainImage")).unwrap());
+            assert_eq!(Some(("common.glsl", 2)), frag.map.lookup(common));
+        }
+    }
+
+    #[test]
+    fn toy_sound_pass() {
+        let mut toy = Toy {
+            sound: Some("vec2 mainSound(int samp, float time) {\n    return vec2(0.0);\n}".into()),
+            ..Default::default()
+        };
+        toy.config.sound.channels = vec![Channel {
+            name: Some(BuiltinName::RgbaNoiseSmall),
+            path: None,
+            buffer: None,
+            config: ChannelConfig::default(),
+        }];
+
+        let tmp_dir = TempDir::new("toy_sound_pass").unwrap().into_path();
+        toy.write(&tmp_dir, true).unwrap();
+        let read = Toy::from_path(&tmp_dir);
+        assert_eq!(toy.sound, read.sound);
+        assert_eq!(toy.config, read.config);
+        assert_eq!(1, read.config.all_channels().count());
+        assert!(read.sources().contains(&tmp_dir.join("sound.glsl")));
+
+        let frag = read.sound_shader().unwrap().unwrap();
+        assert!(frag.source.contains("mainSound(iSampleOffset"));
+        let line = frag
+            .source
+            .lines()
+            .position(|l| l.contains("return vec2"))
+            .unwrap()
+            + 1;
+        assert_eq!(Some(("sound.glsl", 2)), frag.map.lookup(line));
+        assert!(Toy::default().sound_shader().is_none());
+    }
+
+    #[test]
+    fn frag_shader_source_map() {
+        let mut toy = Toy::default();
+        toy.buffers
+            .insert(BufferName::B, "float x;\nfloat y;\nfloat z;".into());
+        let frag = toy.buffer_fragment_shader(BufferName::B).unwrap().unwrap();
+        let lines: Vec<&str> = frag.source.lines().collect();
+        let start = lines.iter().position(|l| *l == "float x;").unwrap() + 1;
+
+        assert_eq!(Some(("bufb.glsl", 1)), frag.map.lookup(start));
+        assert_eq!(Some(("bufb.glsl", 3)), frag.map.lookup(start + 2));
+        assert_eq!(None, frag.map.lookup(start + 3));
+        assert_eq!(None, frag.map.lookup(1));
+
+        // common driver log formats
+        let log = format!(
+            "0:{}(5): error: syntax error\n0({}) : error C0000: bad\nERROR: 0:{}: oops\nlink failed",
+            start + 1,
+            start + 2,
+            start
+        );
+        assert_eq!(
+            "bufb.glsl:2(5): error: syntax error\n\
+             bufb.glsl:3 : error C0000: bad\n\
+             ERROR: bufb.glsl:1: oops\n\
+             link failed",
+            frag.map.remap_log(&log)
+        );
+
+        // lines of the prelude are left untouched
+        assert_eq!("0:1(1): error", frag.map.remap_log("0:1(1): error"));
+    }
+
+    #[test]
+    fn frag_shader_includes() {
+        let tmp_dir = TempDir::new("frag_shader_includes").unwrap().into_path();
+        let lib = tmp_dir.join("lib");
+        fs::create_dir_all(&lib).unwrap();
+        fs::write(
+            lib.join("noise.glsl"),
+            "float n1;\n#include \"util.glsl\"\nfloat n3;",
+        )
+        .unwrap();
+        fs::write(lib.join("util.glsl"), "float u1;").unwrap();
+        let mut toy = Toy {
+            root: tmp_dir.clone(),
+            main_image: "float i1;\n#include \"noise.glsl\"\nfloat i3;".into(),
+            ..Default::default()
+        };
+        toy.config.include_paths = vec!["lib".into()];
+
+        let frag = toy.fragment_shader().unwrap();
+        assert!(!frag.source.contains("#include"));
+        let line = |code: &str| frag.source.lines().position(|l| l == code).unwrap() + 1;
+        for (code, file, number) in [
+            ("float i1;", "image.glsl", 1),
+            ("float n1;", "noise.glsl", 1),
+            ("float u1;", "util.glsl", 1),
+            ("float n3;", "noise.glsl", 3),
+            ("float i3;", "image.glsl", 3),
+        ] {
+            assert_eq!(
+                Some((file, number)),
+                frag.map.lookup(line(code)),
+                "{}",
+                code
+            );
+        }
+        assert!(line("float i1;") < line("float n1;"));
+        assert!(line("float u1;") < line("float n3;"));
+
+        // included files are sources of the toy
+        let sources = toy.sources();
+        assert!(sources.contains(&tmp_dir.join("lib/noise.glsl")));
+        assert!(sources.contains(&lib.join("util.glsl")));
+
+        // cycles and missing files are errors referring to the include
+        fs::write(lib.join("util.glsl"), "#include \"noise.glsl\"").unwrap();
+        assert!(matches!(
+            toy.fragment_shader(),
+            Err(IncludeError::Cycle { line: 1, .. })
+        ));
+        toy.main_image = "\n#include \"missing.glsl\"".into();
+        let err = toy.fragment_shader().unwrap_err();
+        assert_eq!(
+            "image.glsl:2: included file \"missing.glsl\" not found",
+            err.to_string()
+        );
     }
 }
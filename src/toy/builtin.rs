@@ -0,0 +1,402 @@
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use font8x8::legacy::{BASIC_LEGACY, LATIN_LEGACY};
+use image::{ImageFormat, Rgba, RgbaImage};
+use serde::{Deserialize, Serialize};
+
+use super::cubemap::CubeLayout;
+use super::volume::{self, Volume};
+use super::{ChannelConfig, ChannelError, TextureFilter, TextureWrap};
+
+/// Environment variable overriding the asset directory
+pub const ASSETS_VAR: &str = "TASTY_ASSETS";
+
+/// Images of generated built-ins by position in `BuiltinName::ALL`, generated
+/// when first loaded
+static GENERATED: [OnceLock<RgbaImage>; BuiltinName::ALL.len()] =
+    [const { OnceLock::new() }; BuiltinName::ALL.len()];
+
+/// Built-in channel data, standing in for Shadertoy's media
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum BuiltinName {
+    RgbaNoiseSmall,
+    /// Equirectangular sky with a sun, for cube map channels
+    Sky,
+    /// Cross of distinctly colored faces, each with its top left corner
+    /// marked, for checking the orientation of cube map channels
+    CubeFaces,
+    /// 32x32x32 grey noise, for volume channels
+    #[serde(rename = "grey_noise3d")]
+    GreyNoise3D,
+    /// 32x32x32 RGBA noise, for volume channels
+    #[serde(rename = "rgba_noise3d")]
+    RgbaNoise3D,
+    RgbaNoiseMedium,
+    GrayNoiseSmall,
+    GrayNoiseMedium,
+    /// 8x8 ordered dithering matrix
+    Bayer,
+    /// 16x16 grid of glyphs indexed by character code, with coverage in red
+    /// and a signed distance in alpha
+    Font1,
+    Organic1,
+    Abstract1,
+    London,
+}
+
+/// Where the data of a built-in comes from
+#[derive(Debug, Clone, Copy)]
+pub enum Source {
+    /// Image compiled into the binary
+    Embedded(&'static [u8]),
+    /// Image generated when loaded
+    Generated(fn() -> RgbaImage),
+    /// Volume of noise generated when loaded, with the given components
+    Volume(u8),
+    /// Image file of the asset directory, named after the built-in with a
+    /// `.png` or `.jpg` extension. Shadertoy's photos cannot be distributed,
+    /// so they are provided by the user
+    Asset,
+}
+
+impl BuiltinName {
+    pub const ALL: [BuiltinName; 13] = [
+        BuiltinName::RgbaNoiseSmall,
+        BuiltinName::RgbaNoiseMedium,
+        BuiltinName::GrayNoiseSmall,
+        BuiltinName::GrayNoiseMedium,
+        BuiltinName::Bayer,
+        BuiltinName::Font1,
+        BuiltinName::Organic1,
+        BuiltinName::Abstract1,
+        BuiltinName::London,
+        BuiltinName::Sky,
+        BuiltinName::CubeFaces,
+        BuiltinName::GreyNoise3D,
+        BuiltinName::RgbaNoise3D,
+    ];
+
+    /// Name of the built-in in `toy.toml`
+    pub fn key(&self) -> String {
+        toml::Value::try_from(self)
+            .ok()
+            .and_then(|v| v.as_str().map(String::from))
+            .expect("built-in names serialize to strings")
+    }
+
+    pub fn source(&self) -> Source {
+        match self {
+            BuiltinName::RgbaNoiseSmall => {
+                Source::Embedded(include_bytes!("res/rgba-noise-small.png"))
+            }
+            BuiltinName::Sky => Source::Embedded(include_bytes!("res/sky.png")),
+            BuiltinName::CubeFaces => Source::Embedded(include_bytes!("res/cube-faces.png")),
+            BuiltinName::GreyNoise3D => Source::Volume(1),
+            BuiltinName::RgbaNoise3D => Source::Volume(4),
+            BuiltinName::RgbaNoiseMedium => Source::Generated(|| rgba_noise(256, 1)),
+            BuiltinName::GrayNoiseSmall => Source::Generated(|| gray_noise(64, 2)),
+            BuiltinName::GrayNoiseMedium => Source::Generated(|| gray_noise(256, 3)),
+            BuiltinName::Bayer => Source::Generated(bayer),
+            BuiltinName::Font1 => Source::Generated(font),
+            BuiltinName::Organic1 | BuiltinName::Abstract1 | BuiltinName::London => Source::Asset,
+        }
+    }
+
+    /// Channel configuration the built-in is meant to be read with
+    pub fn config(&self) -> ChannelConfig {
+        let texture = |vflip, filter, wrap| ChannelConfig::Texture {
+            vflip,
+            filter,
+            wrap,
+        };
+        match self {
+            // noise is looked up at texel offsets, so it is not flipped
+            BuiltinName::RgbaNoiseSmall
+            | BuiltinName::RgbaNoiseMedium
+            | BuiltinName::GrayNoiseSmall
+            | BuiltinName::GrayNoiseMedium => {
+                texture(false, TextureFilter::Linear, TextureWrap::Repeat)
+            }
+            BuiltinName::Bayer => texture(false, TextureFilter::Nearest, TextureWrap::Repeat),
+            BuiltinName::Font1 => texture(false, TextureFilter::Mipmap, TextureWrap::Clamp),
+            BuiltinName::Organic1 | BuiltinName::Abstract1 | BuiltinName::London => {
+                texture(true, TextureFilter::Mipmap, TextureWrap::Repeat)
+            }
+            BuiltinName::Sky | BuiltinName::CubeFaces => ChannelConfig::Cubemap {
+                layout: CubeLayout::Image,
+            },
+            BuiltinName::GreyNoise3D | BuiltinName::RgbaNoise3D => ChannelConfig::Volume,
+        }
+    }
+
+    /// Image of a generated built-in, generated once and then shared by every
+    /// channel reading it
+    pub fn generated(&self) -> Option<&'static RgbaImage> {
+        let Source::Generated(generate) = self.source() else {
+            return None;
+        };
+        let index = Self::ALL
+            .iter()
+            .position(|name| name == self)
+            .expect("every built-in is listed");
+        Some(GENERATED[index].get_or_init(generate))
+    }
+
+    /// Encoded data of the built-in, looking up assets in `assets`
+    pub fn bytes(&self, assets: Option<&Path>) -> Result<Vec<u8>, ChannelError> {
+        match self.source() {
+            Source::Embedded(bytes) => Ok(bytes.into()),
+            Source::Generated(_) => {
+                let mut bytes = vec![];
+                self.generated()
+                    .expect("built-in is generated")
+                    .write_to(&mut Cursor::new(&mut bytes), ImageFormat::Png)
+                    .map_err(ChannelError::Image)?;
+                Ok(bytes)
+            }
+            Source::Volume(channels) => Ok(Volume::noise(volume::NOISE_SIZE, channels).to_bytes()),
+            Source::Asset => {
+                let path = self
+                    .asset_path(assets)
+                    .ok_or_else(|| ChannelError::MissingAsset(*self, assets.map(Path::to_owned)))?;
+                std::fs::read(&path).map_err(|e| ChannelError::Io(path, e))
+            }
+        }
+    }
+
+    /// File of an asset built-in in `assets`, if it exists
+    pub fn asset_path(&self, assets: Option<&Path>) -> Option<PathBuf> {
+        let dir = assets?;
+        ["png", "jpg", "jpeg"]
+            .iter()
+            .map(|ext| dir.join(self.key()).with_extension(ext))
+            .find(|p| p.is_file())
+    }
+}
+
+/// Directory asset built-ins are read from: `$TASTY_ASSETS`, or `tasty/assets`
+/// in the user's data directory, `$XDG_DATA_HOME` or else `~/.local/share`
+pub fn asset_dir() -> Option<PathBuf> {
+    if let Some(dir) = std::env::var_os(ASSETS_VAR) {
+        return Some(dir.into());
+    }
+    let data = std::env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".local/share")))?;
+    Some(data.join("tasty/assets"))
+}
+
+/// Bytes from a xorshift generator, so that generated built-ins are the same
+/// on every run
+pub fn random_bytes(len: usize, seed: u32) -> Vec<u8> {
+    let mut state = 0x2545_f491_u32 ^ seed.wrapping_mul(0x9e37_79b9);
+    (0..len)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            (state >> 24) as u8
+        })
+        .collect()
+}
+
+fn rgba_noise(size: u32, seed: u32) -> RgbaImage {
+    let bytes = random_bytes((size * size * 4) as usize, seed);
+    RgbaImage::from_raw(size, size, bytes).expect("bytes match image size")
+}
+
+fn gray_noise(size: u32, seed: u32) -> RgbaImage {
+    let bytes = random_bytes((size * size) as usize, seed);
+    RgbaImage::from_fn(size, size, |x, y| {
+        let v = bytes[(y * size + x) as usize];
+        Rgba([v, v, v, 255])
+    })
+}
+
+/// Shadertoy's 8x8 Bayer matrix, with thresholds from 0 to 252
+fn bayer() -> RgbaImage {
+    RgbaImage::from_fn(8, 8, |x, y| {
+        // interleave the bits of x ^ y and y, the lowest giving the most
+        // significant bits of the threshold
+        let (a, b) = (x ^ y, y);
+        let mut v = 0;
+        for bit in 0..3 {
+            v = (v << 2) | (((a >> bit) & 1) << 1) | ((b >> bit) & 1);
+        }
+        let v = (v * 4) as u8;
+        Rgba([v, v, v, 255])
+    })
+}
+
+/// Size of a glyph cell of the font atlas, and scale of the 8x8 glyphs in it
+const CELL: u32 = 64;
+const GLYPH_SCALE: f32 = (CELL / 8) as f32;
+
+/// Font atlas laid out like Shadertoy's, with character `c` in the cell at
+/// column `c % 16` and row `c / 16` from the top
+fn font() -> RgbaImage {
+    let mut atlas = RgbaImage::new(16 * CELL, 16 * CELL);
+    for code in 0..256usize {
+        let glyph = match code {
+            0..=127 => BASIC_LEGACY[code],
+            160..=255 => LATIN_LEGACY[code - 160],
+            _ => continue,
+        };
+        let (x, y) = ((code % 16) as u32 * CELL, (code / 16) as u32 * CELL);
+        image::imageops::replace(&mut atlas, &glyph_cell(glyph), x as i64, y as i64);
+    }
+    atlas
+}
+
+/// Cell of the font atlas for an 8x8 glyph, whose alpha holds the distance to
+/// the glyph's outline: 0.5 on the outline, increasing inside
+fn glyph_cell(glyph: [u8; 8]) -> RgbaImage {
+    let set = |x: i32, y: i32| glyph[y as usize] & (1 << x) != 0;
+    let texels: Vec<(i32, i32)> = (0..8).flat_map(|y| (0..8).map(move |x| (x, y))).collect();
+    RgbaImage::from_fn(CELL, CELL, |px, py| {
+        // position in glyph texels
+        let (gx, gy) = (
+            (px as f32 + 0.5) / GLYPH_SCALE,
+            (py as f32 + 0.5) / GLYPH_SCALE,
+        );
+        let inside = set(gx as i32, gy as i32);
+        let distance = texels
+            .iter()
+            .filter(|&&(x, y)| set(x, y) != inside)
+            .map(|&(x, y)| {
+                let dx = (x as f32 - gx).max(gx - x as f32 - 1.0).max(0.0);
+                let dy = (y as f32 - gy).max(gy - y as f32 - 1.0).max(0.0);
+                (dx * dx + dy * dy).sqrt()
+            })
+            .fold(2.0f32, f32::min);
+        let signed = if inside { distance } else { -distance };
+        let coverage = (0.5 + signed * GLYPH_SCALE).clamp(0.0, 1.0);
+        let field = (0.5 + signed * 0.25).clamp(0.0, 1.0);
+        let coverage = (coverage * 255.0) as u8;
+        Rgba([coverage, coverage, coverage, (field * 255.0) as u8])
+    })
+}
+
+/// List the built-ins with their size, recommended channel configuration and
+/// where their data comes from
+pub fn run() {
+    let assets = asset_dir();
+    println!("{:<18} {:<10} {:<53} SOURCE", "NAME", "SIZE", "CONFIG");
+    for name in BuiltinName::ALL {
+        let (size, source) = match name.source() {
+            Source::Embedded(bytes) => (
+                image::load_from_memory(bytes)
+                    .map(|i| format!("{}x{}", i.width(), i.height()))
+                    .unwrap_or_default(),
+                "embedded".to_string(),
+            ),
+            Source::Generated(_) => {
+                let image = name.generated().expect("built-in is generated");
+                (
+                    format!("{}x{}", image.width(), image.height()),
+                    "generated".to_string(),
+                )
+            }
+            Source::Volume(_) => {
+                let n = volume::NOISE_SIZE;
+                (format!("{}x{}x{}", n, n, n), "generated".to_string())
+            }
+            Source::Asset => match name.asset_path(assets.as_deref()) {
+                Some(path) => (
+                    image::image_dimensions(&path)
+                        .map(|(w, h)| format!("{}x{}", w, h))
+                        .unwrap_or_else(|_| "?".into()),
+                    path.display().to_string(),
+                ),
+                None => (
+                    "-".into(),
+                    format!("missing, add {}.jpg to the asset directory", name.key()),
+                ),
+            },
+        };
+        let config = toml::Value::try_from(name.config())
+            .map(|v| v.to_string())
+            .unwrap_or_default();
+        println!("{:<18} {:<10} {:<53} {}", name.key(), size, config, source);
+    }
+    if let Some(dir) = assets {
+        println!(
+            "\nasset directory: {} (set {} to change)",
+            dir.display(),
+            ASSETS_VAR
+        );
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tempdir::TempDir;
+
+    #[test]
+    fn builtin_names() {
+        let keys: Vec<_> = BuiltinName::ALL.iter().map(|n| n.key()).collect();
+        assert_eq!("rgba_noise_small", keys[0]);
+        assert!(keys.contains(&"grey_noise3d".to_string()));
+        for (name, key) in BuiltinName::ALL.iter().zip(&keys) {
+            let value = toml::Value::String(key.clone());
+            assert_eq!(*name, value.try_into::<BuiltinName>().unwrap());
+        }
+        // every built-in is listed once
+        let mut sorted = keys.clone();
+        sorted.sort();
+        sorted.dedup();
+        assert_eq!(keys.len(), sorted.len());
+    }
+
+    #[test]
+    fn generated_builtins() {
+        let noise = rgba_noise(256, 1);
+        assert_eq!((256, 256), noise.dimensions());
+        assert_ne!(noise.get_pixel(0, 0), noise.get_pixel(1, 0));
+        let gray = gray_noise(64, 2);
+        let p = gray.get_pixel(5, 7);
+        assert!(p[0] == p[1] && p[1] == p[2]);
+
+        // the Bayer matrix holds every threshold once
+        let mut thresholds: Vec<_> = bayer().pixels().map(|p| p[0]).collect();
+        assert_eq!(0, bayer().get_pixel(0, 0)[0]);
+        assert_eq!(128, bayer().get_pixel(1, 0)[0]);
+        assert_eq!(64, bayer().get_pixel(1, 1)[0]);
+        thresholds.sort();
+        assert_eq!((0..64).map(|v| v * 4).collect::<Vec<u8>>(), thresholds);
+
+        // glyph cells cover the glyph, with the distance field growing inside
+        let cell = glyph_cell(BASIC_LEGACY['I' as usize]);
+        assert_eq!((CELL, CELL), cell.dimensions());
+        let corner = cell.get_pixel(1, 1);
+        assert!(corner[0] == 0 && corner[3] < 128);
+        let center = cell.get_pixel(CELL / 2 - 4, CELL / 2);
+        assert_eq!(255, center[0]);
+        assert!(center[3] > 128);
+
+        // generated images are only generated once
+        let first = BuiltinName::Bayer.generated().unwrap();
+        assert!(std::ptr::eq(first, BuiltinName::Bayer.generated().unwrap()));
+        assert_eq!(bayer(), *first);
+        assert!(BuiltinName::Sky.generated().is_none());
+    }
+
+    #[test]
+    fn asset_builtins() {
+        let tmp_dir = TempDir::new("asset_builtins").unwrap().into_path();
+        assert!(matches!(
+            BuiltinName::London.bytes(Some(&tmp_dir)),
+            Err(ChannelError::MissingAsset(BuiltinName::London, Some(_)))
+        ));
+        std::fs::write(tmp_dir.join("london.jpg"), "jpeg").unwrap();
+        assert_eq!(
+            b"jpeg".to_vec(),
+            BuiltinName::London.bytes(Some(&tmp_dir)).unwrap()
+        );
+        assert!(BuiltinName::Bayer.bytes(None).is_ok());
+    }
+}
@@ -0,0 +1,282 @@
+use std::f32::consts::PI;
+use std::path::Path;
+
+use image::{imageops, DynamicImage, RgbaImage};
+use serde::{Deserialize, Serialize};
+
+/// Faces of a cube map, in the order OpenGL stores them
+pub const FACES: [&str; 6] = ["px", "nx", "py", "ny", "pz", "nz"];
+
+/// Where the faces of a cube map channel are read from
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum CubeLayout {
+    /// A directory of six images named after their face (`px.png`, `nx.png`,
+    /// `py.png`, `ny.png`, `pz.png` and `nz.png`)
+    Faces,
+    /// A single image, either a horizontal (4:3) or vertical (3:4) cross, or
+    /// an equirectangular panorama (2:1)
+    Image,
+    /// The output of the Cube A pass
+    Pass,
+}
+
+/// Error assembling the faces of a cube map
+#[derive(Debug, Clone, PartialEq)]
+pub enum CubeError {
+    /// The image is neither a cross nor an equirectangular panorama
+    Layout(u32, u32),
+    /// The faces are not square images of the same size
+    Faces,
+}
+
+impl std::fmt::Display for CubeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CubeError::Layout(w, h) => write!(
+                f,
+                "{}x{} image is not a 4:3 or 3:4 cross nor a 2:1 panorama",
+                w, h
+            ),
+            CubeError::Faces => write!(f, "cube map faces must be square and the same size"),
+        }
+    }
+}
+
+impl std::error::Error for CubeError {}
+
+/// Direction through texel coordinates `s`, `t` (from -1 to 1) of a face,
+/// following OpenGL's cube map conventions
+pub fn direction(face: usize, s: f32, t: f32) -> [f32; 3] {
+    match face {
+        0 => [1.0, -t, -s],
+        1 => [-1.0, -t, s],
+        2 => [s, 1.0, t],
+        3 => [s, -1.0, -t],
+        4 => [s, -t, 1.0],
+        _ => [-s, -t, -1.0],
+    }
+}
+
+/// Check that faces are square images of the same size
+pub fn check_faces(faces: &[RgbaImage; 6]) -> Result<(), CubeError> {
+    let size = faces[0].width();
+    if faces.iter().all(|f| f.dimensions() == (size, size)) {
+        Ok(())
+    } else {
+        Err(CubeError::Faces)
+    }
+}
+
+/// Face images of a directory using the `Faces` layout, in `FACES` order
+pub fn face_paths(dir: &Path) -> [std::path::PathBuf; 6] {
+    FACES.map(|face| {
+        ["png", "jpg", "jpeg"]
+            .iter()
+            .map(|ext| dir.join(face).with_extension(ext))
+            .find(|p| p.is_file())
+            .unwrap_or_else(|| dir.join(face).with_extension("png"))
+    })
+}
+
+/// Split a single image into faces, detecting its layout from its size
+pub fn split(image: &DynamicImage) -> Result<[RgbaImage; 6], CubeError> {
+    let image = image.to_rgba8();
+    let (w, h) = image.dimensions();
+    let faces = if w * 3 == h * 4 {
+        // horizontal cross: +Y above -X +Z +X -Z, with -Y below
+        let size = w / 4;
+        [(2, 1), (0, 1), (1, 0), (1, 2), (1, 1), (3, 1)]
+            .map(|(x, y)| imageops::crop_imm(&image, x * size, y * size, size, size).to_image())
+    } else if w * 4 == h * 3 {
+        // vertical cross: +Y above -X +Z +X, with -Y and then -Z below, upside down
+        let size = w / 3;
+        let face = |x, y| imageops::crop_imm(&image, x * size, y * size, size, size).to_image();
+        [
+            face(2, 1),
+            face(0, 1),
+            face(1, 0),
+            face(1, 2),
+            face(1, 1),
+            imageops::rotate180(&face(1, 3)),
+        ]
+    } else if w == h * 2 {
+        let size = (h / 2).max(1);
+        [0, 1, 2, 3, 4, 5].map(|face| from_equirect(&image, face, size))
+    } else {
+        return Err(CubeError::Layout(w, h));
+    };
+    Ok(faces)
+}
+
+/// Sample a face of an equirectangular panorama, whose center looks down -Z
+fn from_equirect(image: &RgbaImage, face: usize, size: u32) -> RgbaImage {
+    let (w, h) = image.dimensions();
+    RgbaImage::from_fn(size, size, |x, y| {
+        let s = (x as f32 + 0.5) / size as f32 * 2.0 - 1.0;
+        let t = (y as f32 + 0.5) / size as f32 * 2.0 - 1.0;
+        let [dx, dy, dz] = direction(face, s, t);
+        let len = (dx * dx + dy * dy + dz * dz).sqrt();
+        let u = 0.5 + dx.atan2(-dz) / (2.0 * PI);
+        let v = (dy / len).clamp(-1.0, 1.0).acos() / PI;
+        let px = ((u * w as f32) as u32).min(w - 1);
+        let py = ((v * h as f32) as u32).min(h - 1);
+        *image.get_pixel(px, py)
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::render::test::{channel, compiled, headless};
+    use crate::toy::{Channel, ChannelConfig, Toy};
+    use image::Rgba;
+    use std::fs;
+    use tempdir::TempDir;
+
+    /// A cross whose faces are filled with their index
+    fn cross(w: u32, h: u32, cells: [(u32, u32); 6]) -> DynamicImage {
+        let size = w / if w > h { 4 } else { 3 };
+        let mut image = RgbaImage::new(w, h);
+        for (face, (x, y)) in cells.iter().enumerate() {
+            for (px, py, pixel) in image.enumerate_pixels_mut() {
+                if px / size == *x && py / size == *y {
+                    *pixel = Rgba([face as u8, 0, 0, 255]);
+                }
+            }
+        }
+        DynamicImage::ImageRgba8(image)
+    }
+
+    #[test]
+    fn split_layouts() {
+        let cells = [(2, 1), (0, 1), (1, 0), (1, 2), (1, 1), (3, 1)];
+        let faces = split(&cross(8, 6, cells)).unwrap();
+        assert!(check_faces(&faces).is_ok());
+        for (i, face) in faces.iter().enumerate() {
+            assert_eq!((2, 2), face.dimensions());
+            assert_eq!(i as u8, face.get_pixel(0, 0)[0]);
+        }
+
+        let cells = [(2, 1), (0, 1), (1, 0), (1, 2), (1, 1), (1, 3)];
+        let faces = split(&cross(6, 8, cells)).unwrap();
+        for (i, face) in faces.iter().enumerate() {
+            assert_eq!(i as u8, face.get_pixel(1, 1)[0]);
+        }
+
+        assert_eq!(
+            Some(CubeError::Layout(5, 5)),
+            split(&DynamicImage::new_rgba8(5, 5)).err()
+        );
+    }
+
+    #[test]
+    fn split_equirect() {
+        // longitude in red, latitude in green
+        let image = RgbaImage::from_fn(64, 32, |x, y| Rgba([(x * 4) as u8, (y * 8) as u8, 0, 255]));
+        let faces = split(&DynamicImage::ImageRgba8(image)).unwrap();
+        assert_eq!((16, 16), faces[0].dimensions());
+
+        let center = |face: usize| faces[face].get_pixel(8, 8).0;
+        let close = |face: usize, expected: [u8; 2]| {
+            let value = center(face);
+            assert!(
+                value[0].abs_diff(expected[0]) <= 8 && value[1].abs_diff(expected[1]) <= 8,
+                "face {}: {:?}",
+                face,
+                value
+            );
+        };
+        // -Z is the center of the panorama, +Z its edges, and +X a quarter
+        // of the way round
+        close(5, [128, 128]);
+        close(0, [192, 128]);
+        close(1, [64, 128]);
+        assert!(!(8..248).contains(&center(4)[0]));
+        // +Y is the top row, -Y the bottom one
+        assert!(center(2)[1] <= 8);
+        assert!(center(3)[1] >= 240);
+    }
+
+    #[test]
+    fn render_cubemap_channel() {
+        let Some(headless) = headless() else {
+            return;
+        };
+        let tmp_dir = TempDir::new("render_cubemap_channel").unwrap().into_path();
+        fs::create_dir(tmp_dir.join("faces")).unwrap();
+        for (i, face) in FACES.iter().enumerate() {
+            let red = 40 * (i as u8 + 1);
+            let image = RgbaImage::from_pixel(4, 4, Rgba([red, 0, 0, 255]));
+            image
+                .save(tmp_dir.join(format!("faces/{face}.png")))
+                .unwrap();
+        }
+
+        // each pixel looks down one axis, in face order
+        let mut toy = Toy {
+            root: tmp_dir,
+            main_image: "void mainImage(out vec4 c, in vec2 p) {
+                vec3 dirs[6] = vec3[6](vec3(1, 0, 0), vec3(-1, 0, 0), vec3(0, 1, 0),
+                                       vec3(0, -1, 0), vec3(0, 0, 1), vec3(0, 0, -1));
+                c = texture(iChannel0, dirs[int(p.x)]);
+            }"
+            .into(),
+            ..Default::default()
+        };
+        toy.config.channels = vec![Channel {
+            path: Some("faces".into()),
+            ..channel(ChannelConfig::Cubemap {
+                layout: CubeLayout::Faces,
+            })
+        }];
+        assert!(toy
+            .fragment_shader()
+            .unwrap()
+            .source
+            .contains("samplerCube iChannel0"));
+
+        let (mut renderer, target) = compiled(&headless, &toy, (6, 1));
+        let image = renderer.capture(&target);
+        let red: Vec<_> = image.pixels().map(|p| p[0]).collect();
+        assert_eq!(vec![40, 80, 120, 160, 200, 240], red);
+    }
+
+    #[test]
+    fn render_cube_pass() {
+        let Some(headless) = headless() else {
+            return;
+        };
+        let mut toy = Toy {
+            main_image: "void mainImage(out vec4 c, in vec2 p) {
+                vec3 dirs[2] = vec3[2](vec3(1, 0, 0), vec3(0, -1, 0));
+                c = texture(iChannel0, dirs[int(p.x)]);
+            }"
+            .into(),
+            cube: Some(
+                "void mainCubemap(out vec4 c, in vec2 p, in vec3 ro, in vec3 rd) {
+                    c = vec4(rd * 0.5 + 0.5, iResolution.x / 2048.0);
+                }"
+                .into(),
+            ),
+            ..Default::default()
+        };
+        toy.config.channels = vec![channel(ChannelConfig::Cubemap {
+            layout: CubeLayout::Pass,
+        })];
+
+        // the cube pass renders ray directions at the face resolution
+        let (mut renderer, target) = compiled(&headless, &toy, (2, 1));
+        let image = renderer.capture(&target);
+        let close = |actual: [u8; 4], expected: [u8; 4]| {
+            assert!(
+                actual.iter().zip(expected).all(|(a, e)| a.abs_diff(e) <= 2),
+                "{:?} != {:?}",
+                actual,
+                expected
+            )
+        };
+        close(image.get_pixel(0, 0).0, [255, 128, 128, 128]);
+        close(image.get_pixel(1, 0).0, [128, 0, 128, 128]);
+    }
+}
@@ -0,0 +1,180 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::{error, fs, io};
+
+/// Error expanding the `#include` directives of a source file
+#[derive(Debug)]
+pub enum IncludeError {
+    /// No file with the included name exists in any include directory
+    NotFound {
+        file: String,
+        line: usize,
+        name: String,
+    },
+    /// The included file is already being included, directly or indirectly
+    Cycle {
+        file: String,
+        line: usize,
+        name: String,
+    },
+    /// The included file could not be read
+    Io(PathBuf, io::Error),
+}
+
+impl fmt::Display for IncludeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IncludeError::NotFound { file, line, name } => {
+                write!(f, "{}:{}: included file {:?} not found", file, line, name)
+            }
+            IncludeError::Cycle { file, line, name } => {
+                write!(f, "{}:{}: {:?} is included recursively", file, line, name)
+            }
+            IncludeError::Io(path, e) => write!(f, "error reading {:?}: {}", path, e),
+        }
+    }
+}
+
+impl error::Error for IncludeError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            IncludeError::Io(_, e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// Name of the file included by a `#include "name"` line
+pub fn directive(line: &str) -> Option<&str> {
+    let rest = line.trim_start().strip_prefix('#')?.trim_start();
+    let rest = rest.strip_prefix("include")?.trim_start();
+    let rest = rest.strip_prefix('"')?;
+    let (name, rest) = rest.split_once('"')?;
+    let rest = rest.trim();
+    if rest.is_empty() || rest.starts_with("//") {
+        Some(name)
+    } else {
+        None
+    }
+}
+
+/// Directories `#include` directives are resolved in
+#[derive(Debug, Clone, PartialEq)]
+pub struct Includes {
+    root: PathBuf,
+    libraries: Vec<PathBuf>,
+}
+
+impl Includes {
+    /// Search the toy directory `root` followed by library `paths` relative to it
+    pub fn new<P: AsRef<Path>>(root: P, paths: &[String]) -> Self {
+        let root = root.as_ref();
+        Includes {
+            root: root.to_path_buf(),
+            libraries: paths.iter().map(|p| root.join(p)).collect(),
+        }
+    }
+
+    /// The toy directory
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Paths an include from a file in `dir` is looked up at, in order
+    fn candidates(&self, name: &str, dir: &Path) -> Vec<PathBuf> {
+        let mut paths = vec![dir.join(name)];
+        let dirs = std::iter::once(&self.root).chain(&self.libraries);
+        for path in dirs.map(|d| d.join(name)) {
+            if !paths.contains(&path) {
+                paths.push(path);
+            }
+        }
+        paths
+    }
+
+    /// Resolve an include from a file in `dir`, preferring files next to it
+    pub fn resolve(&self, name: &str, dir: &Path) -> Option<PathBuf> {
+        self.candidates(name, dir).into_iter().find(|p| p.is_file())
+    }
+
+    /// Add the files `contents` includes, directly or indirectly, to `found`,
+    /// along with every path a missing include could be created at
+    pub fn dependencies(&self, contents: &str, dir: &Path, found: &mut Vec<PathBuf>) {
+        for name in contents.lines().filter_map(directive) {
+            match self.resolve(name, dir) {
+                Some(path) if !found.contains(&path) => {
+                    found.push(path.clone());
+                    if let Ok(included) = fs::read_to_string(&path) {
+                        let dir = path.parent().unwrap_or(dir);
+                        self.dependencies(&included, dir, found);
+                    }
+                }
+                Some(_) => (),
+                None => found.extend(self.candidates(name, dir)),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tempdir::TempDir;
+
+    #[test]
+    fn include_directives() {
+        assert_eq!(Some("noise.glsl"), directive("#include \"noise.glsl\""));
+        assert_eq!(
+            Some("lib/sdf.glsl"),
+            directive("  #  include  \"lib/sdf.glsl\" ")
+        );
+        assert_eq!(Some("a.glsl"), directive("#include \"a.glsl\" // noise"));
+        assert_eq!(None, directive("#include <a.glsl>"));
+        assert_eq!(None, directive("#include \"a.glsl\" b"));
+        assert_eq!(None, directive("// #include \"a.glsl\""));
+        assert_eq!(None, directive("#define include"));
+    }
+
+    #[test]
+    fn include_resolution() {
+        let tmp_dir = TempDir::new("include_resolution").unwrap().into_path();
+        let toy = tmp_dir.join("toy");
+        let lib = tmp_dir.join("lib");
+        fs::create_dir_all(&toy).unwrap();
+        fs::create_dir_all(lib.join("nested")).unwrap();
+        fs::write(lib.join("a.glsl"), "#include \"nested/b.glsl\"").unwrap();
+        fs::write(
+            lib.join("nested/b.glsl"),
+            "#include \"c.glsl\"\n#include \"b.glsl\"",
+        )
+        .unwrap();
+        fs::write(lib.join("nested/c.glsl"), "").unwrap();
+
+        let includes = Includes::new(&toy, &["../lib".into()]);
+        assert_eq!(None, includes.resolve("b.glsl", &toy));
+
+        // the toy directory comes before library paths
+        fs::write(toy.join("a.glsl"), "").unwrap();
+        assert_eq!(Some(toy.join("a.glsl")), includes.resolve("a.glsl", &toy));
+        fs::remove_file(toy.join("a.glsl")).unwrap();
+        assert_eq!(
+            Some(toy.join("../lib/a.glsl")),
+            includes.resolve("a.glsl", &toy)
+        );
+
+        // nested includes are found next to the including file, and missing
+        // ones are reported wherever they may be created
+        let mut found = vec![];
+        includes.dependencies("#include \"a.glsl\"\n#include \"d.glsl\"", &toy, &mut found);
+        assert_eq!(
+            vec![
+                toy.join("../lib/a.glsl"),
+                toy.join("../lib/nested/b.glsl"),
+                toy.join("../lib/nested/c.glsl"),
+                toy.join("d.glsl"),
+                toy.join("../lib/d.glsl"),
+            ],
+            found
+        );
+    }
+}
@@ -1,3 +1,8 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::include::{self, IncludeError, Includes};
+
 pub const VERTEX: &str = r#"#version 330
     attribute vec2 in_pos;
     attribute vec2 in_uv;
@@ -21,9 +26,8 @@ pub const MAIN_IMAGE: &str = r#"void mainImage( out vec4 fragColor, in vec2 frag
     fragColor = vec4(col,1.0);
 }"#;
 
-pub fn build_fragment_shader(main_image: &str) -> String {
-    format!(
-        r#"#version 330
+/// Declarations shared by every pass, preceding the user source
+const PRELUDE: &str = r#"#version 330
 varying lowp vec2 texcoord;
 
 uniform vec3 iResolution;
@@ -32,21 +36,374 @@ uniform float iTime;
 uniform float iTimeDelta;
 uniform int iFrame;
 uniform float iFrameRate;
+uniform float iChannelTime[4];
+uniform vec3 iChannelResolution[4];
+uniform vec4 iDate;
+uniform float iSampleRate;
+"#;
+
+/// Render a pixel of the main image or a buffer, offset by the position of
+/// the tile being rendered when a large still is rendered in tiles
+const MAIN: &str = r#"
+uniform vec2 iTileOffset;
+
+void main() {
+    mainImage(outColor, gl_FragCoord.xy + iTileOffset);
+}"#;
 
-uniform sampler2D iChannel0;
-uniform sampler2D iChannel1;
-uniform sampler2D iChannel2;
-uniform sampler2D iChannel3;
+/// Width and height of each face of the Cube A pass
+pub const CUBE_SIZE: u32 = 1024;
 
+/// Render the face `iCubeFace` of the Cube A pass, in the order and with the
+/// orientation of OpenGL's cube map faces
+const CUBE_MAIN: &str = r#"
+uniform int iCubeFace;
+
+void main() {
+    vec2 st = gl_FragCoord.xy / iResolution.xy * 2.0 - 1.0;
+    vec3 directions[6] = vec3[6](
+        vec3(1.0, -st.y, -st.x), vec3(-1.0, -st.y, st.x),
+        vec3(st.x, 1.0, st.y), vec3(st.x, -1.0, -st.y),
+        vec3(st.x, -st.y, 1.0), vec3(-st.x, -st.y, -1.0));
+    mainCubemap(outColor, gl_FragCoord.xy, vec3(0.0), normalize(directions[iCubeFace]));
+}"#;
+
+/// Width and height of the target a block of sound samples is rendered to
+pub const SOUND_BLOCK: u32 = 512;
+
+/// Declarations of the sound pass, following those shared by every pass
+const SOUND_UNIFORMS: &str = r#"uniform float iTimeOffset;
+uniform int iSampleOffset;
+"#;
+
+/// GLSL type of the sampler a channel is read with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SamplerType {
+    #[default]
+    Texture2D,
+    Cube,
+    Texture3D,
+}
 
-out vec4 outColor;
+impl SamplerType {
+    fn glsl(&self) -> &'static str {
+        match self {
+            SamplerType::Texture2D => "sampler2D",
+            SamplerType::Cube => "samplerCube",
+            SamplerType::Texture3D => "sampler3D",
+        }
+    }
+}
 
-// mainImage
-{main_image}
+/// Declarations of `iChannel0` to `iChannel3` and of the pass output, with
+/// channels not in `samplers` read as 2D textures
+fn channel_declarations(samplers: &[SamplerType]) -> String {
+    let mut declarations = String::from("\n");
+    for i in 0..super::MAX_CHANNELS {
+        let sampler = samplers.get(i).copied().unwrap_or_default();
+        declarations.push_str(&format!("uniform {} iChannel{};\n", sampler.glsl(), i));
+    }
+    declarations.push_str("\nout vec4 outColor;\n");
+    declarations
+}
 
+/// Render one sample per pixel of a block of `SOUND_BLOCK` samples a side,
+/// packing each stereo channel into two bytes as unsigned 16 bit values
+fn sound_main() -> String {
+    format!(
+        r#"
 void main() {{
-    mainImage(outColor, gl_FragCoord.xy);
+    int offset = int(gl_FragCoord.y) * {} + int(gl_FragCoord.x);
+    vec2 y = mainSound(iSampleOffset + offset, iTimeOffset + float(offset) / iSampleRate);
+    vec2 v = min(floor((0.5 + 0.5 * clamp(y, -1.0, 1.0)) * 65536.0), 65535.0);
+    vec2 lo = mod(v, 256.0) / 255.0;
+    vec2 hi = floor(v / 256.0) / 255.0;
+    outColor = vec4(lo.x, hi.x, lo.y, hi.y);
 }}"#,
-        main_image = main_image
+        SOUND_BLOCK
+    )
+}
+
+/// Origin of a range of lines of a generated shader
+#[derive(Debug, Clone, PartialEq)]
+struct Segment {
+    /// First line of the range in the generated shader, starting at 1
+    start: usize,
+    len: usize,
+    file: String,
+    /// Line of the file corresponding to `start`
+    first_line: usize,
+}
+
+/// Maps lines of a generated shader back to the files they were read from
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SourceMap {
+    segments: Vec<Segment>,
+}
+
+impl SourceMap {
+    /// File and line a line of the generated shader came from
+    pub fn lookup(&self, line: usize) -> Option<(&str, usize)> {
+        self.segments
+            .iter()
+            .find(|s| line >= s.start && line < s.start + s.len)
+            .map(|s| (s.file.as_str(), s.first_line + line - s.start))
+    }
+
+    /// Rewrite references to generated lines in a driver's compile log, e.g.
+    /// `0:12(3)`, `0(12)` or `ERROR: 0:12:`, as `file:line`
+    pub fn remap_log(&self, log: &str) -> String {
+        log.lines()
+            .map(|l| self.remap_log_line(l))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    fn remap_log_line(&self, line: &str) -> String {
+        let Some((start, end, number)) = find_line_reference(line) else {
+            return line.to_string();
+        };
+        match self.lookup(number) {
+            Some((file, mapped)) => {
+                format!("{}{}:{}{}", &line[..start], file, mapped, &line[end..])
+            }
+            None => line.to_string(),
+        }
+    }
+}
+
+/// Position and line number of the first `0:LINE` or `0(LINE)` reference to
+/// the (only) source string of a shader in a log line
+fn find_line_reference(line: &str) -> Option<(usize, usize, usize)> {
+    let bytes = line.as_bytes();
+    (0..bytes.len().saturating_sub(2)).find_map(|i| {
+        let at_word_start = i == 0 || !bytes[i - 1].is_ascii_alphanumeric();
+        if !at_word_start || bytes[i] != b'0' || !matches!(bytes[i + 1], b':' | b'(') {
+            return None;
+        }
+        let digits = bytes[i + 2..]
+            .iter()
+            .take_while(|b| b.is_ascii_digit())
+            .count();
+        if digits == 0 {
+            return None;
+        }
+        let mut end = i + 2 + digits;
+        if bytes[i + 1] == b'(' {
+            if bytes.get(end) != Some(&b')') {
+                return None;
+            }
+            end += 1;
+        }
+        let number = line[i + 2..i + 2 + digits].parse().ok()?;
+        Some((i, end, number))
+    })
+}
+
+/// A generated fragment shader along with the origin of its lines
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Fragment {
+    pub source: String,
+    pub map: SourceMap,
+}
+
+impl Fragment {
+    fn next_line(&self) -> usize {
+        self.source.matches('\n').count() + 1
+    }
+
+    /// Lines of the shader with their number, starting at 1, and the file and
+    /// line they were read from, or `None` for generated code
+    pub fn lines(&self) -> impl Iterator<Item = (usize, &str, Option<(&str, usize)>)> {
+        self.source
+            .lines()
+            .enumerate()
+            .map(|(i, line)| (i + 1, line, self.map.lookup(i + 1)))
+    }
+
+    /// Rewrite each line of generated code with `rewrite`, which may return
+    /// several lines, keeping lines read from files mapped to their origin
+    pub fn rewrite_generated<F>(&self, mut rewrite: F) -> Fragment
+    where
+        F: FnMut(&str) -> String,
+    {
+        let mut fragment = Fragment::default();
+        let mut segments = self.map.segments.iter().peekable();
+        for (number, line, origin) in self.lines() {
+            if origin.is_some() {
+                if let Some(segment) = segments.next_if(|s| s.start == number) {
+                    fragment.map.segments.push(Segment {
+                        start: fragment.next_line(),
+                        ..segment.clone()
+                    });
+                }
+                fragment.source.push_str(line);
+            } else {
+                fragment.source.push_str(&rewrite(line));
+            }
+            fragment.source.push('\n');
+        }
+        fragment
+    }
+
+    /// Append generated code
+    fn push(&mut self, code: &str) {
+        self.source.push_str(code);
+    }
+
+    /// Append lines of `file` starting at `first_line`, recording where they end up
+    fn push_lines(&mut self, lines: &[&str], file: &str, first_line: usize) {
+        if lines.is_empty() {
+            return;
+        }
+        if !self.source.is_empty() && !self.source.ends_with('\n') {
+            self.source.push('\n');
+        }
+        self.map.segments.push(Segment {
+            start: self.next_line(),
+            len: lines.len(),
+            file: file.into(),
+            first_line,
+        });
+        for line in lines {
+            self.source.push_str(line);
+            self.source.push('\n');
+        }
+    }
+
+    /// Append the contents of `file` in `dir`, replacing `#include` directives
+    /// with the contents of the included files
+    fn push_file(
+        &mut self,
+        contents: &str,
+        file: &str,
+        dir: &Path,
+        includes: &Includes,
+        stack: &mut Vec<PathBuf>,
+    ) -> Result<(), IncludeError> {
+        let lines: Vec<&str> = contents.lines().collect();
+        let mut start = 0;
+        for (i, line) in lines.iter().enumerate() {
+            let Some(name) = include::directive(line) else {
+                continue;
+            };
+            self.push_lines(&lines[start..i], file, start + 1);
+            start = i + 1;
+
+            let path = includes
+                .resolve(name, dir)
+                .ok_or_else(|| IncludeError::NotFound {
+                    file: file.into(),
+                    line: i + 1,
+                    name: name.into(),
+                })?;
+            let canonical = path.canonicalize().unwrap_or_else(|_| path.clone());
+            if stack.contains(&canonical) {
+                return Err(IncludeError::Cycle {
+                    file: file.into(),
+                    line: i + 1,
+                    name: name.into(),
+                });
+            }
+            let included =
+                fs::read_to_string(&path).map_err(|e| IncludeError::Io(path.clone(), e))?;
+            stack.push(canonical);
+            self.push_file(
+                &included,
+                name,
+                path.parent().unwrap_or(dir),
+                includes,
+                stack,
+            )?;
+            stack.pop();
+        }
+        self.push_lines(&lines[start..], file, start + 1);
+        Ok(())
+    }
+}
+
+/// Expand the `#include` directives of `contents`, read from `file` in the
+/// toy directory, without any of the code added to build a shader
+pub fn preprocess(
+    contents: &str,
+    file: &str,
+    includes: &Includes,
+) -> Result<Fragment, IncludeError> {
+    let mut fragment = Fragment::default();
+    fragment.push_file(contents, file, includes.root(), includes, &mut vec![])?;
+    Ok(fragment)
+}
+
+fn build(
+    prelude: &str,
+    main: &str,
+    common: Option<&str>,
+    source: &str,
+    file: &str,
+    samplers: &[SamplerType],
+    includes: &Includes,
+) -> Result<Fragment, IncludeError> {
+    let root = includes.root();
+    let mut fragment = Fragment::default();
+    fragment.push(prelude);
+    fragment.push(&channel_declarations(samplers));
+    if let Some(common) = common {
+        fragment.push("\n// common\n");
+        fragment.push_file(common, "common.glsl", root, includes, &mut vec![])?;
+    }
+    fragment.push(&format!("\n// {}\n", file));
+    fragment.push_file(source, file, root, includes, &mut vec![])?;
+    fragment.push(main);
+    Ok(fragment)
+}
+
+/// Build the fragment shader of a pass whose source was read from `file` in
+/// the toy directory, preceded by the code shared by every pass and reading
+/// channels with `samplers`
+pub fn build_fragment_shader(
+    common: Option<&str>,
+    main_image: &str,
+    file: &str,
+    samplers: &[SamplerType],
+    includes: &Includes,
+) -> Result<Fragment, IncludeError> {
+    build(PRELUDE, MAIN, common, main_image, file, samplers, includes)
+}
+
+/// Build the fragment shader of the sound pass from `sound.glsl`, rendering
+/// blocks of `SOUND_BLOCK` by `SOUND_BLOCK` samples
+pub fn build_sound_shader(
+    common: Option<&str>,
+    sound: &str,
+    samplers: &[SamplerType],
+    includes: &Includes,
+) -> Result<Fragment, IncludeError> {
+    build(
+        &format!("{}{}", PRELUDE, SOUND_UNIFORMS),
+        &sound_main(),
+        common,
+        sound,
+        "sound.glsl",
+        samplers,
+        includes,
+    )
+}
+
+/// Build the fragment shader of the Cube A pass from `cubea.glsl`, rendering
+/// the face `iCubeFace` of a cube map with `mainCubemap`
+pub fn build_cube_shader(
+    common: Option<&str>,
+    cube: &str,
+    samplers: &[SamplerType],
+    includes: &Includes,
+) -> Result<Fragment, IncludeError> {
+    build(
+        PRELUDE,
+        CUBE_MAIN,
+        common,
+        cube,
+        "cubea.glsl",
+        samplers,
+        includes,
     )
 }
@@ -0,0 +1,212 @@
+use std::fs::File;
+use std::io;
+use std::path::Path;
+
+use symphonia::core::audio::SampleBuffer;
+use symphonia::core::codecs::DecoderOptions;
+use symphonia::core::errors::Error;
+use symphonia::core::formats::FormatOptions;
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::MetadataOptions;
+use symphonia::core::probe::Hint;
+
+/// Decoded audio read by an audio channel, mixed down to mono
+#[derive(Debug, Clone, PartialEq)]
+pub struct Track {
+    pub sample_rate: u32,
+    /// Samples in `[-1, 1]`
+    pub samples: Vec<f32>,
+}
+
+impl Track {
+    /// Decode a WAV, OGG Vorbis or FLAC file, detecting the format from its content
+    pub fn decode(path: &Path) -> Result<Track, Error> {
+        let file = File::open(path)?;
+        let mut hint = Hint::new();
+        if let Some(extension) = path.extension().and_then(|e| e.to_str()) {
+            hint.with_extension(extension);
+        }
+        let stream = MediaSourceStream::new(Box::new(file), Default::default());
+        let mut format = symphonia::default::get_probe()
+            .format(
+                &hint,
+                stream,
+                &FormatOptions::default(),
+                &MetadataOptions::default(),
+            )?
+            .format;
+        let track = format
+            .default_track()
+            .ok_or(Error::Unsupported("file has no audio track"))?;
+        let id = track.id;
+        let mut sample_rate = track.codec_params.sample_rate.unwrap_or(44100);
+        let mut decoder = symphonia::default::get_codecs()
+            .make(&track.codec_params, &DecoderOptions::default())?;
+
+        let mut samples = vec![];
+        loop {
+            let packet = match format.next_packet() {
+                Ok(packet) => packet,
+                Err(Error::IoError(e)) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+                Err(e) => return Err(e),
+            };
+            if packet.track_id() != id {
+                continue;
+            }
+            let decoded = decoder.decode(&packet)?;
+            let spec = *decoded.spec();
+            sample_rate = spec.rate;
+            let mut buffer = SampleBuffer::<f32>::new(decoded.capacity() as u64, spec);
+            buffer.copy_interleaved_ref(decoded);
+            let channels = spec.channels.count().max(1);
+            samples.extend(
+                buffer
+                    .samples()
+                    .chunks_exact(channels)
+                    .map(|frame| frame.iter().sum::<f32>() / channels as f32),
+            );
+        }
+        Ok(Track {
+            sample_rate,
+            samples,
+        })
+    }
+
+    /// Length of the track, in seconds
+    pub fn duration(&self) -> f32 {
+        self.samples.len() as f32 / self.sample_rate as f32
+    }
+
+    /// Playback position at `time`, looping over the track
+    pub fn position(&self, time: f32) -> f32 {
+        let duration = self.duration();
+        if duration > 0.0 {
+            time.rem_euclid(duration)
+        } else {
+            0.0
+        }
+    }
+
+    /// The `len` samples preceding `position`, silent before the start
+    pub fn window(&self, position: f32, len: usize) -> Vec<f32> {
+        let end = (position * self.sample_rate as f32) as isize;
+        (end - len as isize..end)
+            .map(|i| {
+                usize::try_from(i)
+                    .ok()
+                    .and_then(|i| self.samples.get(i))
+                    .copied()
+                    .unwrap_or(0.0)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::render::test::{channel, compiled, headless};
+    use crate::toy::{ChannelConfig, Toy};
+    use tempdir::TempDir;
+
+    /// Write interleaved 16 bit `samples` of `channels` channels at 8 kHz
+    fn write_wav(path: &Path, channels: u16, samples: impl IntoIterator<Item = i16>) {
+        let spec = hound::WavSpec {
+            channels,
+            sample_rate: 8000,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut writer = hound::WavWriter::create(path, spec).unwrap();
+        for sample in samples {
+            writer.write_sample(sample).unwrap();
+        }
+        writer.finalize().unwrap();
+    }
+
+    #[test]
+    fn decode_wav() {
+        let tmp_dir = TempDir::new("decode_wav").unwrap().into_path();
+        let path = tmp_dir.join("song.wav");
+        let samples = (0..4000).flat_map(|i| [(i % 100) as i16 * 100, 16384]);
+        write_wav(&path, 2, samples);
+
+        // channels are mixed down
+        let track = Track::decode(&path).unwrap();
+        assert_eq!(8000, track.sample_rate);
+        assert_eq!(4000, track.samples.len());
+        assert!((track.samples[1] - (100.0 + 16384.0) / 65536.0).abs() < 1e-4);
+        assert_eq!(0.5, track.duration());
+
+        // positions loop, and windows are padded with silence
+        assert!((track.position(1.25) - 0.25).abs() < 1e-6);
+        let window = track.window(0.0005, 8);
+        assert_eq!(vec![0.0; 4], window[..4]);
+        assert_eq!(track.samples[..4], window[4..]);
+
+        // files that are not audio are errors
+        std::fs::write(&path, "foobarbaz").unwrap();
+        assert!(Track::decode(&path).is_err());
+    }
+
+    #[test]
+    fn render_audio_channel() {
+        let Some(headless) = headless() else {
+            return;
+        };
+        let tmp_dir = TempDir::new("render_audio_channel").unwrap().into_path();
+        let samples = (0..8000).map(|i| if i < 4000 { 16384 } else { -16384 });
+        write_wav(&tmp_dir.join("song.wav"), 1, samples);
+
+        let mut toy = Toy {
+            root: tmp_dir,
+            main_image: "void mainImage(out vec4 c, in vec2 p) {
+                c = vec4(texelFetch(iChannel0, ivec2(0, 1), 0).x,
+                         texelFetch(iChannel0, ivec2(511, 1), 0).x,
+                         iChannelTime[0] / 4.0, 1.0);
+            }"
+            .into(),
+            ..Default::default()
+        };
+        toy.config.channels = vec![channel(ChannelConfig::Audio {
+            path: "song.wav".into(),
+        })];
+        let (mut renderer, target) = compiled(&headless, &toy, (4, 4));
+
+        // the waveform follows `iTime`, looping over the track
+        for (time, expected) in [
+            (0.25, [192, 192, 16]),
+            (0.75, [64, 64, 48]),
+            (1.25, [192, 192, 16]),
+        ] {
+            renderer.uniforms.iTime = time;
+            let pixel = renderer.capture(&target).get_pixel(0, 0).0;
+            for (value, expected) in pixel.iter().zip(expected) {
+                assert!(value.abs_diff(expected) <= 1, "{}: {:?}", time, pixel);
+            }
+        }
+    }
+
+    #[test]
+    fn too_many_channels() {
+        let Some(headless) = headless() else {
+            return;
+        };
+        let tmp_dir = TempDir::new("too_many_channels").unwrap().into_path();
+        write_wav(&tmp_dir.join("song.wav"), 1, [0]);
+
+        let mut toy = Toy {
+            root: tmp_dir,
+            ..Default::default()
+        };
+        let audio = channel(ChannelConfig::Audio {
+            path: "song.wav".into(),
+        });
+        toy.config.channels = vec![audio; 5];
+
+        // the fifth channel has no iChannelTime, and is rejected rather than drawn
+        let mut renderer = headless.renderer(4, 4);
+        let e = renderer.compile(&toy).unwrap_err();
+        assert!(e.to_string().contains("at most 4"), "{}", e);
+    }
+}
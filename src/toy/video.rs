@@ -0,0 +1,169 @@
+use std::path::{Path, PathBuf};
+use std::{fs, io};
+
+use image::{DynamicImage, ImageFormat, ImageReader};
+
+/// Frames of a video channel, read from a directory of images in file name
+/// order (e.g. `0001.png`, `0002.png`, ...)
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sequence {
+    pub frames: Vec<PathBuf>,
+    /// Frames per second of playback
+    pub fps: f32,
+    /// Whether playback restarts after the last frame, rather than holding it
+    pub looping: bool,
+}
+
+/// Image files of a directory, sorted by name
+pub fn frames(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut frames = vec![];
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_file() && ImageFormat::from_path(&path).is_ok() {
+            frames.push(path);
+        }
+    }
+    frames.sort();
+    Ok(frames)
+}
+
+impl Sequence {
+    /// Frames elapsed at `time`, tolerating rounding errors of `time` that
+    /// would otherwise show the previous frame
+    fn elapsed(&self, time: f32) -> f32 {
+        (time * self.fps + 1e-3).floor().max(0.0)
+    }
+
+    /// Number of the frame shown at `time`, or `None` without frames
+    pub fn frame(&self, time: f32) -> Option<usize> {
+        let count = self.frames.len();
+        if count == 0 {
+            return None;
+        }
+        let frame = self.elapsed(time) as usize;
+        Some(if self.looping {
+            frame % count
+        } else {
+            frame.min(count - 1)
+        })
+    }
+
+    /// Playback position at `time`, in seconds
+    pub fn position(&self, time: f32) -> f32 {
+        let duration = self.frames.len() as f32 / self.fps;
+        if self.looping && duration > 0.0 {
+            let loops = (self.elapsed(time) / self.frames.len() as f32).floor();
+            (time - loops * duration).max(0.0)
+        } else {
+            time.clamp(0.0, duration)
+        }
+    }
+
+    /// Decode a frame, detecting the format from its content
+    pub fn load(&self, frame: usize) -> image::ImageResult<DynamicImage> {
+        ImageReader::open(&self.frames[frame])?
+            .with_guessed_format()?
+            .decode()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::render::test::{channel, compiled, headless, options};
+    use crate::render::RenderOptions;
+    use crate::toy::{ChannelConfig, Toy};
+    use tempdir::TempDir;
+
+    #[test]
+    fn sequence_frames() {
+        let tmp_dir = TempDir::new("sequence_frames").unwrap().into_path();
+        for name in ["0002.png", "0001.png", "0010.png", "notes.txt"] {
+            fs::write(tmp_dir.join(name), "").unwrap();
+        }
+        let frames = frames(&tmp_dir).unwrap();
+        assert_eq!(
+            ["0001.png", "0002.png", "0010.png"].map(|n| tmp_dir.join(n)),
+            frames[..]
+        );
+
+        let mut sequence = Sequence {
+            frames,
+            fps: 10.0,
+            looping: true,
+        };
+        // frames are exact at the fixed timestep of offline renders
+        let times = (0..7).map(|i| i as f32 / 20.0 + 0.1);
+        let shown: Vec<_> = times.map(|t| sequence.frame(t).unwrap()).collect();
+        assert_eq!(vec![1, 1, 2, 2, 0, 0, 1], shown);
+        assert!((sequence.position(0.35) - 0.05).abs() < 1e-6);
+        assert!(sequence.position(0.3) < 1e-6);
+
+        // without looping the last frame is held
+        sequence.looping = false;
+        assert_eq!(Some(2), sequence.frame(1.0));
+        assert_eq!(0.3, sequence.position(1.0));
+
+        sequence.frames.clear();
+        assert_eq!(None, sequence.frame(0.0));
+    }
+
+    #[test]
+    fn render_video_channel() {
+        let Some(headless) = headless() else {
+            return;
+        };
+        let tmp_dir = TempDir::new("render_video_channel").unwrap().into_path();
+        fs::create_dir(tmp_dir.join("frames")).unwrap();
+        for (i, red) in [50, 100, 150].into_iter().enumerate() {
+            let frame = image::RgbaImage::from_pixel(2, 2, image::Rgba([red, 0, 0, 255]));
+            frame
+                .save(tmp_dir.join(format!("frames/{:04}.png", i + 1)))
+                .unwrap();
+        }
+
+        let mut toy = Toy {
+            root: tmp_dir,
+            main_image: "void mainImage(out vec4 c, in vec2 p) {
+                c = vec4(texture(iChannel0, vec2(0.5)).x, iChannelTime[0], 0.0, 1.0);
+            }"
+            .into(),
+            ..Default::default()
+        };
+        toy.config.channels = vec![channel(ChannelConfig::Video {
+            path: "frames".into(),
+            looping: true,
+            fps: 10.0,
+        })];
+
+        // frames follow the fixed timestep of offline renders, looping
+        let (mut renderer, target) = compiled(&headless, &toy, (4, 4));
+        let options = RenderOptions {
+            fps: 20.0,
+            ..options(1)
+        };
+        let mut shown = vec![];
+        for frame in 0..8 {
+            options.set_frame(&mut renderer.uniforms, frame);
+            let pixel = renderer.capture(&target).get_pixel(0, 0).0;
+            shown.push([pixel[0], pixel[1]]);
+        }
+        let expected = [
+            [50, 0],
+            [50, 13],
+            [100, 26],
+            [100, 38],
+            [150, 51],
+            [150, 64],
+            [50, 0],
+            [50, 13],
+        ];
+        for (value, expected) in shown.iter().zip(expected) {
+            assert!(
+                value[0] == expected[0] && value[1].abs_diff(expected[1]) <= 1,
+                "{:?}",
+                shown
+            );
+        }
+    }
+}
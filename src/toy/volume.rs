@@ -0,0 +1,245 @@
+use super::builtin;
+
+/// Magic bytes starting Shadertoy's volume files
+const MAGIC: &[u8; 4] = b"BIN\0";
+
+/// Size of the header of a volume file
+const HEADER_SIZE: usize = 20;
+
+/// Edge of the built-in noise volumes
+pub const NOISE_SIZE: u32 = 32;
+
+/// A 3D texture read by a volume channel, with 8 bits per component
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Volume {
+    pub width: u32,
+    pub height: u32,
+    pub depth: u32,
+    /// Components per texel, 1 (grey) or 4 (RGBA)
+    pub channels: u8,
+    /// Texels in x, then y, then z order
+    pub data: Vec<u8>,
+}
+
+/// Error decoding a volume file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VolumeError {
+    /// The file does not start with a volume header
+    Header,
+    /// The header describes a layout other than 8 bit grey or RGBA texels
+    Format {
+        channels: u8,
+        layout: u8,
+        format: u16,
+    },
+    /// The file holds a different amount of data than its header describes
+    Size { expected: usize, actual: usize },
+    /// The header describes more texels than can be addressed
+    Dimensions { width: u32, height: u32, depth: u32 },
+}
+
+impl std::fmt::Display for VolumeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            VolumeError::Header => write!(f, "not a volume file, missing the BIN header"),
+            VolumeError::Format {
+                channels,
+                layout,
+                format,
+            } => write!(
+                f,
+                "unsupported volume with {} channels, layout {} and format {}",
+                channels, layout, format
+            ),
+            VolumeError::Size { expected, actual } => write!(
+                f,
+                "volume should hold {} bytes of texels but holds {}",
+                expected, actual
+            ),
+            VolumeError::Dimensions {
+                width,
+                height,
+                depth,
+            } => write!(f, "volume of {}x{}x{} is too large", width, height, depth),
+        }
+    }
+}
+
+impl std::error::Error for VolumeError {}
+
+impl Volume {
+    /// Decode Shadertoy's volume format: a 20 byte header with the `BIN\0`
+    /// magic, the width, height and depth as little endian `u32`s, the
+    /// number of channels and the layout as `u8`s and the format as a `u16`,
+    /// followed by the texels
+    pub fn parse(bytes: &[u8]) -> Result<Volume, VolumeError> {
+        if bytes.len() < HEADER_SIZE || &bytes[..4] != MAGIC {
+            return Err(VolumeError::Header);
+        }
+        let word = |offset: usize| {
+            u32::from_le_bytes(bytes[offset..offset + 4].try_into().expect("4 bytes"))
+        };
+        let (width, height, depth) = (word(4), word(8), word(12));
+        let channels = bytes[16];
+        let layout = bytes[17];
+        let format = u16::from_le_bytes([bytes[18], bytes[19]]);
+        if !matches!(channels, 1 | 4) || layout != 0 || format != 0 {
+            return Err(VolumeError::Format {
+                channels,
+                layout,
+                format,
+            });
+        }
+
+        let data = &bytes[HEADER_SIZE..];
+        let expected = [height, depth, channels as u32]
+            .iter()
+            .try_fold(width as usize, |size, &n| size.checked_mul(n as usize))
+            .ok_or(VolumeError::Dimensions {
+                width,
+                height,
+                depth,
+            })?;
+        if data.len() != expected {
+            return Err(VolumeError::Size {
+                expected,
+                actual: data.len(),
+            });
+        }
+        Ok(Volume {
+            width,
+            height,
+            depth,
+            channels,
+            data: data.to_vec(),
+        })
+    }
+
+    /// Encode the volume in Shadertoy's volume format
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        for size in [self.width, self.height, self.depth] {
+            bytes.extend(size.to_le_bytes());
+        }
+        bytes.extend([self.channels, 0, 0, 0]);
+        bytes.extend(&self.data);
+        bytes
+    }
+
+    /// Tileable noise like Shadertoy's Grey Noise3D (1 channel) and RGBA
+    /// Noise3D (4 channels) volumes, with every texel independently random
+    pub fn noise(size: u32, channels: u8) -> Volume {
+        let len = (size * size * size) as usize * channels as usize;
+        let data = builtin::random_bytes(len, channels as u32);
+        Volume {
+            width: size,
+            height: size,
+            depth: size,
+            channels,
+            data,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::render::test::{channel, compiled, headless};
+    use crate::toy::{BuiltinName, Channel, ChannelConfig, Toy};
+    use std::fs;
+    use tempdir::TempDir;
+
+    #[test]
+    fn volume_format() {
+        let volume = Volume::noise(4, 4);
+        assert_eq!(4 * 4 * 4 * 4, volume.data.len());
+        assert_eq!(volume, Volume::noise(4, 4));
+        // texels are not all the same
+        assert!(volume.data.iter().any(|&b| b != volume.data[0]));
+
+        let bytes = volume.to_bytes();
+        assert_eq!(HEADER_SIZE + volume.data.len(), bytes.len());
+        assert_eq!(Ok(volume), Volume::parse(&bytes));
+
+        assert_eq!(Err(VolumeError::Header), Volume::parse(b"PNG"));
+        assert_eq!(
+            Err(VolumeError::Size {
+                expected: 256,
+                actual: 255
+            }),
+            Volume::parse(&bytes[..bytes.len() - 1])
+        );
+        // corrupt dimensions are reported rather than overflowing
+        let mut huge = bytes.clone();
+        huge[4..16].copy_from_slice(&[0xff; 12]);
+        assert_eq!(
+            Err(VolumeError::Dimensions {
+                width: u32::MAX,
+                height: u32::MAX,
+                depth: u32::MAX
+            }),
+            Volume::parse(&huge)
+        );
+        let mut grey_u16 = Volume::noise(2, 1).to_bytes();
+        grey_u16[18] = 1;
+        assert_eq!(
+            Err(VolumeError::Format {
+                channels: 1,
+                layout: 0,
+                format: 1
+            }),
+            Volume::parse(&grey_u16)
+        );
+    }
+
+    #[test]
+    fn render_volume_channel() {
+        let Some(headless) = headless() else {
+            return;
+        };
+        let tmp_dir = TempDir::new("render_volume_channel").unwrap().into_path();
+        let volume = Volume {
+            width: 2,
+            height: 2,
+            depth: 2,
+            channels: 1,
+            data: vec![10, 20, 30, 40, 50, 60, 70, 80],
+        };
+        fs::write(tmp_dir.join("grey.bin"), volume.to_bytes()).unwrap();
+
+        // each pixel reads a texel of the first row, on both slices
+        let mut toy = Toy {
+            root: tmp_dir,
+            main_image: "void mainImage(out vec4 c, in vec2 p) {
+                ivec3 texel = ivec3(int(p.x) % 2, 0, int(p.x) / 2);
+                c = vec4(texelFetch(iChannel0, texel, 0).x, iChannelResolution[0].z / 255.0,
+                         texture(iChannel1, vec3(0.5)).w, 1.0);
+            }"
+            .into(),
+            ..Default::default()
+        };
+        toy.config.channels = vec![
+            Channel {
+                path: Some("grey.bin".into()),
+                ..channel(ChannelConfig::Volume)
+            },
+            Channel {
+                name: Some(BuiltinName::RgbaNoise3D),
+                ..channel(ChannelConfig::Volume)
+            },
+        ];
+        assert!(toy
+            .fragment_shader()
+            .unwrap()
+            .source
+            .contains("uniform sampler3D iChannel1;"));
+
+        let (mut renderer, target) = compiled(&headless, &toy, (4, 1));
+        let image = renderer.capture(&target);
+        let red: Vec<_> = image.pixels().map(|p| p[0]).collect();
+        assert_eq!(vec![10, 20, 50, 60], red);
+        assert_eq!(2, image.get_pixel(0, 0)[1]);
+        // the built-in noise is not blank
+        assert!(image.get_pixel(0, 0)[2] > 0);
+    }
+}
@@ -1,83 +1,210 @@
 use futures::{
-    channel::mpsc::{channel, Receiver, Sender},
+    channel::mpsc::{channel, unbounded, Receiver, Sender, UnboundedReceiver},
     executor::ThreadPool,
-    SinkExt, StreamExt,
+    stream, SinkExt, StreamExt,
 };
-use log;
-use std::fs;
+use std::collections::HashSet;
 use std::path::Path;
 use std::path::PathBuf;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
 
-use notify::{
-    event::{DataChange, ModifyKind},
-    INotifyWatcher,
-};
-use notify::{Config, Error, Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use clap::Args;
+use notify::{Config, Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
 
+use crate::render::{parse_positive, parse_size};
 use crate::runtime::{IRuntime, Runtime};
+use crate::terminal::{self, TerminalMode};
 use crate::toy::Toy;
 
-fn async_watcher<P: AsRef<Path>>(
-    path: P,
-) -> notify::Result<(RecommendedWatcher, Receiver<notify::Result<Event>>)> {
-    let (mut tx, rx) = channel(1);
+/// Time to wait for related file events (e.g. truncate then write) to settle
+const DEBOUNCE: Duration = Duration::from_millis(50);
+
+/// Options of the watch window
+#[derive(Args, Debug, Clone)]
+pub struct WatchOptions {
+    /// Resolution of stills saved with Ctrl+Shift+S, four times the window
+    /// size if not given
+    #[arg(long, value_parser = parse_size, conflicts_with = "terminal")]
+    pub still_size: Option<(u32, u32)>,
+
+    /// Record frames from the start to an animation or video, in the format
+    /// of its extension: gif, png (APNG), webp or y4m. Recordings started
+    /// with Ctrl+Shift+R use the same format, GIF by default
+    #[arg(long, conflicts_with = "terminal")]
+    pub record: Option<PathBuf>,
 
-    let mut watcher = RecommendedWatcher::new(
+    /// Frames per second of recordings, which advance `iTime` by a fixed
+    /// step rather than following the clock
+    #[arg(long, value_parser = parse_positive, default_value_t = 30.0)]
+    pub record_fps: f32,
+
+    /// Stream frames to the terminal instead of opening a window, with the
+    /// graphics protocol detected from the terminal unless given
+    #[arg(long, value_enum, num_args = 0..=1, default_missing_value = "auto")]
+    pub terminal: Option<TerminalMode>,
+}
+
+type FileEvents = UnboundedReceiver<notify::Result<Event>>;
+
+fn async_watcher() -> notify::Result<(RecommendedWatcher, FileEvents)> {
+    // unbounded so the notify thread never blocks on us, since changing
+    // watches from the receiving task waits for that thread
+    let (tx, rx) = unbounded();
+
+    let watcher = RecommendedWatcher::new(
         move |res| {
-            futures::executor::block_on(async {
-                tx.send(res).await.unwrap();
-            })
+            // the receiver is gone once the watch task has stopped
+            let _ = tx.unbounded_send(res);
         },
         Config::default(),
     )?;
-    watcher
-        .watch(path.as_ref(), RecursiveMode::Recursive)
-        .expect("can always watch");
 
     Ok((watcher, rx))
 }
 
-async fn run_watch(mut file_event_chan: Receiver<Result<Event, Error>>, mut toy_chan: Sender<Toy>) {
-    while let Some(res) = file_event_chan.next().await {
-        // dbg!(&res);
-        match res {
-            Ok(event) => match event {
-                Event {
-                    kind: EventKind::Modify(ModifyKind::Data(DataChange::Any)),
-                    ref paths,
-                    attrs: _,
-                } => {
-                    let p = &paths[0];
-                    match p.file_name().unwrap().to_owned().to_str().unwrap() {
-                        "image.glsl" => match fs::read_to_string(p) {
-                            Ok(toy) => toy_chan
-                                .send(Toy {
-                                    main_image: toy,
-                                    ..Default::default()
-                                })
-                                .await
-                                .unwrap(),
-                            Err(err) => println!("Error reading {:?}: {:}", p, err),
-                        },
-                        _ => (),
+/// Resolve the directory of a path so it compares equal to watcher event paths
+fn normalize(path: &Path) -> PathBuf {
+    match (path.parent(), path.file_name()) {
+        (Some(parent), Some(name)) => parent
+            .canonicalize()
+            .map(|p| p.join(name))
+            .unwrap_or_else(|_| path.to_path_buf()),
+        _ => path.to_path_buf(),
+    }
+}
+
+/// Files a toy is built from, and the directories that must be watched for them
+struct ToySources {
+    files: HashSet<PathBuf>,
+    dirs: HashSet<PathBuf>,
+}
+
+impl ToySources {
+    fn new(toy: &Toy) -> Self {
+        let files: HashSet<PathBuf> = toy.sources().iter().map(|p| normalize(p)).collect();
+        let dirs = files
+            .iter()
+            .filter_map(|p| p.parent())
+            .map(Path::to_path_buf)
+            .collect();
+        ToySources { files, dirs }
+    }
+
+    /// Watch directories of these sources, unwatching those of the `previous` ones
+    fn update_watches(&self, watcher: &mut RecommendedWatcher, previous: Option<&ToySources>) {
+        let empty = HashSet::new();
+        let previous = previous.map(|p| &p.dirs).unwrap_or(&empty);
+        for dir in previous.difference(&self.dirs) {
+            if let Err(e) = watcher.unwatch(dir) {
+                log::debug!("Error unwatching {:?}: {}", dir, e);
+            }
+        }
+        for dir in self.dirs.difference(previous) {
+            if let Err(e) = watcher.watch(dir, RecursiveMode::NonRecursive) {
+                log::warn!("Error watching {:?}: {}", dir, e);
+            }
+        }
+    }
+
+    fn is_affected_by(&self, event: &Event) -> bool {
+        match event.kind {
+            EventKind::Access(_) => false,
+            _ => event
+                .paths
+                .iter()
+                .any(|p| self.files.contains(&normalize(p))),
+        }
+    }
+}
+
+/// Timer on a thread of its own, firing once `duration` has passed since it
+/// was last reset, so a burst of events only fires it once
+struct Debounce {
+    reset: mpsc::Sender<()>,
+}
+
+impl Debounce {
+    fn new(duration: Duration) -> (Self, UnboundedReceiver<()>) {
+        let (reset, resets) = mpsc::channel();
+        let (tx, rx) = unbounded();
+        thread::spawn(move || {
+            // idle until the first reset of a burst
+            while resets.recv().is_ok() {
+                loop {
+                    match resets.recv_timeout(duration) {
+                        Ok(()) => continue,
+                        Err(RecvTimeoutError::Timeout) => break,
+                        Err(RecvTimeoutError::Disconnected) => return,
                     }
                 }
-                _ => (),
-            },
-            Err(e) => println!("watch error: {:?}", e),
+                if tx.unbounded_send(()).is_err() {
+                    return;
+                }
+            }
+        });
+        (Self { reset }, rx)
+    }
+
+    fn reset(&self) {
+        let _ = self.reset.send(());
+    }
+}
+
+/// File event, or the end of a burst of them
+enum WatchEvent {
+    File(notify::Result<Event>),
+    Settled,
+}
+
+async fn run_watch(
+    root: PathBuf,
+    mut watcher: RecommendedWatcher,
+    mut sources: ToySources,
+    file_event_chan: FileEvents,
+    mut toy_chan: Sender<Toy>,
+) {
+    // let bursts of events from a single save settle before reloading
+    let (debounce, settled) = Debounce::new(DEBOUNCE);
+    let mut events = stream::select(
+        file_event_chan.map(WatchEvent::File),
+        settled.map(|_| WatchEvent::Settled),
+    );
+    while let Some(event) = events.next().await {
+        match event {
+            WatchEvent::File(Ok(event)) if sources.is_affected_by(&event) => debounce.reset(),
+            WatchEvent::File(Ok(_)) => (),
+            WatchEvent::File(Err(e)) => log::error!("watch error: {:?}", e),
+            WatchEvent::Settled => {
+                let toy = Toy::from_path(&root);
+                let updated = ToySources::new(&toy);
+                updated.update_watches(&mut watcher, Some(&sources));
+                sources = updated;
+
+                if toy_chan.send(toy).await.is_err() {
+                    log::info!("Toy channel closed, stopping watch");
+                    return;
+                }
+            }
         }
     }
 }
 
-fn start_async_watch<P: AsRef<Path>>(path: P) -> (INotifyWatcher, Receiver<Toy>) {
-    let (_watcher, rx) = async_watcher(path).expect("Can watch");
+fn start_async_watch<P: AsRef<Path>>(path: P) -> Receiver<Toy> {
+    let toy = Toy::from_path(&path);
+    let sources = ToySources::new(&toy);
+    let (mut watcher, rx) = async_watcher().expect("Can watch");
+    sources.update_watches(&mut watcher, None);
+
     let (tx, toy_chan) = channel(1);
     let pool = ThreadPool::new().unwrap();
-    let _ = pool.spawn_ok(async { run_watch(rx, tx).await });
-    return (_watcher, toy_chan);
+    let root = path.as_ref().to_path_buf();
+    pool.spawn_ok(async { run_watch(root, watcher, sources, rx, tx).await });
+    toy_chan
 }
 
-pub fn run(path: PathBuf) {
+pub fn run(path: PathBuf, options: WatchOptions) {
     // Create initial files
     let toy = Toy::from_path(&path);
     if let Err(e) = toy.write(&path, false) {
@@ -85,25 +212,71 @@ pub fn run(path: PathBuf) {
     };
 
     // Start watch
-    let (_w, toy_chan) = start_async_watch(&path);
+    let toy_chan = start_async_watch(&path);
 
     // Start graphics
-    Runtime::start(toy, Some(toy_chan));
+    if let Some(mode) = options.terminal {
+        if let Err(e) = terminal::run(toy, toy_chan, mode) {
+            log::error!("Error drawing {:?} in the terminal: {}", path, e);
+            std::process::exit(1);
+        }
+        return;
+    }
+    Runtime::start(toy, Some(toy_chan), options);
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::toy::{BufferName, Channel, ChannelConfig};
     use futures::executor;
+    use std::fs;
     use tempdir::TempDir;
 
+    #[test]
+    fn terminal_conflicts() {
+        #[derive(clap::Parser)]
+        struct Cli {
+            #[command(flatten)]
+            watch: WatchOptions,
+        }
+        let parse = |args: &[&str]| {
+            clap::Parser::try_parse_from(["watch"].iter().chain(args)).map(|cli: Cli| cli.watch)
+        };
+        assert!(parse(&["--terminal"]).is_ok());
+        assert!(parse(&["--record", "out.gif", "--still-size", "64x32"]).is_ok());
+        assert!(parse(&["--terminal", "--record", "out.gif"]).is_err());
+        assert!(parse(&["--terminal=blocks", "--still-size", "64x32"]).is_err());
+    }
+
+    #[test]
+    fn debounce_fires_once_settled() {
+        let (debounce, mut settled) = Debounce::new(DEBOUNCE);
+        let mut last_reset = std::time::Instant::now();
+        for _ in 0..4 {
+            thread::sleep(DEBOUNCE / 4);
+            last_reset = std::time::Instant::now();
+            debounce.reset();
+        }
+        executor::block_on(settled.next()).unwrap();
+        assert!(last_reset.elapsed() >= DEBOUNCE);
+
+        // a single burst fires once
+        thread::sleep(DEBOUNCE * 2);
+        assert!(settled.try_next().is_err());
+
+        // the timer thread stops with the debounce
+        drop(debounce);
+        assert_eq!(None, executor::block_on(settled.next()));
+    }
+
     #[test]
     fn watch_sends_updates() {
         let mut toy = Toy::default();
         let tmp_dir = TempDir::new("example").unwrap().into_path();
         let _ = toy.write(&tmp_dir, false);
 
-        let (_w, mut toy_chan) = start_async_watch(&tmp_dir);
+        let mut toy_chan = start_async_watch(&tmp_dir);
 
         // no messages initially
         let msg = toy_chan.try_next();
@@ -111,12 +284,91 @@ mod test {
 
         // write toy and get new config
         toy.main_image = "test".into();
-        toy.write(&tmp_dir.clone(), true).unwrap();
+        toy.write(&tmp_dir, true).unwrap();
         let msg = executor::block_on(async { toy_chan.next().await });
 
         match msg {
             Some(cfg) => assert_eq!(cfg.main_image, toy.main_image),
-            None => assert!(false, "Channel should not be closed"),
+            None => panic!("Channel should not be closed"),
         }
     }
+
+    #[test]
+    fn watch_reloads_full_toy() {
+        let mut toy = Toy::default();
+        let tmp_dir = TempDir::new("watch_reloads_full_toy").unwrap().into_path();
+        let assets = TempDir::new("watch_reloads_full_toy_assets")
+            .unwrap()
+            .into_path();
+        toy.config.channels = vec![Channel {
+            name: None,
+            path: Some(assets.join("noise.png").to_string_lossy().into()),
+            buffer: None,
+            config: ChannelConfig::default(),
+        }];
+        toy.write(&tmp_dir, true).unwrap();
+
+        let mut toy_chan = start_async_watch(&tmp_dir);
+
+        // config is kept when a pass changes
+        fs::write(tmp_dir.join("bufa.glsl"), "buffer").unwrap();
+        let msg = executor::block_on(async { toy_chan.next().await }).unwrap();
+        assert_eq!(toy.config, msg.config);
+        assert_eq!("buffer", msg.buffers[&BufferName::A]);
+
+        // common code is watched before it exists
+        fs::write(tmp_dir.join("common.glsl"), "common").unwrap();
+        let msg = executor::block_on(async { toy_chan.next().await }).unwrap();
+        assert_eq!(Some("common"), msg.common.as_deref());
+
+        // channel files outside of the toy directory are watched
+        fs::write(assets.join("noise.png"), "data").unwrap();
+        let msg = executor::block_on(async { toy_chan.next().await }).unwrap();
+        assert_eq!(toy.config, msg.config);
+
+        // unrelated files are ignored
+        fs::write(tmp_dir.join("notes.txt"), "notes").unwrap();
+        thread::sleep(DEBOUNCE * 4);
+        assert!(toy_chan.try_next().is_err());
+
+        // config changes are picked up
+        fs::write(tmp_dir.join("toy.toml"), "").unwrap();
+        let msg = executor::block_on(async { toy_chan.next().await }).unwrap();
+        assert!(msg.config.channels.is_empty());
+    }
+
+    #[test]
+    fn watch_reloads_included_files() {
+        let tmp_dir = TempDir::new("watch_reloads_included_files")
+            .unwrap()
+            .into_path();
+        let lib = TempDir::new("watch_reloads_included_files_lib")
+            .unwrap()
+            .into_path();
+        let mut toy = Toy {
+            main_image: "#include \"noise.glsl\"".into(),
+            ..Default::default()
+        };
+        toy.config.include_paths = vec![lib.to_string_lossy().into()];
+        toy.write(&tmp_dir, true).unwrap();
+        fs::write(lib.join("noise.glsl"), "#include \"hash.glsl\"").unwrap();
+
+        let mut toy_chan = start_async_watch(&tmp_dir);
+
+        // included library files are watched
+        fs::write(lib.join("noise.glsl"), "float noise;").unwrap();
+        let msg = executor::block_on(async { toy_chan.next().await }).unwrap();
+        assert_eq!(toy.main_image, msg.main_image);
+
+        // as are missing files once created
+        fs::write(lib.join("noise.glsl"), "#include \"hash.glsl\"").unwrap();
+        executor::block_on(async { toy_chan.next().await }).unwrap();
+        fs::write(tmp_dir.join("hash.glsl"), "float hash;").unwrap();
+        let msg = executor::block_on(async { toy_chan.next().await }).unwrap();
+        assert!(msg
+            .fragment_shader()
+            .unwrap()
+            .source
+            .contains("float hash;"));
+    }
 }
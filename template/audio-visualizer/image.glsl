@@ -0,0 +1,19 @@
+void mainImage( out vec4 fragColor, in vec2 fragCoord )
+{
+    vec2 uv = fragCoord/iResolution.xy;
+
+    // Row 0 of the audio texture holds the spectrum and row 1 the waveform,
+    // read with low frequencies spread over more of the screen
+    float fft = texture(iChannel0, vec2(uv.x*uv.x, 0.25)).x;
+    float wave = texture(iChannel0, vec2(uv.x, 0.75)).x;
+
+    // Spectrum as bars, colored by frequency
+    float bars = step(uv.y, fft*0.8);
+    vec3 col = bars*(0.5 + 0.5*cos(6.0*uv.x + vec3(0,2,4)));
+
+    // Waveform as a line across the middle
+    float line = smoothstep(0.01, 0.0, abs(uv.y - 0.5 - 0.3*(wave - 0.5)));
+    col += line;
+
+    fragColor = vec4(col, 1.0);
+}
@@ -0,0 +1,84 @@
+// Sphere tracing of a signed distance field, with soft shadows
+
+const int STEPS = 128;
+const float FAR = 50.0;
+
+float sdSphere( vec3 p, float r )
+{
+    return length(p) - r;
+}
+
+// Distance to the closest surface of the scene
+float map( vec3 p )
+{
+    vec3 q = p - vec3(0.0, 1.0 + 0.25*sin(2.0*iTime), 0.0);
+    return min(sdSphere(q, 1.0), p.y);
+}
+
+vec3 calcNormal( vec3 p )
+{
+    vec2 e = vec2(0.001, 0.0);
+    return normalize(vec3(map(p+e.xyy) - map(p-e.xyy),
+                          map(p+e.yxy) - map(p-e.yxy),
+                          map(p+e.yyx) - map(p-e.yyx)));
+}
+
+float march( vec3 ro, vec3 rd )
+{
+    float t = 0.0;
+    for( int i=0; i<STEPS && t<FAR; i++ )
+    {
+        float d = map(ro + rd*t);
+        if( d < 0.001*t ) return t;
+        t += d;
+    }
+    return -1.0;
+}
+
+float softShadow( vec3 ro, vec3 rd )
+{
+    float res = 1.0;
+    float t = 0.02;
+    for( int i=0; i<64 && t<10.0; i++ )
+    {
+        float h = map(ro + rd*t);
+        res = min(res, 8.0*h/t);
+        if( res < 0.001 ) break;
+        t += clamp(h, 0.02, 0.5);
+    }
+    return clamp(res, 0.0, 1.0);
+}
+
+void mainImage( out vec4 fragColor, in vec2 fragCoord )
+{
+    vec2 p = (2.0*fragCoord - iResolution.xy)/iResolution.y;
+
+    // Camera orbiting the origin, turned by dragging the mouse
+    float angle = 0.2*iTime + 6.0*iMouse.x/iResolution.x;
+    vec3 ro = vec3(4.0*cos(angle), 2.0, 4.0*sin(angle));
+    vec3 ww = normalize(vec3(0.0, 1.0, 0.0) - ro);
+    vec3 uu = normalize(cross(ww, vec3(0.0, 1.0, 0.0)));
+    vec3 vv = cross(uu, ww);
+    vec3 rd = normalize(p.x*uu + p.y*vv + 1.5*ww);
+
+    vec3 sky = vec3(0.6, 0.75, 0.9) - 0.4*rd.y;
+    vec3 col = sky;
+    float t = march(ro, rd);
+    if( t > 0.0 )
+    {
+        vec3 pos = ro + rd*t;
+        vec3 nor = calcNormal(pos);
+        vec3 light = normalize(vec3(0.6, 0.8, -0.4));
+        float dif = clamp(dot(nor, light), 0.0, 1.0)*softShadow(pos, light);
+        float amb = 0.5 + 0.5*nor.y;
+
+        vec3 mate = pos.y < 0.01
+            ? vec3(0.3 + 0.2*mod(floor(pos.x) + floor(pos.z), 2.0))
+            : vec3(0.8, 0.3, 0.2);
+        col = mate*(dif*vec3(1.0, 0.95, 0.8) + amb*vec3(0.15, 0.2, 0.3));
+        col = mix(col, sky, 1.0 - exp(-0.002*t*t));
+    }
+
+    // Gamma correction
+    fragColor = vec4(pow(col, vec3(0.4545)), 1.0);
+}
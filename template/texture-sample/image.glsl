@@ -0,0 +1,24 @@
+// Layers of a noise texture, summed at increasing frequencies
+float noise( vec2 uv )
+{
+    return texture(iChannel0, uv).x;
+}
+
+void mainImage( out vec4 fragColor, in vec2 fragCoord )
+{
+    // iChannelResolution holds the size of the texture in pixels, so a
+    // texel of the first layer covers eight pixels
+    vec2 uv = fragCoord/(8.0*iChannelResolution[0].xy) + vec2(0.01*iTime, 0.0);
+
+    float f = 0.0;
+    float amp = 0.5;
+    for( int i=0; i<5; i++ )
+    {
+        f += amp*noise(uv);
+        uv = 2.0*uv + 0.02*iTime;
+        amp *= 0.5;
+    }
+
+    vec3 col = mix(vec3(0.1, 0.2, 0.4), vec3(1.0, 0.9, 0.7), smoothstep(0.3, 0.8, f));
+    fragColor = vec4(col, 1.0);
+}